@@ -0,0 +1,917 @@
+// Reference: https://gbdev.io/pandocs/Audio.html
+// Reference: https://gbdev.io/pandocs/Audio_Registers.html
+// Reference: https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware
+
+mod noise;
+mod square;
+mod wave;
+
+use std::{collections::VecDeque, fmt};
+
+use crate::cpu::CPU_FREQUENCY;
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+
+// sound registers, NRxy is register y of channel x
+pub const NR10_REGISTER: u16 = 0xFF10; // channel 1 sweep
+pub const NR11_REGISTER: u16 = 0xFF11; // channel 1 duty and length
+pub const NR12_REGISTER: u16 = 0xFF12; // channel 1 envelope
+pub const NR13_REGISTER: u16 = 0xFF13; // channel 1 frequency, low 8 bits
+pub const NR14_REGISTER: u16 = 0xFF14; // channel 1 trigger, length enable, frequency
+pub const NR21_REGISTER: u16 = 0xFF16;
+pub const NR22_REGISTER: u16 = 0xFF17;
+pub const NR23_REGISTER: u16 = 0xFF18;
+pub const NR24_REGISTER: u16 = 0xFF19;
+pub const NR30_REGISTER: u16 = 0xFF1A; // channel 3 DAC
+pub const NR31_REGISTER: u16 = 0xFF1B;
+pub const NR32_REGISTER: u16 = 0xFF1C; // channel 3 volume
+pub const NR33_REGISTER: u16 = 0xFF1D;
+pub const NR34_REGISTER: u16 = 0xFF1E;
+pub const NR41_REGISTER: u16 = 0xFF20;
+pub const NR42_REGISTER: u16 = 0xFF21;
+pub const NR43_REGISTER: u16 = 0xFF22; // channel 4 LFSR clock and width
+pub const NR44_REGISTER: u16 = 0xFF23;
+pub const NR50_REGISTER: u16 = 0xFF24; // master volume
+pub const NR51_REGISTER: u16 = 0xFF25; // panning
+pub const NR52_REGISTER: u16 = 0xFF26; // power and channel status
+pub const WAVE_RAM_START: u16 = 0xFF30;
+pub const WAVE_RAM_END: u16 = 0xFF3F;
+
+const NR52_ENABLE: u8 = 0x80;
+
+// bits that always read as 1, from NR10 to the unused registers before wave
+// RAM, write only registers read as all 1s
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// the output high pass filter's charge factor per cycle, it takes the DC
+// offset of the DACs out of the signal
+const HIGH_PASS_CHARGE: f64 = 0.999958;
+
+// the sample buffer holds this much audio, older samples are dropped when
+// nobody reads them
+const SAMPLE_BUFFER_SECONDS: usize = 1;
+
+// at most one sample per cycle
+pub const MAX_SAMPLE_RATE: u32 = CPU_FREQUENCY as u32;
+
+pub const CHANNELS: usize = 4;
+
+// the names of NR10-NR52 for the register log, None for the unused ones
+const REGISTER_NAMES: [Option<&str>; 0x17] = [
+    Some("NR10"),
+    Some("NR11"),
+    Some("NR12"),
+    Some("NR13"),
+    Some("NR14"),
+    None,
+    Some("NR21"),
+    Some("NR22"),
+    Some("NR23"),
+    Some("NR24"),
+    Some("NR30"),
+    Some("NR31"),
+    Some("NR32"),
+    Some("NR33"),
+    Some("NR34"),
+    None,
+    Some("NR41"),
+    Some("NR42"),
+    Some("NR43"),
+    Some("NR44"),
+    Some("NR50"),
+    Some("NR51"),
+    Some("NR52"),
+];
+
+// a write to a sound register or wave RAM, cycle counts from power on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub cycle: u64,
+    pub addr: u16,
+    pub value: u8,
+}
+
+// one line per write, like "    123456 NR12 F0"
+impl fmt::Display for RegisterWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>10} ", self.cycle)?;
+        match self.addr {
+            WAVE_RAM_START..=WAVE_RAM_END => write!(f, "WAV{:X}", self.addr - WAVE_RAM_START)?,
+            _ => match REGISTER_NAMES.get((self.addr - NR10_REGISTER) as usize) {
+                Some(Some(name)) => write!(f, "{}", name)?,
+                _ => write!(f, "{:04X}", self.addr)?,
+            },
+        }
+        write!(f, " {:02X}", self.value)
+    }
+}
+
+// the length counter turns a channel off once it has counted down
+#[derive(Default)]
+struct Length {
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    fn load(&mut self, max: u16, value: u8) {
+        self.counter = max - value as u16;
+    }
+
+    // on DMG turning the APU off keeps the counter but not the enable bit
+    fn power_off(&self) -> Length {
+        Length {
+            counter: self.counter,
+            enabled: false,
+        }
+    }
+
+    // true when the channel should be turned off
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    // handles NRx4's length enable and trigger bits, true when the channel
+    // should be turned off
+    fn write_control(&mut self, value: u8, max: u16, next_step_clocks_length: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = value & 0x40 != 0;
+        let trigger = value & 0x80 != 0;
+        let mut expired = false;
+        // enabling the counter in the half of the frame sequencer period
+        // that doesn't clock it still clocks it once
+        if !was_enabled && self.enabled && !next_step_clocks_length && self.counter > 0 {
+            self.counter -= 1;
+            expired = self.counter == 0 && !trigger;
+        }
+        if trigger && self.counter == 0 {
+            self.counter = max;
+            if self.enabled && !next_step_clocks_length {
+                self.counter -= 1;
+            }
+        }
+        expired
+    }
+}
+
+// changes the volume every 1-7 steps of the 64 Hz envelope clock
+#[derive(Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period = value & 0x07;
+    }
+
+    // the DAC is off when the top 5 bits of NRx2 are clear
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+pub struct Apu {
+    enabled: bool,
+    // the registers as written, for reading back
+    registers: [u8; 0x17],
+    channel1: Square,
+    channel2: Square,
+    channel3: Wave,
+    channel4: Noise,
+    // the frame sequencer step that runs next, 0-7
+    frame_step: u8,
+    // output samples per second, 0 for none
+    sample_rate: u32,
+    // cycles run towards the next sample, multiplied by the sample rate
+    sample_clock: u64,
+    // a ring buffer of interleaved left and right samples, -1.0 to 1.0
+    samples: VecDeque<f32>,
+    // the high pass filter's capacitors, left and right
+    capacitors: [f32; 2],
+    high_pass_charge: f32,
+    // left out of the mix, channel 1 first
+    muted: [bool; CHANNELS],
+    // each channel mixed on its own, when capture_channels is set
+    capture_channels: bool,
+    channel_samples: [VecDeque<f32>; CHANNELS],
+    channel_capacitors: [[f32; 2]; CHANNELS],
+    // cycles run since power on, to timestamp the register log
+    cycles: u64,
+    register_log: Option<Vec<RegisterWrite>>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu {
+            enabled: false,
+            registers: [0; 0x17],
+            channel1: Square::new(true),
+            channel2: Square::new(false),
+            channel3: Wave::default(),
+            channel4: Noise::default(),
+            frame_step: 0,
+            sample_rate: 0,
+            sample_clock: 0,
+            samples: VecDeque::new(),
+            capacitors: [0.0; 2],
+            high_pass_charge: 0.0,
+            muted: [false; CHANNELS],
+            capture_channels: false,
+            channel_samples: Default::default(),
+            channel_capacitors: [[0.0; 2]; CHANNELS],
+            cycles: 0,
+            register_log: None,
+        }
+    }
+}
+
+impl Apu {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // starts producing stereo samples at this rate, 0 stops them
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        assert!(rate <= MAX_SAMPLE_RATE, "sample rate above the CPU clock");
+        self.sample_rate = rate;
+        self.sample_clock = 0;
+        self.samples.clear();
+        for samples in &mut self.channel_samples {
+            samples.clear();
+        }
+        if rate > 0 {
+            self.high_pass_charge = HIGH_PASS_CHARGE.powf(CPU_FREQUENCY / rate as f64) as f32;
+        }
+    }
+
+    // the samples waiting in the buffer, counting left and right separately
+    pub fn samples_available(&self) -> usize {
+        self.samples.len()
+    }
+
+    // every sample in the buffer, left then right
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    // fills as much of the output as there are samples for, for audio
+    // callbacks that ask for a fixed amount, returns the samples written
+    pub fn read_samples(&mut self, output: &mut [f32]) -> usize {
+        // whole left and right pairs only
+        let count = self.samples.len().min(output.len()) & !1;
+        for (slot, sample) in output.iter_mut().zip(self.samples.drain(..count)) {
+            *slot = sample;
+        }
+        count
+    }
+
+    // channel is 0-3, a muted channel still plays in its own capture
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        self.muted[channel] = muted;
+    }
+
+    pub fn channel_muted(&self, channel: usize) -> bool {
+        self.muted[channel]
+    }
+
+    // also buffers each channel mixed on its own, at the same rate as the
+    // mixed output
+    pub fn set_channel_capture(&mut self, capture: bool) {
+        self.capture_channels = capture;
+        for samples in &mut self.channel_samples {
+            samples.clear();
+        }
+    }
+
+    // the channel's samples since the last call, left then right
+    pub fn take_channel_samples(&mut self, channel: usize) -> Vec<f32> {
+        self.channel_samples[channel].drain(..).collect()
+    }
+
+    // starts or stops recording every write to the sound registers
+    pub fn set_register_logging(&mut self, logging: bool) {
+        self.register_log = logging.then(Vec::new);
+    }
+
+    // the writes since the last call, oldest first
+    pub fn take_register_log(&mut self) -> Vec<RegisterWrite> {
+        self.register_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        self.cycles += cycles as u64;
+        if self.sample_rate == 0 {
+            self.tick_channels(cycles);
+            return;
+        }
+        // run up to each sample point in turn
+        let frequency = CPU_FREQUENCY as u64;
+        let rate = self.sample_rate as u64;
+        let mut remaining = cycles as u64;
+        while remaining > 0 {
+            let until_sample = (frequency - self.sample_clock).div_ceil(rate);
+            let step = remaining.min(until_sample);
+            self.tick_channels(step as i64);
+            remaining -= step;
+            self.sample_clock += step * rate;
+            if self.sample_clock >= frequency {
+                self.sample_clock -= frequency;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn tick_channels(&mut self, cycles: i64) {
+        if !self.enabled {
+            return;
+        }
+        self.channel1.tick(cycles);
+        self.channel2.tick(cycles);
+        self.channel3.tick(cycles);
+        self.channel4.tick(cycles);
+    }
+
+    // the 512 Hz clock from the divider that drives length (256 Hz),
+    // sweep (128 Hz) and the envelopes (64 Hz)
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.frame_step.is_multiple_of(2) {
+            self.channel1.clock_length();
+            self.channel2.clock_length();
+            self.channel3.clock_length();
+            self.channel4.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.channel1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.channel1.clock_envelope();
+            self.channel2.clock_envelope();
+            self.channel4.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    // each channel's DAC output, -1.0 to 1.0, or 0 when the DAC is off
+    fn dac_outputs(&self) -> [f32; CHANNELS] {
+        let dac = |enabled: bool, output: u8| {
+            if enabled {
+                output as f32 / 7.5 - 1.0
+            } else {
+                0.0
+            }
+        };
+        [
+            dac(self.channel1.dac_enabled(), self.channel1.output()),
+            dac(self.channel2.dac_enabled(), self.channel2.output()),
+            dac(self.channel3.dac_enabled(), self.channel3.output()),
+            dac(self.channel4.dac_enabled(), self.channel4.output()),
+        ]
+    }
+
+    // mixes the channel outputs through NR51 and NR50
+    fn mix(&self, outputs: [f32; CHANNELS]) -> [f32; 2] {
+        if !self.enabled {
+            return [0.0; 2];
+        }
+        let panning = self.registers[(NR51_REGISTER - NR10_REGISTER) as usize];
+        let volume = self.registers[(NR50_REGISTER - NR10_REGISTER) as usize];
+        let mut mixed = [0.0; 2];
+        // the left side is the high nibble of both registers
+        for (side, shift) in [(0, 4), (1, 0)] {
+            let sum: f32 = (0..CHANNELS)
+                .filter(|channel| panning >> (shift + channel) & 1 != 0)
+                .map(|channel| outputs[channel])
+                .sum();
+            let master = ((volume >> shift) & 0x07) as f32 + 1.0;
+            mixed[side] = sum / 4.0 * master / 8.0;
+        }
+        mixed
+    }
+
+    fn push_sample(&mut self) {
+        let capacity = self.sample_rate as usize * 2 * SAMPLE_BUFFER_SECONDS;
+        let outputs = self.dac_outputs();
+        let mut audible = outputs;
+        for (output, &muted) in audible.iter_mut().zip(&self.muted) {
+            if muted {
+                *output = 0.0;
+            }
+        }
+        let mixed = self.mix(audible);
+        push_filtered(
+            &mut self.samples,
+            capacity,
+            mixed,
+            &mut self.capacitors,
+            self.high_pass_charge,
+        );
+        if !self.capture_channels {
+            return;
+        }
+        for channel in 0..CHANNELS {
+            let mut solo = [0.0; CHANNELS];
+            solo[channel] = outputs[channel];
+            let mixed = self.mix(solo);
+            push_filtered(
+                &mut self.channel_samples[channel],
+                capacity,
+                mixed,
+                &mut self.channel_capacitors[channel],
+                self.high_pass_charge,
+            );
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            NR52_REGISTER => {
+                let status = [
+                    self.channel1.enabled(),
+                    self.channel2.enabled(),
+                    self.channel3.enabled(),
+                    self.channel4.enabled(),
+                ]
+                .iter()
+                .enumerate()
+                .fold(0, |status, (channel, &on)| status | (on as u8) << channel);
+                READ_MASKS[(addr - NR10_REGISTER) as usize]
+                    | if self.enabled { NR52_ENABLE } else { 0 }
+                    | status
+            }
+            NR10_REGISTER..=NR51_REGISTER => {
+                let index = (addr - NR10_REGISTER) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.channel3.read_ram((addr - WAVE_RAM_START) as usize)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if let Some(log) = &mut self.register_log {
+            log.push(RegisterWrite {
+                cycle: self.cycles,
+                addr,
+                value,
+            });
+        }
+        match addr {
+            NR52_REGISTER => {
+                let enabled = value & NR52_ENABLE != 0;
+                if self.enabled && !enabled {
+                    self.power_off();
+                } else if !self.enabled && enabled {
+                    // the frame sequencer starts over
+                    self.frame_step = 0;
+                }
+                self.enabled = enabled;
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => self
+                .channel3
+                .write_ram((addr - WAVE_RAM_START) as usize, value),
+            // the DMG still lets the length counters be loaded while the
+            // APU is off
+            NR11_REGISTER if !self.enabled => self.channel1.write_length(value),
+            NR21_REGISTER if !self.enabled => self.channel2.write_length(value),
+            NR31_REGISTER if !self.enabled => self.channel3.write_length(value),
+            NR41_REGISTER if !self.enabled => self.channel4.write_length(value),
+            NR10_REGISTER..=NR51_REGISTER if self.enabled => {
+                let index = (addr - NR10_REGISTER) as usize;
+                self.registers[index] = value;
+                // length is clocked on even steps
+                let next_step_clocks_length = self.frame_step.is_multiple_of(2);
+                let register = index % 5;
+                match index / 5 {
+                    0 => self
+                        .channel1
+                        .write(register, value, next_step_clocks_length),
+                    1 => self
+                        .channel2
+                        .write(register, value, next_step_clocks_length),
+                    2 => self
+                        .channel3
+                        .write(register, value, next_step_clocks_length),
+                    3 => self
+                        .channel4
+                        .write(register, value, next_step_clocks_length),
+                    // NR50 and NR51 are only read back
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // clears every register, wave RAM and the length counters survive
+    fn power_off(&mut self) {
+        self.registers = [0; 0x17];
+        self.channel1.reset();
+        self.channel2.reset();
+        self.channel3.reset();
+        self.channel4.reset();
+    }
+}
+
+// runs a stereo sample through the high pass filter into a ring buffer
+fn push_filtered(
+    samples: &mut VecDeque<f32>,
+    capacity: usize,
+    input: [f32; 2],
+    capacitors: &mut [f32; 2],
+    charge: f32,
+) {
+    if samples.len() >= capacity {
+        samples.drain(..2);
+    }
+    for (side, input) in input.into_iter().enumerate() {
+        let output = input - capacitors[side];
+        capacitors[side] = input - output * charge;
+        samples.push_back(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powered() -> Apu {
+        let mut apu = Apu::default();
+        apu.write(NR52_REGISTER, NR52_ENABLE);
+        apu
+    }
+
+    fn status(apu: &Apu) -> u8 {
+        apu.read(NR52_REGISTER) & 0x0F
+    }
+
+    #[test]
+    fn test_power() {
+        let mut apu = Apu::default();
+        assert_eq!(apu.read(NR52_REGISTER), 0x70);
+        // ignored while off, apart from the length counters
+        apu.write(NR50_REGISTER, 0x77);
+        assert_eq!(apu.read(NR50_REGISTER), 0x00);
+        apu.write(WAVE_RAM_START, 0x12);
+        assert_eq!(apu.read(WAVE_RAM_START), 0x12);
+
+        apu.write(NR52_REGISTER, 0xFF);
+        assert_eq!(apu.read(NR52_REGISTER), 0xF0);
+        apu.write(NR50_REGISTER, 0x77);
+        apu.write(NR11_REGISTER, 0xC0);
+        assert_eq!(apu.read(NR50_REGISTER), 0x77);
+        assert_eq!(apu.read(NR11_REGISTER), 0xFF);
+        assert_eq!(apu.read(NR13_REGISTER), 0xFF);
+        assert_eq!(apu.read(0xFF27), 0xFF);
+
+        // powering off clears the registers but not wave RAM
+        apu.write(NR52_REGISTER, 0x00);
+        apu.write(NR52_REGISTER, NR52_ENABLE);
+        assert_eq!(apu.read(NR50_REGISTER), 0x00);
+        assert_eq!(apu.read(NR11_REGISTER), 0x3F);
+        assert_eq!(apu.read(WAVE_RAM_START), 0x12);
+    }
+
+    #[test]
+    fn test_power_keeps_length() {
+        let mut apu = powered();
+        apu.write(NR21_REGISTER, 62);
+        apu.write(NR52_REGISTER, 0x00);
+        apu.write(NR52_REGISTER, NR52_ENABLE);
+
+        // the trigger doesn't reload the counter that survived
+        apu.write(NR22_REGISTER, 0xF0);
+        apu.write(NR24_REGISTER, 0xC0);
+        assert_eq!(status(&apu), 0x02);
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(status(&apu), 0x00);
+    }
+
+    #[test]
+    fn test_trigger_and_length() {
+        let mut apu = powered();
+        // the DAC is off, triggering does nothing
+        apu.write(NR24_REGISTER, 0x80);
+        assert_eq!(status(&apu), 0x00);
+
+        apu.write(NR22_REGISTER, 0xF0);
+        apu.write(NR21_REGISTER, 62);
+        apu.write(NR24_REGISTER, 0xC0);
+        assert_eq!(status(&apu), 0x02);
+        apu.clock_frame_sequencer();
+        assert_eq!(status(&apu), 0x02);
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(status(&apu), 0x00);
+
+        // turning the DAC off stops the channel
+        apu.write(NR24_REGISTER, 0x80);
+        assert_eq!(status(&apu), 0x02);
+        apu.write(NR22_REGISTER, 0x00);
+        assert_eq!(status(&apu), 0x00);
+    }
+
+    #[test]
+    fn test_length_quirks() {
+        // enabling length when the next step doesn't clock it clocks it once
+        let mut apu = powered();
+        apu.write(NR42_REGISTER, 0xF0);
+        apu.write(NR41_REGISTER, 63);
+        apu.write(NR44_REGISTER, 0x80);
+        apu.clock_frame_sequencer();
+        apu.write(NR44_REGISTER, 0x40);
+        assert_eq!(status(&apu), 0x00);
+
+        // triggering with an empty counter loads 64, minus that extra clock
+        let mut apu = powered();
+        apu.clock_frame_sequencer();
+        apu.write(NR42_REGISTER, 0xF0);
+        apu.write(NR44_REGISTER, 0xC0);
+        for _ in 0..62 {
+            apu.clock_frame_sequencer();
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(status(&apu), 0x08);
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(status(&apu), 0x00);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut apu = powered();
+        apu.write(NR12_REGISTER, 0xF0);
+        // an overflow on trigger turns the channel off straight away
+        apu.write(NR10_REGISTER, 0x01);
+        apu.write(NR13_REGISTER, 0xFF);
+        apu.write(NR14_REGISTER, 0x87);
+        assert_eq!(status(&apu), 0x00);
+
+        // the sweep on step 2 takes 0x500 to 0x780, the overflow check
+        // that follows (0xB40) turns the channel off
+        apu.write(NR10_REGISTER, 0x11);
+        apu.write(NR13_REGISTER, 0x00);
+        apu.write(NR14_REGISTER, 0x85);
+        assert_eq!(status(&apu), 0x01);
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(status(&apu), 0x01);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.channel1.frequency, 0x780);
+        assert_eq!(status(&apu), 0x00);
+
+        // leaving negate mode after a negated calculation
+        apu.write(NR10_REGISTER, 0x19);
+        apu.write(NR14_REGISTER, 0x85);
+        assert_eq!(status(&apu), 0x01);
+        apu.write(NR10_REGISTER, 0x11);
+        assert_eq!(status(&apu), 0x00);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut apu = powered();
+        apu.write(NR22_REGISTER, 0x21);
+        apu.write(NR24_REGISTER, 0x80);
+        assert_eq!(apu.channel2.envelope.volume, 2);
+        // the envelope is clocked on step 7
+        for _ in 0..8 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.channel2.envelope.volume, 1);
+        for _ in 0..16 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.channel2.envelope.volume, 0);
+        // the channel stays on at volume 0
+        assert_eq!(status(&apu), 0x02);
+    }
+
+    #[test]
+    fn test_square_duty() {
+        let mut apu = powered();
+        apu.write(NR22_REGISTER, 0xF0);
+        // 50% duty, period (2048 - 2047) * 4 = 4 cycles per step
+        apu.write(NR21_REGISTER, 0x80);
+        apu.write(NR23_REGISTER, 0xFF);
+        apu.write(NR24_REGISTER, 0x87);
+        let mut pattern = Vec::new();
+        for _ in 0..8 {
+            apu.tick(4);
+            pattern.push(apu.channel2.output());
+        }
+        assert_eq!(pattern, [0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn test_wave() {
+        let mut apu = powered();
+        for i in 0..16 {
+            apu.write(WAVE_RAM_START + i, 0x0F | (i as u8) << 4);
+        }
+        apu.write(NR30_REGISTER, 0x80);
+        // full volume, period (2048 - 2047) * 2 = 2 cycles per sample
+        apu.write(NR32_REGISTER, 0x20);
+        apu.write(NR33_REGISTER, 0xFF);
+        apu.write(NR34_REGISTER, 0x87);
+        assert_eq!(status(&apu), 0x04);
+        apu.tick(2);
+        assert_eq!(apu.channel3.output(), 0x0F);
+        apu.tick(2);
+        assert_eq!(apu.channel3.output(), 0x01);
+        // half volume
+        apu.write(NR32_REGISTER, 0x40);
+        assert_eq!(apu.channel3.output(), 0x00);
+        apu.tick(2);
+        assert_eq!(apu.channel3.output(), 0x07);
+        // the CPU sees the byte being played
+        assert_eq!(apu.read(WAVE_RAM_START + 5), 0x1F);
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut apu = powered();
+        apu.write(NR42_REGISTER, 0xF0);
+        // the fastest clock, 8 cycles per shift
+        apu.write(NR43_REGISTER, 0x00);
+        apu.write(NR44_REGISTER, 0x80);
+        // all ones shift in zeros from the XOR until they reach bit 0
+        let mut outputs = Vec::new();
+        for _ in 0..16 {
+            apu.tick(8);
+            outputs.push(apu.channel4.output());
+        }
+        assert_eq!(outputs[..14], [0; 14]);
+        assert_eq!(outputs[14], 15);
+
+        // the 7 bit mode repeats every 127 shifts
+        apu.write(NR43_REGISTER, 0x08);
+        apu.write(NR44_REGISTER, 0x80);
+        apu.tick(8 * 7);
+        let lfsr = apu.channel4.lfsr & 0x7F;
+        apu.tick(8 * 127);
+        assert_eq!(apu.channel4.lfsr & 0x7F, lfsr);
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut apu = powered();
+        apu.set_sample_rate(48000);
+        apu.tick(CPU_FREQUENCY as i64 / 10);
+        let samples = apu.take_samples();
+        assert!((samples.len() as i64 - 9600).abs() <= 2);
+        assert!(apu.take_samples().is_empty());
+
+        // channel 2 on the left only
+        apu.write(NR50_REGISTER, 0x77);
+        apu.write(NR51_REGISTER, 0x20);
+        apu.write(NR22_REGISTER, 0xF0);
+        apu.write(NR24_REGISTER, 0x80);
+        apu.tick(CPU_FREQUENCY as i64 / 100);
+        let samples = apu.take_samples();
+        assert!(samples.chunks(2).any(|sample| sample[0].abs() > 0.1));
+        assert!(samples.chunks(2).all(|sample| sample[1] == 0.0));
+
+        // reading part of the buffer
+        apu.tick(CPU_FREQUENCY as i64 / 1000);
+        let mut output = [1.0; 7];
+        assert_eq!(apu.read_samples(&mut output), 6);
+        assert_eq!(output[6], 1.0);
+        assert_eq!(apu.samples_available(), 96 - 6);
+
+        // the buffer keeps the last second
+        apu.tick(CPU_FREQUENCY as i64 * 2);
+        assert_eq!(apu.samples_available(), 96000);
+
+        // no samples without a rate
+        apu.set_sample_rate(0);
+        apu.tick(1000);
+        assert!(apu.take_samples().is_empty());
+
+        // one sample per cycle at the highest rate
+        apu.set_sample_rate(MAX_SAMPLE_RATE);
+        apu.tick(10);
+        assert_eq!(apu.samples_available(), 20);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sample_rate_above_cpu_clock() {
+        let mut apu = powered();
+        apu.set_sample_rate(MAX_SAMPLE_RATE + 1);
+    }
+
+    #[test]
+    fn test_mute_and_capture() {
+        let mut apu = powered();
+        apu.set_sample_rate(48000);
+        apu.set_channel_capture(true);
+        apu.write(NR50_REGISTER, 0x77);
+        apu.write(NR51_REGISTER, 0xFF);
+        apu.write(NR22_REGISTER, 0xF0);
+        apu.write(NR24_REGISTER, 0x80);
+        apu.set_channel_muted(1, true);
+        assert!(apu.channel_muted(1));
+        apu.tick(CPU_FREQUENCY as i64 / 100);
+
+        // the muted channel is silent in the mix but not on its own
+        let loud = |samples: Vec<f32>| samples.iter().any(|sample| sample.abs() > 0.1);
+        assert!(!loud(apu.take_samples()));
+        assert!(loud(apu.take_channel_samples(1)));
+        let channel1 = apu.take_channel_samples(0);
+        assert!((channel1.len() as i64 - 960).abs() <= 2);
+        assert!(!loud(channel1));
+
+        apu.set_channel_muted(1, false);
+        apu.tick(CPU_FREQUENCY as i64 / 100);
+        assert!(loud(apu.take_samples()));
+
+        // nothing is captured once it's turned off
+        apu.set_channel_capture(false);
+        apu.tick(CPU_FREQUENCY as i64 / 100);
+        assert!(apu.take_channel_samples(1).is_empty());
+    }
+
+    #[test]
+    fn test_register_log() {
+        let mut apu = powered();
+        apu.write(NR12_REGISTER, 0xF0);
+        apu.set_register_logging(true);
+        apu.tick(100);
+        apu.write(NR12_REGISTER, 0xF3);
+        apu.tick(20);
+        apu.write(WAVE_RAM_START + 0x0A, 0x12);
+        apu.write(0xFF15, 0x00);
+        let log = apu.take_register_log();
+        assert_eq!(
+            log[0],
+            RegisterWrite {
+                cycle: 100,
+                addr: NR12_REGISTER,
+                value: 0xF3
+            }
+        );
+        assert_eq!(log[0].to_string(), "       100 NR12 F3");
+        assert_eq!(log[1].to_string(), "       120 WAVA 12");
+        assert_eq!(log[2].to_string(), "       120 FF15 00");
+        assert!(apu.take_register_log().is_empty());
+
+        apu.set_register_logging(false);
+        apu.write(NR12_REGISTER, 0x00);
+        assert!(apu.take_register_log().is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+// Reference: https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-4--noise
+
+use super::{Envelope, Length};
+
+const LENGTH_MAX: u16 = 64;
+// the base period for each divider code, shifted left by the clock shift
+const DIVISORS: [i64; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// channel 4, pseudo random noise from a linear feedback shift register
+pub struct Noise {
+    enabled: bool,
+    length: Length,
+    envelope: Envelope,
+    shift: u8,
+    // the LFSR is 7 bits instead of 15, for a more regular sound
+    short_mode: bool,
+    divisor: u8,
+    timer: i64,
+    pub(super) lfsr: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            enabled: false,
+            length: Length::default(),
+            envelope: Envelope::default(),
+            shift: 0,
+            short_mode: false,
+            divisor: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+}
+
+impl Noise {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    fn period(&self) -> i64 {
+        DIVISORS[self.divisor as usize] << self.shift
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        // shifts of 14 and 15 stop the LFSR
+        if self.shift >= 14 {
+            return;
+        }
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.step_lfsr();
+        }
+    }
+
+    fn step_lfsr(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+        }
+    }
+
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            1 => self.write_length(value),
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.shift = value >> 4;
+                self.short_mode = value & 0x08 != 0;
+                self.divisor = value & 0x07;
+            }
+            4 => {
+                if self
+                    .length
+                    .write_control(value, LENGTH_MAX, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.enabled = self.dac_enabled();
+                    self.timer = self.period();
+                    self.envelope.trigger();
+                    self.lfsr = 0x7FFF;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // turning the APU off keeps the length counter
+    pub fn reset(&mut self) {
+        *self = Noise {
+            length: self.length.power_off(),
+            ..Noise::default()
+        };
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(LENGTH_MAX, value & 0x3F);
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
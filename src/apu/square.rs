@@ -0,0 +1,195 @@
+// Reference: https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-1--pulse-with-period-sweep
+
+use super::{Envelope, Length};
+
+// the waveforms for each duty setting, one bit per step
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const LENGTH_MAX: u16 = 64;
+const MAX_FREQUENCY: u16 = 0x7FF;
+
+// channel 1 (with the sweep) and channel 2
+#[derive(Default)]
+pub struct Square {
+    has_sweep: bool,
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    // 11 bits, the period is (2048 - frequency) * 4 cycles
+    pub(super) frequency: u16,
+    timer: i64,
+    length: Length,
+    pub(super) envelope: Envelope,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    // the sweep works on a copy of the frequency
+    shadow_frequency: u16,
+    // a calculation in negate mode happened since the last trigger
+    sweep_negated: bool,
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Square {
+        Square {
+            has_sweep,
+            ..Square::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // the digital output, 0-15
+    pub fn output(&self) -> u8 {
+        let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step) & 1 != 0;
+        if self.enabled && high {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    fn period(&self) -> i64 {
+        (2048 - self.frequency as i64) * 4
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    // register is 0-4 for NRx0-NRx4, next_step_clocks_length is whether
+    // the frame sequencer's next step clocks the length counters
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 if self.has_sweep => {
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                // leaving negate mode after using it turns the channel off
+                if !self.sweep_negate && self.sweep_negated {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(LENGTH_MAX, value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                let trigger = value & 0x80 != 0;
+                if self
+                    .length
+                    .write_control(value, LENGTH_MAX, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // turning the APU off keeps the length counter
+    pub fn reset(&mut self) {
+        *self = Square {
+            length: self.length.power_off(),
+            ..Square::new(self.has_sweep)
+        };
+    }
+
+    // on DMG the length can be loaded while the APU is off
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(LENGTH_MAX, value & 0x3F);
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if self.has_sweep {
+            self.shadow_frequency = self.frequency;
+            self.sweep_timer = sweep_reload(self.sweep_period);
+            self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+            self.sweep_negated = false;
+            // an overflow is caught straight away
+            if self.sweep_shift != 0 {
+                self.calculate_sweep();
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = sweep_reload(self.sweep_period);
+        if !self.sweep_enabled || self.sweep_period == 0 {
+            return;
+        }
+        let frequency = self.calculate_sweep();
+        if frequency <= MAX_FREQUENCY && self.sweep_shift != 0 {
+            self.frequency = frequency;
+            self.shadow_frequency = frequency;
+            // the new frequency is checked for overflow again
+            self.calculate_sweep();
+        }
+    }
+
+    // the next sweep frequency, turning the channel off if it overflows
+    fn calculate_sweep(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.sweep_negated = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        if frequency > MAX_FREQUENCY {
+            self.enabled = false;
+        }
+        frequency
+    }
+}
+
+// a sweep period of 0 counts as 8
+fn sweep_reload(period: u8) -> u8 {
+    if period == 0 {
+        8
+    } else {
+        period
+    }
+}
@@ -0,0 +1,128 @@
+// Reference: https://gbdev.io/pandocs/Audio_Registers.html#sound-channel-3--wave-output
+
+use super::Length;
+
+const LENGTH_MAX: u16 = 256;
+
+// channel 3, plays back 32 4-bit samples from wave RAM
+#[derive(Default)]
+pub struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: Length,
+    // 0 mutes, 1-3 shift the samples right by 0-2
+    volume: u8,
+    // 11 bits, the period is (2048 - frequency) * 2 cycles
+    frequency: u16,
+    timer: i64,
+    position: u8,
+    // the sample last read from wave RAM
+    sample: u8,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.volume == 0 {
+            return 0;
+        }
+        self.sample >> (self.volume - 1)
+    }
+
+    fn period(&self) -> i64 {
+        (2048 - self.frequency as i64) * 2
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        if !self.enabled {
+            return;
+        }
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            // the high nibble is played first
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    pub fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.write_length(value),
+            2 => self.volume = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                if self
+                    .length
+                    .write_control(value, LENGTH_MAX, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.enabled = self.dac_enabled;
+                    self.timer = self.period();
+                    self.position = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(LENGTH_MAX, value);
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // while the channel plays, the CPU sees the byte being played instead
+    // of the one it asked for
+    fn ram_index(&self, index: usize) -> usize {
+        if self.enabled {
+            self.position as usize / 2
+        } else {
+            index
+        }
+    }
+
+    pub fn read_ram(&self, index: usize) -> u8 {
+        self.ram[self.ram_index(index)]
+    }
+
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        let index = self.ram_index(index);
+        self.ram[index] = value;
+    }
+
+    // turning the APU off keeps wave RAM and the length counter
+    pub fn reset(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            length: self.length.power_off(),
+            ..Wave::default()
+        };
+    }
+}
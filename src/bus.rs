@@ -0,0 +1,508 @@
+// Reference: https://gbdev.io/pandocs/Memory_Map.html
+
+use crate::apu::{self, Apu};
+use crate::cartridge::Cartridge;
+use crate::interrupt::{self, IE_REGISTER, IF_REGISTER};
+use crate::joypad::{self, Joypad};
+use crate::ppu::{self, Ppu};
+use crate::serial::{self, Serial};
+use crate::timer::{self, Timer};
+
+// memory map
+pub const ROM_START: u16 = 0x0000;
+pub const ROM_END: u16 = 0x7FFF;
+pub const VRAM_START: u16 = 0x8000;
+pub const VRAM_END: u16 = 0x9FFF;
+pub const EXTERNAL_RAM_START: u16 = 0xA000;
+pub const EXTERNAL_RAM_END: u16 = 0xBFFF;
+pub const WRAM_START: u16 = 0xC000;
+pub const WRAM_END: u16 = 0xDFFF;
+pub const ECHO_RAM_START: u16 = 0xE000;
+pub const ECHO_RAM_END: u16 = 0xFDFF;
+pub const OAM_START: u16 = 0xFE00;
+pub const OAM_END: u16 = 0xFE9F;
+pub const UNUSABLE_START: u16 = 0xFEA0;
+pub const UNUSABLE_END: u16 = 0xFEFF;
+pub const IO_START: u16 = 0xFF00;
+pub const IO_END: u16 = 0xFF7F;
+pub const HRAM_START: u16 = 0xFF80;
+pub const HRAM_END: u16 = 0xFFFE;
+
+// the boot ROM covers the start of the cartridge until it's unmapped
+pub const BOOT_ROM_END: u16 = 0x00FF;
+// writing a non-zero value unmaps the boot ROM, it can't be mapped again
+pub const BOOT_ROM_REGISTER: u16 = 0xFF50;
+
+// Reference: https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+// writing XX copies XX00-XX9F into OAM, one byte per m-cycle
+pub const DMA_REGISTER: u16 = 0xFF46;
+const DMA_LENGTH: u16 = 0xA0;
+
+// the APU's frame sequencer runs off falling edges of this divider bit (512 Hz)
+const FRAME_SEQUENCER_BIT: u16 = 0x1000;
+
+// the falling edges of the frame sequencer bit as the divider counted from
+// one value to the other, which is every time the bits above it change
+fn frame_sequencer_clocks(before: u16, after: u16) -> u16 {
+    (after >> 13).wrapping_sub(before >> 13) & 0x07
+}
+
+pub struct Bus {
+    cartridge: Option<Cartridge>,
+    boot_rom: Vec<u8>,
+    boot_rom_mapped: bool,
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    ppu: Ppu,
+    apu: Apu,
+    // the next byte of a running OAM DMA transfer
+    dma_index: Option<u16>,
+    wram: [u8; 0x2000],
+    io: [u8; 0x80],
+    hram: [u8; 0x7F],
+    interrupt_enable: u8,
+    // a plain 64 KiB of RAM in place of the memory map, for CPU test vectors
+    #[cfg(any(test, feature = "test-harness"))]
+    flat_memory: Option<Vec<u8>>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus {
+            cartridge: None,
+            boot_rom: Vec::new(),
+            boot_rom_mapped: false,
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            serial: Serial::new(),
+            ppu: Ppu::default(),
+            apu: Apu::default(),
+            dma_index: None,
+            wram: [0; 0x2000],
+            io: [0; 0x80],
+            hram: [0; 0x7F],
+            interrupt_enable: 0,
+            #[cfg(any(test, feature = "test-harness"))]
+            flat_memory: None,
+        }
+    }
+}
+
+impl Bus {
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = boot_rom;
+        self.boot_rom_mapped = true;
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    // runs the hardware outside the CPU for the cycles an instruction took
+    pub fn tick(&mut self, cycles: i64) {
+        // none of it is connected to flat memory
+        #[cfg(any(test, feature = "test-harness"))]
+        if self.flat_memory.is_some() {
+            return;
+        }
+        let divider = self.timer.divider();
+        self.timer.tick(cycles);
+        for _ in 0..frame_sequencer_clocks(divider, self.timer.divider()) {
+            self.apu.clock_frame_sequencer();
+        }
+        if self.timer.take_interrupt() {
+            self.request_interrupt(interrupt::TIMER);
+        }
+        if self.joypad.take_interrupt() {
+            self.request_interrupt(interrupt::JOYPAD);
+        }
+        self.serial.tick(cycles);
+        if self.serial.take_interrupt() {
+            self.request_interrupt(interrupt::SERIAL);
+        }
+        for _ in 0..cycles / 4 {
+            self.tick_dma();
+        }
+        self.ppu.tick(cycles);
+        self.apu.tick(cycles);
+        self.io[(IF_REGISTER - IO_START) as usize] |= self.ppu.take_interrupts();
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.tick(cycles);
+        }
+    }
+
+    fn tick_dma(&mut self) {
+        let Some(index) = self.dma_index else {
+            return;
+        };
+        let mut source = (self.io[(DMA_REGISTER - IO_START) as usize] as u16) << 8 | index;
+        // sources past the end of WRAM read the echo
+        if source >= ECHO_RAM_START {
+            source -= 0x2000;
+        }
+        let value = self.read8(source);
+        self.ppu.write_oam_dma(index as usize, value);
+        self.dma_index = (index + 1 < DMA_LENGTH).then_some(index + 1);
+    }
+
+    pub fn dma_active(&self) -> bool {
+        self.dma_index.is_some()
+    }
+
+    // sets the interrupt's bit in IF
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        self.io[(IF_REGISTER - IO_START) as usize] |= 1 << interrupt;
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
+    pub fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    pub fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
+    pub fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    // every address reads back what was written to it from now on, with no
+    // hardware behind any of them
+    #[cfg(any(test, feature = "test-harness"))]
+    pub fn use_flat_memory(&mut self) {
+        self.flat_memory = Some(vec![0; 0x10000]);
+    }
+
+    pub fn read8(&self, addr: u16) -> u8 {
+        #[cfg(any(test, feature = "test-harness"))]
+        if let Some(memory) = &self.flat_memory {
+            return memory[addr as usize];
+        }
+        match addr {
+            ROM_START..=BOOT_ROM_END if self.boot_rom_mapped => {
+                self.boot_rom.get(addr as usize).copied().unwrap_or(0xFF)
+            }
+            ROM_START..=ROM_END => self.read_cartridge(addr),
+            VRAM_START..=VRAM_END => self.ppu.read_vram(addr),
+            EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.read_cartridge(addr),
+            WRAM_START..=WRAM_END => self.wram[(addr - WRAM_START) as usize],
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram[(addr - ECHO_RAM_START) as usize],
+            // the PPU's OAM bus is taken by DMA
+            OAM_START..=OAM_END if self.dma_active() => 0xFF,
+            OAM_START..=OAM_END => self.ppu.read_oam(addr),
+            UNUSABLE_START..=UNUSABLE_END => 0x00,
+            IO_START..=IO_END => self.read_io(addr),
+            HRAM_START..=HRAM_END => self.hram[(addr - HRAM_START) as usize],
+            IE_REGISTER => self.interrupt_enable,
+        }
+    }
+
+    pub fn write8(&mut self, addr: u16, value: u8) {
+        #[cfg(any(test, feature = "test-harness"))]
+        if let Some(memory) = &mut self.flat_memory {
+            memory[addr as usize] = value;
+            return;
+        }
+        match addr {
+            ROM_START..=ROM_END => self.write_cartridge(addr, value),
+            VRAM_START..=VRAM_END => self.ppu.write_vram(addr, value),
+            EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.write_cartridge(addr, value),
+            WRAM_START..=WRAM_END => self.wram[(addr - WRAM_START) as usize] = value,
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram[(addr - ECHO_RAM_START) as usize] = value,
+            OAM_START..=OAM_END if self.dma_active() => {}
+            OAM_START..=OAM_END => self.ppu.write_oam(addr, value),
+            UNUSABLE_START..=UNUSABLE_END => {}
+            IO_START..=IO_END => self.write_io(addr, value),
+            HRAM_START..=HRAM_END => self.hram[(addr - HRAM_START) as usize] = value,
+            IE_REGISTER => self.interrupt_enable = value,
+        }
+    }
+
+    // little endian, the low byte is at addr
+    pub fn read16(&self, addr: u16) -> u16 {
+        (self.read8(addr.wrapping_add(1)) as u16) << 8 | self.read8(addr) as u16
+    }
+
+    pub fn write16(&mut self, addr: u16, value: u16) {
+        self.write8(addr, value as u8);
+        self.write8(addr.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // an empty cartridge slot reads as open bus
+    fn read_cartridge(&self, addr: u16) -> u8 {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.read(addr),
+            None => 0xFF,
+        }
+    }
+
+    // writes to ROM are commands for the cartridge's memory bank controller
+    fn write_cartridge(&mut self, addr: u16, value: u8) {
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.write(addr, value);
+        }
+    }
+
+    fn read_io(&self, addr: u16) -> u8 {
+        match addr {
+            joypad::P1_REGISTER => self.joypad.read(),
+            serial::SB_REGISTER | serial::SC_REGISTER => self.serial.read(addr),
+            timer::DIV_REGISTER..=timer::TAC_REGISTER => self.timer.read(addr),
+            ppu::LCDC_REGISTER..=ppu::LYC_REGISTER | ppu::BGP_REGISTER..=ppu::WX_REGISTER => {
+                self.ppu.read(addr)
+            }
+            apu::NR10_REGISTER..=apu::WAVE_RAM_END => self.apu.read(addr),
+            // the upper 3 bits of IF are unused and always read as 1
+            IF_REGISTER => self.io[(addr - IO_START) as usize] | 0xE0,
+            BOOT_ROM_REGISTER => 0xFF,
+            _ => self.io[(addr - IO_START) as usize],
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            timer::DIV_REGISTER => {
+                // resetting the divider can be a falling edge for the APU too
+                let divider = self.timer.divider();
+                self.timer.write(addr, value);
+                if divider & FRAME_SEQUENCER_BIT != 0 {
+                    self.apu.clock_frame_sequencer();
+                }
+            }
+            timer::TIMA_REGISTER..=timer::TAC_REGISTER => self.timer.write(addr, value),
+            joypad::P1_REGISTER => self.joypad.write(value),
+            serial::SB_REGISTER | serial::SC_REGISTER => self.serial.write(addr, value),
+            apu::NR10_REGISTER..=apu::WAVE_RAM_END => self.apu.write(addr, value),
+            ppu::LCDC_REGISTER..=ppu::LYC_REGISTER | ppu::BGP_REGISTER..=ppu::WX_REGISTER => {
+                self.ppu.write(addr, value);
+                // writing STAT can raise an interrupt straight away
+                self.io[(IF_REGISTER - IO_START) as usize] |= self.ppu.take_interrupts();
+            }
+            DMA_REGISTER => {
+                self.io[(addr - IO_START) as usize] = value;
+                self.dma_index = Some(0);
+            }
+            BOOT_ROM_REGISTER => {
+                if value != 0 {
+                    self.boot_rom_mapped = false;
+                }
+            }
+            _ => self.io[(addr - IO_START) as usize] = value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge;
+
+    #[test]
+    fn test_regions() {
+        let mut bus = Bus::default();
+        for addr in [0x8000, 0xC000, 0xFE00, 0xFF80, 0xFFFE, 0xFFFF] {
+            bus.write8(addr, 0x42);
+            assert_eq!(bus.read8(addr), 0x42);
+        }
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mut bus = Bus::default();
+        bus.write8(0xC123, 0x12);
+        assert_eq!(bus.read8(0xE123), 0x12);
+        bus.write8(0xFDFF, 0x34);
+        assert_eq!(bus.read8(0xDDFF), 0x34);
+    }
+
+    #[test]
+    fn test_rom_is_read_only() {
+        let mut bus = Bus::default();
+        let mut rom = cartridge::test_rom(0x00, 0x00, 0x00);
+        rom[0x0000] = 0x31;
+        bus.insert_cartridge(Cartridge::from_bytes(rom).unwrap());
+        bus.write8(0x0000, 0x00);
+        assert_eq!(bus.read8(0x0000), 0x31);
+    }
+
+    #[test]
+    fn test_external_ram() {
+        let mut bus = Bus::default();
+        // no cartridge
+        assert_eq!(bus.read8(0x0100), 0xFF);
+        assert_eq!(bus.read8(0xA000), 0xFF);
+
+        // ROM+RAM, 8 KiB
+        let rom = cartridge::test_rom(0x08, 0x00, 0x02);
+        bus.insert_cartridge(Cartridge::from_bytes(rom).unwrap());
+        bus.write8(0xA000, 0x42);
+        assert_eq!(bus.read8(0xA000), 0x42);
+    }
+
+    #[test]
+    fn test_flat_memory() {
+        let mut bus = Bus::default();
+        bus.use_flat_memory();
+        for addr in [0x0000, 0x7FFF, 0xFE00, 0xFF00, 0xFF44, 0xFFFF] {
+            bus.write8(addr, 0x5A);
+            assert_eq!(bus.read8(addr), 0x5A);
+        }
+        // the hardware isn't running behind it
+        bus.tick(1024);
+        assert_eq!(bus.timer().divider(), 0);
+    }
+
+    #[test]
+    fn test_unusable() {
+        let mut bus = Bus::default();
+        bus.write8(0xFEA0, 0x12);
+        assert_eq!(bus.read8(0xFEA0), 0x00);
+    }
+
+    #[test]
+    fn test_16bit() {
+        let mut bus = Bus::default();
+        bus.write16(0xC000, 0x1234);
+        assert_eq!(bus.read8(0xC000), 0x34);
+        assert_eq!(bus.read8(0xC001), 0x12);
+        assert_eq!(bus.read16(0xC000), 0x1234);
+    }
+
+    #[test]
+    fn test_boot_rom() {
+        let mut bus = Bus::default();
+        let mut rom = cartridge::test_rom(0x00, 0x00, 0x00);
+        rom[0x0000] = 0x31;
+        bus.insert_cartridge(Cartridge::from_bytes(rom).unwrap());
+        bus.map_boot_rom(vec![0xAA; 0x100]);
+        assert_eq!(bus.read8(0x0000), 0xAA);
+        assert_eq!(bus.read8(0x00FF), 0xAA);
+        // the header is still visible underneath
+        assert_eq!(bus.read8(0x0134), b'T');
+
+        // writing 0 does nothing
+        bus.write8(BOOT_ROM_REGISTER, 0x00);
+        assert!(bus.boot_rom_mapped());
+        bus.write8(BOOT_ROM_REGISTER, 0x01);
+        assert!(!bus.boot_rom_mapped());
+        assert_eq!(bus.read8(0x0000), 0x31);
+        assert_eq!(bus.read8(BOOT_ROM_REGISTER), 0xFF);
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut bus = Bus::default();
+        // 262144 Hz, overflow on the first increment
+        bus.write8(timer::TAC_REGISTER, 0x05);
+        bus.write8(timer::TIMA_REGISTER, 0xFF);
+        bus.write8(timer::TMA_REGISTER, 0xF0);
+        bus.tick(20);
+        assert_eq!(bus.read8(timer::TIMA_REGISTER), 0xF0);
+        assert_eq!(bus.read8(IF_REGISTER), 0xE0 | (1 << interrupt::TIMER));
+    }
+
+    #[test]
+    fn test_serial_interrupt() {
+        let mut bus = Bus::default();
+        bus.serial_mut().set_capture(true);
+        bus.write8(serial::SB_REGISTER, b'!');
+        bus.write8(serial::SC_REGISTER, 0x81);
+        bus.tick(4096);
+        assert_eq!(bus.read8(IF_REGISTER), 0xE0 | (1 << interrupt::SERIAL));
+        assert_eq!(bus.serial_mut().take_output(), b"!");
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::default();
+        for i in 0..0xA0 {
+            bus.write8(0xC100 + i, i as u8);
+        }
+        bus.write8(DMA_REGISTER, 0xC1);
+        assert_eq!(bus.read8(DMA_REGISTER), 0xC1);
+        assert!(bus.dma_active());
+        assert_eq!(bus.read8(0xFE00), 0xFF);
+        bus.tick(0x9F * 4);
+        assert!(bus.dma_active());
+        bus.tick(4);
+        assert!(!bus.dma_active());
+        assert_eq!(bus.read8(0xFE00), 0x00);
+        assert_eq!(bus.read8(0xFE9F), 0x9F);
+    }
+
+    #[test]
+    fn test_frame_sequencer() {
+        assert_eq!(frame_sequencer_clocks(0x1FFC, 0x2000), 1);
+        assert_eq!(frame_sequencer_clocks(0x2000, 0x3FFC), 0);
+        assert_eq!(frame_sequencer_clocks(0xFFFC, 0x0004), 1);
+
+        // a length counter of 1 runs out on the first clock
+        let mut bus = Bus::default();
+        bus.write8(apu::NR52_REGISTER, 0x80);
+        bus.write8(apu::NR22_REGISTER, 0xF0);
+        bus.write8(apu::NR21_REGISTER, 63);
+        bus.write8(apu::NR24_REGISTER, 0xC0);
+        assert_eq!(bus.read8(apu::NR52_REGISTER), 0xF2);
+        bus.tick(0x1FFC);
+        assert_eq!(bus.read8(apu::NR52_REGISTER), 0xF2);
+        bus.tick(4);
+        assert_eq!(bus.read8(apu::NR52_REGISTER), 0xF0);
+
+        // resetting DIV with bit 12 set clocks it as well, on step 2
+        bus.tick(0x2000);
+        bus.write8(apu::NR21_REGISTER, 63);
+        bus.write8(apu::NR24_REGISTER, 0xC0);
+        bus.tick(0x1000);
+        assert_eq!(bus.read8(apu::NR52_REGISTER), 0xF2);
+        bus.write8(timer::DIV_REGISTER, 0);
+        assert_eq!(bus.read8(apu::NR52_REGISTER), 0xF0);
+    }
+
+    #[test]
+    fn test_interrupt_flag() {
+        let mut bus = Bus::default();
+        bus.write8(IF_REGISTER, 0x01);
+        assert_eq!(bus.read8(IF_REGISTER), 0xE1);
+    }
+}
@@ -0,0 +1,613 @@
+// Reference: https://gbdev.io/pandocs/The_Cartridge_Header.html
+
+use std::{
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::mbc;
+use crate::mbc::mbc3::{Rtc, RTC_SAVE_SIZE, RTC_SAVE_SIZE_32BIT};
+
+// header layout
+const HEADER_END: usize = 0x0150;
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const CGB_FLAG: usize = 0x0143;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION: usize = 0x014A;
+const VERSION: usize = 0x014C;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    // the file is too small to hold a header
+    TooSmall(usize),
+    UnsupportedCartridgeType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    // the file size doesn't match the ROM size in the header
+    RomSizeMismatch { expected: usize, actual: usize },
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+    // a save file that doesn't fit the cartridge's RAM (and clock)
+    SaveSizeMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(err) => write!(f, "{}", err),
+            CartridgeError::TooSmall(size) => {
+                write!(f, "ROM is {} bytes, too small for a header", size)
+            }
+            CartridgeError::UnsupportedCartridgeType(code) => {
+                write!(f, "unsupported cartridge type {:#04X}", code)
+            }
+            CartridgeError::InvalidRomSize(code) => write!(f, "invalid ROM size {:#04X}", code),
+            CartridgeError::InvalidRamSize(code) => write!(f, "invalid RAM size {:#04X}", code),
+            CartridgeError::RomSizeMismatch { expected, actual } => write!(
+                f,
+                "header declares a {} byte ROM but the file is {} bytes",
+                expected, actual
+            ),
+            CartridgeError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum is {:#04X}, computed {:#04X}",
+                expected, actual
+            ),
+            CartridgeError::GlobalChecksum { expected, actual } => write!(
+                f,
+                "global checksum is {:#06X}, computed {:#06X}",
+                expected, actual
+            ),
+            CartridgeError::SaveSizeMismatch { expected, actual } => {
+                write!(f, "save file is {} bytes, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(err: io::Error) -> Self {
+        CartridgeError::Io(err)
+    }
+}
+
+// memory bank controller chips
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mbc {
+    None,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mbc: Mbc,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<CartridgeType> {
+        // (mbc, ram, battery, timer, rumble)
+        let (mbc, ram, battery, timer, rumble) = match code {
+            0x00 => (Mbc::None, false, false, false, false),
+            0x01 => (Mbc::Mbc1, false, false, false, false),
+            0x02 => (Mbc::Mbc1, true, false, false, false),
+            0x03 => (Mbc::Mbc1, true, true, false, false),
+            0x05 => (Mbc::Mbc2, false, false, false, false),
+            0x06 => (Mbc::Mbc2, false, true, false, false),
+            0x08 => (Mbc::None, true, false, false, false),
+            0x09 => (Mbc::None, true, true, false, false),
+            0x0F => (Mbc::Mbc3, false, true, true, false),
+            0x10 => (Mbc::Mbc3, true, true, true, false),
+            0x11 => (Mbc::Mbc3, false, false, false, false),
+            0x12 => (Mbc::Mbc3, true, false, false, false),
+            0x13 => (Mbc::Mbc3, true, true, false, false),
+            0x19 => (Mbc::Mbc5, false, false, false, false),
+            0x1A => (Mbc::Mbc5, true, false, false, false),
+            0x1B => (Mbc::Mbc5, true, true, false, false),
+            0x1C => (Mbc::Mbc5, false, false, false, true),
+            0x1D => (Mbc::Mbc5, true, false, false, true),
+            0x1E => (Mbc::Mbc5, true, true, false, true),
+            _ => return None,
+        };
+        Some(CartridgeType {
+            code,
+            mbc,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    // 0x143 bit 7 clear
+    DmgOnly,
+    // 0x80, works on both
+    CgbEnhanced,
+    // 0xC0
+    CgbOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub title: String,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl Header {
+    // parses and validates the header at 0x0100-0x014F
+    pub fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let computed = compute_header_checksum(rom);
+        if header_checksum != computed {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: header_checksum,
+                actual: computed,
+            });
+        }
+
+        let cgb = match rom[CGB_FLAG] {
+            0xC0 => CgbSupport::CgbOnly,
+            flag if flag & 0x80 != 0 => CgbSupport::CgbEnhanced,
+            _ => CgbSupport::DmgOnly,
+        };
+        // newer cartridges reuse the end of the title for the
+        // manufacturer code and CGB flag
+        let title_end = if cgb == CgbSupport::DmgOnly {
+            TITLE_END + 1
+        } else {
+            TITLE_END
+        };
+        let title = rom[TITLE_START..title_end]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let cartridge_type = CartridgeType::from_code(rom[CARTRIDGE_TYPE]).ok_or(
+            CartridgeError::UnsupportedCartridgeType(rom[CARTRIDGE_TYPE]),
+        )?;
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(CartridgeError::InvalidRomSize(code)),
+        };
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartridgeError::InvalidRamSize(code)),
+        };
+
+        Ok(Header {
+            title,
+            cgb,
+            sgb: rom[SGB_FLAG] == 0x03,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            destination: if rom[DESTINATION] == 0x00 {
+                Destination::Japan
+            } else {
+                Destination::Overseas
+            },
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum: (rom[GLOBAL_CHECKSUM] as u16) << 8 | rom[GLOBAL_CHECKSUM + 1] as u16,
+        })
+    }
+}
+
+// the boot ROM refuses to start a cartridge when this doesn't match
+pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_START..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1))
+}
+
+// sum of every byte in the ROM except the checksum itself
+pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16))
+}
+
+pub struct Cartridge {
+    pub header: Header,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    controller: mbc::Controller,
+    // RAM was written since the last save
+    ram_dirty: bool,
+}
+
+// battery saves sit next to the ROM, game.gb saves to game.sav
+pub fn save_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("sav")
+}
+
+impl Cartridge {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cartridge, CartridgeError> {
+        let rom = fs::read(path)?;
+        Cartridge::from_bytes(rom)
+    }
+
+    pub fn from_bytes(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = Header::parse(&rom)?;
+        if rom.len() != header.rom_size {
+            return Err(CartridgeError::RomSizeMismatch {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+        let controller = mbc::Controller::new(&header, &rom).ok_or(
+            CartridgeError::UnsupportedCartridgeType(header.cartridge_type.code),
+        )?;
+        let ram = vec![0; controller.ram_size(&header)];
+        Ok(Cartridge {
+            header,
+            rom,
+            ram,
+            controller,
+            ram_dirty: false,
+        })
+    }
+
+    // Real hardware never checks the global checksum, so a mismatch is only
+    // reported here rather than refusing to load the cartridge.
+    pub fn verify_global_checksum(&self) -> Result<(), CartridgeError> {
+        let computed = compute_global_checksum(&self.rom);
+        if computed != self.header.global_checksum {
+            return Err(CartridgeError::GlobalChecksum {
+                expected: self.header.global_checksum,
+                actual: computed,
+            });
+        }
+        Ok(())
+    }
+
+    // 0x0000-0x7FFF and 0xA000-0xBFFF
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => self.controller.read_rom(&self.rom, addr),
+            0xA000..=0xBFFF => self.controller.read_ram(&self.ram, addr),
+            _ => 0xFF,
+        }
+    }
+
+    // writes to ROM go to the memory bank controller's registers
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x7FFF => self.controller.write_rom(addr, value),
+            0xA000..=0xBFFF => {
+                self.ram_dirty |= self.controller.write_ram(&mut self.ram, addr, value);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.battery
+    }
+
+    pub fn ram_dirty(&self) -> bool {
+        self.ram_dirty
+    }
+
+    // the RAM followed by the RTC footer on MBC3 carts with a clock, the
+    // same layout other emulators use so saves can be moved between them
+    pub fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = self.rtc() {
+            data.extend_from_slice(&rtc.save());
+        }
+        data
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        let ram_size = self.ram.len();
+        let footer = data.len().saturating_sub(ram_size);
+        let valid = match self.rtc() {
+            // a save without a footer is accepted, the clock just starts over
+            Some(_) => [0, RTC_SAVE_SIZE_32BIT, RTC_SAVE_SIZE].contains(&footer),
+            None => footer == 0,
+        };
+        if data.len() < ram_size || !valid {
+            return Err(CartridgeError::SaveSizeMismatch {
+                expected: self.save_data().len(),
+                actual: data.len(),
+            });
+        }
+        self.ram.copy_from_slice(&data[..ram_size]);
+        if let (Some(rtc), true) = (self.rtc_mut(), footer > 0) {
+            rtc.load(&data[ram_size..]);
+        }
+        self.ram_dirty = false;
+        Ok(())
+    }
+
+    // returns false when there is no save yet
+    pub fn load_save_file<P: AsRef<Path>>(&mut self, path: P) -> Result<bool, CartridgeError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        self.load_save_data(&data)?;
+        Ok(true)
+    }
+
+    // writes to a temporary file first so a crash can't leave half a save
+    pub fn write_save_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CartridgeError> {
+        let path = path.as_ref();
+        let temp = path.with_extension("sav.tmp");
+        fs::write(&temp, self.save_data())?;
+        fs::rename(&temp, path)?;
+        self.ram_dirty = false;
+        Ok(())
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        self.controller.tick(cycles);
+    }
+
+    // the MBC3 real time clock, if the cartridge has one
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.controller.rtc()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.controller.rtc_mut()
+    }
+
+    pub fn rumble_active(&self) -> bool {
+        self.controller.rumble_active()
+    }
+}
+
+// builds a ROM with a valid header and checksums, for tests elsewhere that
+// need a cartridge
+#[cfg(test)]
+pub(crate) fn test_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000 << rom_size];
+    rom[TITLE_START..TITLE_START + 4].copy_from_slice(b"TEST");
+    rom[CARTRIDGE_TYPE] = cartridge_type;
+    rom[ROM_SIZE] = rom_size;
+    rom[RAM_SIZE] = ram_size;
+    rom[DESTINATION] = 0x01;
+    rom[HEADER_CHECKSUM] = compute_header_checksum(&rom);
+    let global = compute_global_checksum(&rom);
+    rom[GLOBAL_CHECKSUM] = (global >> 8) as u8;
+    rom[GLOBAL_CHECKSUM + 1] = global as u8;
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let cartridge = Cartridge::from_bytes(test_rom(0x03, 0x01, 0x03)).unwrap();
+        let header = &cartridge.header;
+        assert_eq!(header.title, "TEST");
+        assert_eq!(header.cgb, CgbSupport::DmgOnly);
+        assert!(!header.sgb);
+        assert_eq!(header.cartridge_type.mbc, Mbc::Mbc1);
+        assert!(header.cartridge_type.ram);
+        assert!(header.cartridge_type.battery);
+        assert!(!header.cartridge_type.timer);
+        assert_eq!(header.rom_size, 0x10000);
+        assert_eq!(header.ram_size, 0x8000);
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.version, 0);
+        assert!(cartridge.verify_global_checksum().is_ok());
+    }
+
+    #[test]
+    fn test_cgb_title() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[TITLE_START..=TITLE_END].copy_from_slice(b"ABCDEFGHIJKLMNO\xC0");
+        rom[HEADER_CHECKSUM] = compute_header_checksum(&rom);
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.cgb, CgbSupport::CgbOnly);
+        assert_eq!(header.title, "ABCDEFGHIJKLMNO");
+    }
+
+    #[test]
+    fn test_header_checksum() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[TITLE_START] = b'X';
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::HeaderChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_global_checksum() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[0x4000] = 0x12;
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert!(matches!(
+            cartridge.verify_global_checksum(),
+            Err(CartridgeError::GlobalChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(matches!(
+            Cartridge::from_bytes(vec![0; 0x100]),
+            Err(CartridgeError::TooSmall(0x100))
+        ));
+        assert!(matches!(
+            Cartridge::from_bytes(test_rom(0xFC, 0x00, 0x00)),
+            Err(CartridgeError::UnsupportedCartridgeType(0xFC))
+        ));
+
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom.truncate(0x4000);
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::RomSizeMismatch {
+                expected: 0x8000,
+                actual: 0x4000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_mbc1_write() {
+        // MBC1, 128 KiB
+        let mut rom = test_rom(0x01, 0x02, 0x00);
+        rom[0x3 * 0x4000] = 0x42;
+        let mut cartridge = Cartridge::from_bytes(rom).unwrap();
+        cartridge.write(0x2100, 0x03);
+        assert_eq!(cartridge.read(0x4000), 0x42);
+        // the write changed the bank, not the ROM
+        assert_eq!(cartridge.read(0x2100), 0x00);
+    }
+
+    #[test]
+    fn test_controllers() {
+        // MBC2+BATTERY, the header has no RAM size but the chip has 512 nibbles
+        let cartridge = Cartridge::from_bytes(test_rom(0x06, 0x01, 0x00)).unwrap();
+        assert_eq!(cartridge.ram.len(), 0x200);
+        assert!(cartridge.rtc().is_none());
+
+        // MBC3+TIMER+RAM+BATTERY
+        let mut cartridge = Cartridge::from_bytes(test_rom(0x10, 0x01, 0x03)).unwrap();
+        assert!(cartridge.rtc().is_some());
+        cartridge.tick(crate::cpu::CPU_FREQUENCY as i64);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x08);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 1);
+
+        // MBC5+RUMBLE
+        let mut cartridge = Cartridge::from_bytes(test_rom(0x1C, 0x01, 0x00)).unwrap();
+        cartridge.write(0x4000, 0x08);
+        assert!(cartridge.rumble_active());
+    }
+
+    #[test]
+    fn test_save_data() {
+        // MBC1+RAM+BATTERY, 8 KiB
+        let mut cartridge = Cartridge::from_bytes(test_rom(0x03, 0x00, 0x02)).unwrap();
+        assert!(cartridge.has_battery());
+        // disabled RAM isn't written
+        cartridge.write(0xA123, 0x42);
+        assert!(!cartridge.ram_dirty());
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA123, 0x42);
+        assert!(cartridge.ram_dirty());
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), 0x2000);
+
+        let mut loaded = Cartridge::from_bytes(test_rom(0x03, 0x00, 0x02)).unwrap();
+        loaded.load_save_data(&data).unwrap();
+        loaded.write(0x0000, 0x0A);
+        assert_eq!(loaded.read(0xA123), 0x42);
+        assert!(matches!(
+            loaded.load_save_data(&[0; 0x100]),
+            Err(CartridgeError::SaveSizeMismatch {
+                expected: 0x2000,
+                actual: 0x100
+            })
+        ));
+    }
+
+    #[test]
+    fn test_save_rtc() {
+        // MBC3+TIMER+RAM+BATTERY, 32 KiB
+        let mut cartridge = Cartridge::from_bytes(test_rom(0x10, 0x01, 0x03)).unwrap();
+        // the clock registers aren't RAM
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x08);
+        cartridge.write(0xA000, 0x00);
+        assert!(!cartridge.ram_dirty());
+        cartridge.tick(61 * crate::cpu::CPU_FREQUENCY as i64);
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), 0x8000 + RTC_SAVE_SIZE);
+        assert_eq!(data[0x8000..0x8008], [1, 0, 0, 0, 1, 0, 0, 0]);
+
+        let mut loaded = Cartridge::from_bytes(test_rom(0x10, 0x01, 0x03)).unwrap();
+        loaded.load_save_data(&data).unwrap();
+        assert_eq!(loaded.rtc().unwrap().register(0x09), 1);
+        // without the footer, or with a 32 bit timestamp
+        loaded.load_save_data(&data[..0x8000]).unwrap();
+        loaded.load_save_data(&data[..0x8000 + 44]).unwrap();
+        assert!(loaded.load_save_data(&data[..0x8000 + 10]).is_err());
+    }
+
+    #[test]
+    fn test_save_file() {
+        let dir = std::env::temp_dir().join(format!("emulator-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = save_path(dir.join("game.gb"));
+        assert_eq!(path, dir.join("game.sav"));
+
+        let mut cartridge = Cartridge::from_bytes(test_rom(0x03, 0x00, 0x02)).unwrap();
+        assert!(!cartridge.load_save_file(&path).unwrap());
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        cartridge.write_save_file(&path).unwrap();
+        assert!(!cartridge.ram_dirty());
+
+        let mut loaded = Cartridge::from_bytes(test_rom(0x03, 0x00, 0x02)).unwrap();
+        assert!(loaded.load_save_file(&path).unwrap());
+        assert_eq!(loaded.ram[0], 0x42);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file() {
+        assert!(matches!(
+            Cartridge::from_file("does/not/exist.gb"),
+            Err(CartridgeError::Io(_))
+        ));
+    }
+}
@@ -9,7 +9,33 @@ const C_FLAG: u8 = 4; // Carry flag
 // 4.194304 MHz
 pub const CPU_FREQUENCY: f64 = 4194304.0;
 
-// #[derive(Default)]
+// 8 bit registers that instructions can name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg8 {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    L,
+}
+
+// 16 bit register pairs that instructions can name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg16 {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+// Default is the power on state, everything is zero until the boot ROM (or
+// gameboy::skip_boot_rom) sets it up
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Default)]
 pub struct CPU {
     // 8 Bit registers
     a: u8, // Accumulator
@@ -26,25 +52,18 @@ pub struct CPU {
     // 16 Bit Registers
     pc: u16, // Program counter
     sp: u16, // Stack pointer
-}
 
-impl Default for CPU {
-    fn default() -> Self {
-        CPU {
-            a: 0x01,
-            b: 0x00,
-            c: 0x13,
-            d: 0x00,
-            e: 0xD8,
-            f: 0xB0,
-            h: 0x01,
-            l: 0x4D,
-            i: 0,
-            r: 0,
-            pc: 0x0000,
-            sp: 0xFFFE,
-        }
-    }
+    // Interrupts
+    ime: bool,    // Interrupt master enable
+    ei_delay: u8, // Instructions left until a pending EI sets IME
+
+    // Low power states
+    halted: bool,   // HALT, woken by any pending interrupt
+    stopped: bool,  // STOP, woken by a joypad press
+    halt_bug: bool, // next opcode fetch doesn't increment PC
+
+    // Debugging
+    breakpoint: bool, // LD B,B ran, test ROMs use it to say they're done
 }
 
 pub fn run() {
@@ -86,6 +105,7 @@ pub fn dump_registers(cpu: &CPU) {
     println!("hl: {:#06X}", cpu.get_hl());
     println!("pc: {:#06X}", cpu.get_pc());
     println!("sp: {:#06X}", cpu.get_sp());
+    println!("ime: {}", cpu.get_ime());
 }
 
 pub fn print_flags(cpu: &CPU) {
@@ -99,8 +119,9 @@ pub fn print_flags(cpu: &CPU) {
 // purely because it is more convenient to get and set combined registers with
 // .get_af() and .set_af() than it is to get and set the individual registers
 impl CPU {
+    // PC wraps around from the top of memory
     pub fn increment_pc(&mut self) {
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
     }
 
     pub fn set_z_flag(&mut self, value: bool) {
@@ -183,6 +204,30 @@ impl CPU {
         self.sp
     }
 
+    pub fn get_ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn get_ei_delay(&self) -> u8 {
+        self.ei_delay
+    }
+
+    pub fn get_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn get_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn get_halt_bug(&self) -> bool {
+        self.halt_bug
+    }
+
+    pub fn get_breakpoint(&self) -> bool {
+        self.breakpoint
+    }
+
     pub fn get_hl(&self) -> u16 {
         (self.h as u16) << 8 | self.l as u16
     }
@@ -248,6 +293,30 @@ impl CPU {
         self.sp = value;
     }
 
+    pub fn set_ime(&mut self, value: bool) {
+        self.ime = value;
+    }
+
+    pub fn set_ei_delay(&mut self, value: u8) {
+        self.ei_delay = value;
+    }
+
+    pub fn set_halted(&mut self, value: bool) {
+        self.halted = value;
+    }
+
+    pub fn set_stopped(&mut self, value: bool) {
+        self.stopped = value;
+    }
+
+    pub fn set_halt_bug(&mut self, value: bool) {
+        self.halt_bug = value;
+    }
+
+    pub fn set_breakpoint(&mut self, value: bool) {
+        self.breakpoint = value;
+    }
+
     pub fn set_af(&mut self, value: u16) {
         self.a = (value >> 8) as u8;
         self.f = value as u8;
@@ -268,56 +337,54 @@ impl CPU {
         self.l = value as u8;
     }
 
-    pub fn get_register_8bit(&mut self, register: &str) -> u8 {
+    pub fn get_register_8bit(&self, register: Reg8) -> u8 {
         match register {
-            "a" => self.a,
-            "b" => self.b,
-            "c" => self.c,
-            "d" => self.d,
-            "e" => self.e,
-            "f" => self.f,
-            "h" => self.h,
-            "l" => self.l,
-            _ => panic!("Invalid register"),
+            Reg8::A => self.a,
+            Reg8::B => self.b,
+            Reg8::C => self.c,
+            Reg8::D => self.d,
+            Reg8::E => self.e,
+            Reg8::F => self.f,
+            Reg8::H => self.h,
+            Reg8::L => self.l,
         }
     }
-    pub fn get_register_16bit(&mut self, register: &str) -> u16 {
+
+    pub fn get_register_16bit(&self, register: Reg16) -> u16 {
         match register {
-            "af" => self.get_af(),
-            "bc" => self.get_bc(),
-            "de" => self.get_de(),
-            "hl" => self.get_hl(),
-            "sp" => self.get_sp(),
-            _ => panic!("Invalid register"),
+            Reg16::AF => self.get_af(),
+            Reg16::BC => self.get_bc(),
+            Reg16::DE => self.get_de(),
+            Reg16::HL => self.get_hl(),
+            Reg16::SP => self.get_sp(),
         }
     }
 
-    pub fn set_register_8bit(&mut self, register: &str, value: u8) {
+    pub fn set_register_8bit(&mut self, register: Reg8, value: u8) {
         match register {
-            "a" => self.a = value,
-            "b" => self.b = value,
-            "c" => self.c = value,
-            "d" => self.d = value,
-            "e" => self.e = value,
-            "f" => self.f = value,
-            "h" => self.h = value,
-            "l" => self.l = value,
-            _ => panic!("Invalid register"),
+            Reg8::A => self.a = value,
+            Reg8::B => self.b = value,
+            Reg8::C => self.c = value,
+            Reg8::D => self.d = value,
+            Reg8::E => self.e = value,
+            Reg8::F => self.f = value,
+            Reg8::H => self.h = value,
+            Reg8::L => self.l = value,
         }
     }
 
-    pub fn set_register_16bit(&mut self, register: &str, value: u16) {
+    pub fn set_register_16bit(&mut self, register: Reg16, value: u16) {
         match register {
-            "af" => self.set_af(value),
-            "bc" => self.set_bc(value),
-            "de" => self.set_de(value),
-            "hl" => self.set_hl(value),
-            "sp" => self.set_sp(value),
-            _ => panic!("Invalid register"),
+            Reg16::AF => self.set_af(value),
+            Reg16::BC => self.set_bc(value),
+            Reg16::DE => self.set_de(value),
+            Reg16::HL => self.set_hl(value),
+            Reg16::SP => self.set_sp(value),
         }
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -438,6 +505,33 @@ mod tests {
         assert_eq!(cpu.get_sp(), 0x1234);
     }
 
+    #[test]
+    fn test_register_8bit() {
+        let mut cpu = CPU::default();
+        cpu.set_register_8bit(Reg8::D, 0x12);
+        assert_eq!(cpu.get_d(), 0x12);
+        assert_eq!(cpu.get_register_8bit(Reg8::D), 0x12);
+    }
+
+    #[test]
+    fn test_register_16bit() {
+        let mut cpu = CPU::default();
+        cpu.set_register_16bit(Reg16::DE, 0x1234);
+        assert_eq!(cpu.get_d(), 0x12);
+        assert_eq!(cpu.get_e(), 0x34);
+        assert_eq!(cpu.get_register_16bit(Reg16::DE), 0x1234);
+        cpu.set_register_16bit(Reg16::SP, 0xFFFE);
+        assert_eq!(cpu.get_register_16bit(Reg16::SP), 0xFFFE);
+    }
+
+    #[test]
+    fn test_ime() {
+        let mut cpu = CPU::default();
+        assert!(!cpu.get_ime());
+        cpu.set_ime(true);
+        assert!(cpu.get_ime());
+    }
+
     #[test]
     fn test_af_register() {
         let mut cpu = CPU::default();
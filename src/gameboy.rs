@@ -1,45 +1,559 @@
 // // mod cpu;
+use std::{io, path::Path};
+
+use crate::apu;
+use crate::bus;
 use crate::cpu;
 use crate::instruction;
+use crate::interrupt;
+use crate::joypad;
+use crate::ppu;
+use crate::screenshot;
+use crate::serial::link::Link;
 
 pub struct Gameboy {
     pub cpu: cpu::CPU,
-    pub ram: [u8; 0xFFFF],
+    pub bus: bus::Bus,
 }
 
 pub fn create_gameboy() -> Gameboy {
     let mut gb = Gameboy {
         cpu: cpu::CPU::default(),
-        ram: [0; 0xFFFF],
+        bus: bus::Bus::default(),
     };
-    gb.ram[0xfffe] = 0x00;
+    gb.bus.write8(0xfffe, 0x00);
     gb
 }
 
+// Reference: https://gbdev.io/pandocs/Power_Up_Sequence.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    // the first DMG revision, with its own boot ROM
+    Dmg0,
+    Dmg,
+    // Game Boy Pocket
+    Mgb,
+    Sgb,
+    Sgb2,
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg0" => Some(Model::Dmg0),
+            "dmg" => Some(Model::Dmg),
+            "mgb" => Some(Model::Mgb),
+            "sgb" => Some(Model::Sgb),
+            "sgb2" => Some(Model::Sgb2),
+            _ => None,
+        }
+    }
+}
+
+// hardware registers as the boot ROM leaves them, the sound registers are
+// written so they read back the same
+const POST_BOOT_IO: [(u16, u8); 30] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    // NR52 first, the other sound registers ignore writes while it's off
+    (0xFF26, 0x80), // NR52
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    // retriggers channel 1, which is still playing the chime
+    (0xFF14, 0x87), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF47, 0xFC), // BGP
+];
+
+// maps a boot ROM over the start of the cartridge and starts running it
+pub fn load_boot_rom(gb: &mut Gameboy, boot_rom: Vec<u8>) {
+    gb.bus.map_boot_rom(boot_rom);
+    gb.cpu.set_pc(0x0000);
+}
+
+// starts at the cartridge entry point in the state the model's boot ROM
+// would have left behind
+pub fn skip_boot_rom(gb: &mut Gameboy, model: Model) {
+    // H and C are set by the DMG boot ROM unless the header checksum is 0
+    let header_checksum = gb
+        .bus
+        .cartridge()
+        .map_or(0, |cartridge| cartridge.header.header_checksum);
+    let dmg_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+    let (af, bc, de, hl) = match model {
+        Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+        Model::Dmg => (0x0100 | dmg_flags, 0x0013, 0x00D8, 0x014D),
+        Model::Mgb => (0xFF00 | dmg_flags, 0x0013, 0x00D8, 0x014D),
+        Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+        Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+    };
+    gb.cpu.set_af(af);
+    gb.cpu.set_bc(bc);
+    gb.cpu.set_de(de);
+    gb.cpu.set_hl(hl);
+    gb.cpu.set_sp(0xFFFE);
+    gb.cpu.set_pc(0x0100);
+
+    for (addr, value) in POST_BOOT_IO {
+        gb.bus.write8(addr, value);
+    }
+    if matches!(model, Model::Sgb | Model::Sgb2) {
+        // there's no chime on the SGB, turning the DAC off stops channel 1
+        gb.bus.write8(apu::NR12_REGISTER, 0x00);
+        gb.bus.write8(apu::NR12_REGISTER, 0xF3);
+    }
+    // the divider has been running since power on, how long the SGB boot
+    // takes depends on the SNES so there's no fixed value for it
+    let divider = match model {
+        Model::Dmg0 => 0x1800,
+        Model::Dmg | Model::Mgb => 0xABCC,
+        Model::Sgb | Model::Sgb2 => 0x0000,
+    };
+    gb.bus.timer_mut().set_divider(divider);
+    gb.bus.write8(bus::BOOT_ROM_REGISTER, 0x01);
+}
+
 // reads the byte at the current program counter
 pub fn read_byte(gb: &mut Gameboy) -> u16 {
-    let byte: u8 = gb.ram[gb.cpu.get_pc() as usize];
+    let byte: u8 = gb.bus.read8(gb.cpu.get_pc());
     gb.cpu.increment_pc();
     return byte.into();
 }
 
 pub fn read_short(gb: &mut Gameboy) -> u16 {
-    let short: u16 = (gb.ram[(gb.cpu.get_pc() + 1) as usize] as u16) << 8
-        | (gb.ram[gb.cpu.get_pc() as usize] as u16);
+    let short: u16 = gb.bus.read16(gb.cpu.get_pc());
     gb.cpu.increment_pc();
     gb.cpu.increment_pc();
     return short;
 }
 
+// the time spent asleep per step, so the rest of the hardware keeps running
+// while the CPU is halted or stopped
+const LOW_POWER_CYCLES: i64 = 4;
+
+// runs one instruction (or interrupt dispatch, or low power wait) and keeps
+// the rest of the hardware in step, returns the cycles taken
 pub fn step_cpu(gb: &mut Gameboy) -> i64 {
+    let cycles = run_cpu(gb);
+    gb.bus.tick(cycles);
+    cycles
+}
+
+// the most a frame can take, the PPU may be switched off and never finish one
+const FRAME_CYCLES: i64 = 70224;
+
+// runs until the PPU has finished drawing the next frame, returns the cycles
+// taken
+pub fn run_frame(gb: &mut Gameboy) -> i64 {
+    let frame = frame_count(gb);
+    let mut cycles = 0;
+    while frame_count(gb) == frame && cycles < FRAME_CYCLES {
+        cycles += step_cpu(gb);
+    }
+    cycles
+}
+
+// the last frame the PPU drew, one shade (0-3) per pixel in rows of
+// ppu::SCREEN_WIDTH
+pub fn framebuffer(gb: &Gameboy) -> &ppu::Framebuffer {
+    gb.bus.ppu().framebuffer()
+}
+
+// saves the last frame as a PNG, or a PPM if the path ends in .ppm
+pub fn save_screenshot(gb: &Gameboy, path: &Path) -> io::Result<()> {
+    screenshot::save(framebuffer(gb), path)
+}
+
+// starts collecting interleaved stereo samples at this rate, 0 stops
+pub fn set_sample_rate(gb: &mut Gameboy, rate: u32) {
+    gb.bus.apu_mut().set_sample_rate(rate);
+}
+
+// the samples mixed since the last call
+pub fn take_samples(gb: &mut Gameboy) -> Vec<f32> {
+    gb.bus.apu_mut().take_samples()
+}
+
+// the buttons held down from now on, a press raises the joypad interrupt
+pub fn set_input(gb: &mut Gameboy, state: joypad::InputState) {
+    gb.bus.joypad_mut().set_state(state);
+}
+
+pub fn input(gb: &Gameboy) -> joypad::InputState {
+    gb.bus.joypad().state()
+}
+
+// starts or stops keeping the bytes sent over the serial port, test ROMs
+// print their results this way
+pub fn set_serial_capture(gb: &mut Gameboy, capture: bool) {
+    gb.bus.serial_mut().set_capture(capture);
+}
+
+// the bytes sent over the serial port since the last call, empty unless
+// capturing
+pub fn take_serial_output(gb: &mut Gameboy) -> Vec<u8> {
+    gb.bus.serial_mut().take_output()
+}
+
+// plugs in a link cable to another Game Boy, both run in lockstep from now
+// on
+pub fn connect_link(gb: &mut Gameboy, link: Box<dyn Link>) {
+    gb.bus.serial_mut().connect(link);
+}
+
+// the number of frames drawn since power on
+pub fn frame_count(gb: &Gameboy) -> u64 {
+    gb.bus.ppu().frame()
+}
+
+fn run_cpu(gb: &mut Gameboy) -> i64 {
     let mut cycles = 0;
+
+    if gb.cpu.get_stopped() {
+        if gb.bus.read8(interrupt::IF_REGISTER) & (1 << interrupt::JOYPAD) == 0 {
+            return LOW_POWER_CYCLES;
+        }
+        gb.cpu.set_stopped(false);
+        cycles += LOW_POWER_CYCLES;
+    }
+
+    if gb.cpu.get_halted() {
+        // IE and IF wake the CPU even when IME is off
+        if interrupt::pending_interrupts(gb) == 0 {
+            return LOW_POWER_CYCLES;
+        }
+        gb.cpu.set_halted(false);
+        cycles += LOW_POWER_CYCLES;
+    }
+
+    // an interrupt dispatch takes the place of the next instruction
+    let interrupt_cycles = interrupt::handle_interrupts(gb);
+    if interrupt_cycles > 0 {
+        return cycles + interrupt_cycles;
+    }
+
     // fetch
     let opcode = read_byte(gb);
-    cycles += 4;
-    // decode
-    cycles += 2;
-    // execute
+    if gb.cpu.get_halt_bug() {
+        gb.cpu.set_halt_bug(false);
+        gb.cpu.set_pc(gb.cpu.get_pc().wrapping_sub(1));
+    }
+    // execute, the cycle counts include the fetch
     cycles += instruction::execute_instruction(gb, opcode);
 
-    return cycles;
+    // EI sets IME once the instruction after it has finished
+    let ei_delay = gb.cpu.get_ei_delay();
+    if ei_delay > 0 {
+        gb.cpu.set_ei_delay(ei_delay - 1);
+        if ei_delay == 1 {
+            gb.cpu.set_ime(true);
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge;
+
+    #[test]
+    fn test_step_cycles() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // NOP, LD BC n16, SET 0 B
+        for (i, byte) in [0x00, 0x01, 0x34, 0x12, 0xCB, 0xC0].iter().enumerate() {
+            gb.bus.write8(0xC000 + i as u16, *byte);
+        }
+        assert_eq!(step_cpu(&mut gb), 4);
+        assert_eq!(step_cpu(&mut gb), 12);
+        assert_eq!(step_cpu(&mut gb), 8);
+        // the timer saw every cycle
+        assert_eq!(gb.bus.timer().divider(), 24);
+    }
+
+    #[test]
+    fn test_skip_boot_rom() {
+        let mut gb = create_gameboy();
+        let rom = cartridge::test_rom(0x00, 0x00, 0x00);
+        gb.bus
+            .insert_cartridge(cartridge::Cartridge::from_bytes(rom).unwrap());
+        skip_boot_rom(&mut gb, Model::Dmg);
+        assert_eq!(gb.cpu.get_af(), 0x01B0);
+        assert_eq!(gb.cpu.get_bc(), 0x0013);
+        assert_eq!(gb.cpu.get_de(), 0x00D8);
+        assert_eq!(gb.cpu.get_hl(), 0x014D);
+        assert_eq!(gb.cpu.get_sp(), 0xFFFE);
+        assert_eq!(gb.cpu.get_pc(), 0x0100);
+        assert_eq!(gb.bus.read8(0xFF40), 0x91);
+        assert_eq!(gb.bus.read8(0xFF26), 0xF1);
+        assert_eq!(gb.bus.read8(0xFF14), 0xBF);
+        assert!(!gb.bus.boot_rom_mapped());
+
+        let mut gb = create_gameboy();
+        skip_boot_rom(&mut gb, Model::Sgb2);
+        assert_eq!(gb.cpu.get_af(), 0xFF00);
+        assert_eq!(gb.cpu.get_hl(), 0xC060);
+        assert_eq!(gb.bus.read8(0xFF26), 0xF0);
+        assert_eq!(Model::from_name("MGB"), Some(Model::Mgb));
+    }
+
+    #[test]
+    fn test_boot_rom() {
+        let boot_rom = include_bytes!("../bootloader.bin").to_vec();
+        let mut rom = cartridge::test_rom(0x00, 0x00, 0x00);
+        // the boot ROM locks up unless the cartridge has the logo stored at
+        // the end of the boot ROM
+        rom[0x0104..0x0134].copy_from_slice(&boot_rom[0xCB..0xFB]);
+        // JR -2 at the entry point
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let mut gb = create_gameboy();
+        gb.bus
+            .insert_cartridge(cartridge::Cartridge::from_bytes(rom).unwrap());
+        load_boot_rom(&mut gb, boot_rom);
+
+        let mut steps = 0;
+        while gb.bus.boot_rom_mapped() {
+            step_cpu(&mut gb);
+            steps += 1;
+            assert!(steps < 10_000_000, "stuck at {:#06X}", gb.cpu.get_pc());
+        }
+        // bootloader.bin is the DMG0 boot ROM, it should leave the same state
+        // skipping it does
+        let mut skipped = create_gameboy();
+        skip_boot_rom(&mut skipped, Model::Dmg0);
+        assert_eq!(gb.cpu.get_af(), skipped.cpu.get_af());
+        assert_eq!(gb.cpu.get_bc(), skipped.cpu.get_bc());
+        assert_eq!(gb.cpu.get_de(), skipped.cpu.get_de());
+        assert_eq!(gb.cpu.get_hl(), skipped.cpu.get_hl());
+        assert_eq!(gb.cpu.get_sp(), skipped.cpu.get_sp());
+        assert_eq!(gb.cpu.get_pc(), skipped.cpu.get_pc());
+
+        // the logo is on screen
+        let frame = frame_count(&gb);
+        run_frame(&mut gb);
+        assert_eq!(frame_count(&gb), frame + 1);
+        assert!(framebuffer(&gb).contains(&3));
+    }
+
+    #[test]
+    fn test_run_frame_lcd_off() {
+        // with the LCD off no frame ever finishes
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // JR -2
+        gb.bus.write8(0xC000, 0x18);
+        gb.bus.write8(0xC001, 0xFE);
+        assert!(run_frame(&mut gb) >= FRAME_CYCLES);
+        assert_eq!(frame_count(&gb), 0);
+    }
+
+    #[test]
+    fn test_halt_with_ime() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_ime(true);
+        gb.bus.write8(interrupt::IE_REGISTER, 1 << interrupt::TIMER);
+        // HALT
+        gb.bus.write8(0xC000, 0x76);
+
+        step_cpu(&mut gb);
+        assert!(gb.cpu.get_halted());
+        assert_eq!(step_cpu(&mut gb), LOW_POWER_CYCLES);
+        assert_eq!(gb.cpu.get_pc(), 0xC001);
+
+        interrupt::request_interrupt(&mut gb, interrupt::TIMER);
+        assert_eq!(step_cpu(&mut gb), 24);
+        assert!(!gb.cpu.get_halted());
+        assert_eq!(gb.cpu.get_pc(), 0x50);
+        // returns to the instruction after HALT
+        assert_eq!(instruction::pop_value(&mut gb), 0xC001);
+    }
+
+    #[test]
+    fn test_halt_without_ime() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.bus
+            .write8(interrupt::IE_REGISTER, 1 << interrupt::VBLANK);
+        // HALT, SCF
+        gb.bus.write8(0xC000, 0x76);
+        gb.bus.write8(0xC001, 0x37);
+
+        step_cpu(&mut gb);
+        step_cpu(&mut gb);
+        assert!(gb.cpu.get_halted());
+
+        // wakes up and carries on without dispatching
+        interrupt::request_interrupt(&mut gb, interrupt::VBLANK);
+        gb.cpu.set_c_flag(false);
+        step_cpu(&mut gb);
+        assert!(!gb.cpu.get_halted());
+        assert!(gb.cpu.get_c_flag());
+        assert_eq!(gb.cpu.get_pc(), 0xC002);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.bus
+            .write8(interrupt::IE_REGISTER, 1 << interrupt::VBLANK);
+        interrupt::request_interrupt(&mut gb, interrupt::VBLANK);
+        // HALT, INC A
+        gb.bus.write8(0xC000, 0x76);
+        gb.bus.write8(0xC001, 0x3C);
+        gb.cpu.set_a(0);
+
+        step_cpu(&mut gb);
+        assert!(!gb.cpu.get_halted());
+        // INC A is read twice
+        step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0xC001);
+        step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0xC002);
+        assert_eq!(gb.cpu.get_a(), 2);
+    }
+
+    #[test]
+    fn test_halt_bug_interrupt() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0xFFFE);
+        gb.bus.write8(interrupt::IE_REGISTER, 1 << interrupt::TIMER);
+        interrupt::request_interrupt(&mut gb, interrupt::TIMER);
+        // EI, HALT, the handler at 0x50 reads RST 38 without a cartridge
+        gb.bus.write8(0xC000, 0xFB);
+        gb.bus.write8(0xC001, 0x76);
+
+        step_cpu(&mut gb);
+        step_cpu(&mut gb);
+        assert!(gb.cpu.get_ime());
+        // the interrupt returns to the HALT
+        step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0x50);
+        assert_eq!(gb.bus.read16(0xFFFC), 0xC001);
+        assert!(!gb.cpu.get_halt_bug());
+        // and the handler's first opcode is only read once
+        step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0x38);
+        assert_eq!(gb.bus.read16(0xFFFA), 0x0051);
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // STOP 0
+        gb.bus.write8(0xC000, 0x10);
+        gb.bus.write8(0xC001, 0x00);
+
+        step_cpu(&mut gb);
+        assert!(gb.cpu.get_stopped());
+        assert_eq!(gb.cpu.get_pc(), 0xC002);
+        // other interrupts don't end STOP
+        interrupt::request_interrupt(&mut gb, interrupt::TIMER);
+        assert_eq!(step_cpu(&mut gb), LOW_POWER_CYCLES);
+        assert!(gb.cpu.get_stopped());
+
+        interrupt::request_interrupt(&mut gb, interrupt::JOYPAD);
+        gb.bus.write8(0xC002, 0x37);
+        step_cpu(&mut gb);
+        assert!(!gb.cpu.get_stopped());
+        assert_eq!(gb.cpu.get_pc(), 0xC003);
+    }
+
+    #[test]
+    fn test_input() {
+        let mut gb = create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // LD A,$10; LDH ($00),A; STOP 0; LDH A,($00)
+        for (i, byte) in [0x3E, 0x10, 0xE0, 0x00, 0x10, 0x00, 0xF0, 0x00]
+            .into_iter()
+            .enumerate()
+        {
+            gb.bus.write8(0xC000 + i as u16, byte);
+        }
+        for _ in 0..3 {
+            step_cpu(&mut gb);
+        }
+        assert!(gb.cpu.get_stopped());
+        step_cpu(&mut gb);
+        assert!(gb.cpu.get_stopped());
+
+        // pressing a selected button wakes the CPU from STOP
+        let state = joypad::InputState {
+            start: true,
+            ..joypad::InputState::default()
+        };
+        set_input(&mut gb, state);
+        assert_eq!(input(&gb), state);
+        step_cpu(&mut gb);
+        step_cpu(&mut gb);
+        assert!(!gb.cpu.get_stopped());
+        assert_eq!(gb.cpu.get_a(), 0xD7);
+        assert_ne!(
+            gb.bus.read8(interrupt::IF_REGISTER) & (1 << interrupt::JOYPAD),
+            0
+        );
+    }
+
+    #[test]
+    fn test_link_cable() {
+        use crate::serial::{self, link::MemoryLink};
+        use std::thread;
+
+        // LD A,sb; LDH ($01),A; LD A,sc; LDH ($02),A; JR -2
+        fn run(link: MemoryLink, sb: u8, sc: u8) -> (u8, u8) {
+            let mut gb = create_gameboy();
+            connect_link(&mut gb, Box::new(link));
+            gb.cpu.set_pc(0xC000);
+            for (i, byte) in [0x3E, sb, 0xE0, 0x01, 0x3E, sc, 0xE0, 0x02, 0x18, 0xFE]
+                .into_iter()
+                .enumerate()
+            {
+                gb.bus.write8(0xC000 + i as u16, byte);
+            }
+            let mut cycles = 0;
+            while cycles < 8192 {
+                cycles += step_cpu(&mut gb);
+            }
+            (
+                gb.bus.read8(serial::SB_REGISTER),
+                gb.bus.read8(interrupt::IF_REGISTER),
+            )
+        }
+
+        let (a, b) = MemoryLink::pair();
+        let slave = thread::spawn(move || run(b, 0x99, 0x80));
+        let master = run(a, 0x42, 0x81);
+        let slave = slave.join().unwrap();
+        assert_eq!(master.0, 0x99);
+        assert_eq!(slave.0, 0x42);
+        assert_ne!(master.1 & (1 << interrupt::SERIAL), 0);
+        assert_ne!(slave.1 & (1 << interrupt::SERIAL), 0);
+    }
 }
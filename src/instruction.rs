@@ -1,54 +1,58 @@
-// use core::panic;
-
-use crate::{cpu, gameboy};
+use crate::cpu::{Reg16, Reg8};
+use crate::{gameboy, interrupt};
+
+// An 8-bit instruction operand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(Reg8),
+    // the byte in memory at the address in HL
+    IndirectHL,
+    // the next byte after the opcode, advances PC when read
+    Immediate,
+}
 
 pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
     let mut cycles: i64 = 0;
 
-    println!("Executing opcode: {:02X}", opcode);
     match opcode {
         0x00 => {
             // NOP
-            cpu::dump_registers(&gb.cpu);
-            // print ram
-            // println!("{:?}", gb.ram);
-            panic!("NOP");
-            // let _ = Command::new("pause").status();
             cycles += 4;
         }
         0x01 => {
             // LD BC n16
-            load_immediate_16bit(gb, "bc");
+            load_immediate_16bit(gb, Reg16::BC);
             cycles += 12;
         }
         0x02 => {
             // LD BC A
             let addr = gb.cpu.get_bc();
-            gb.ram[addr as usize] = gb.cpu.get_a();
+            gb.bus.write8(addr, gb.cpu.get_a());
             cycles += 8;
         }
         0x03 => {
             // INC BC
-            increment_16bit(gb, "bc");
+            increment_16bit(gb, Reg16::BC);
             cycles += 8;
         }
         0x04 => {
             // INC B
-            increment_8bit(gb, "b");
+            increment_8bit(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x05 => {
             // DEC B
-            decrement_8bit(gb, "b");
+            decrement_8bit(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x06 => {
             // LD B n8
-            load_immediate_8bit(gb, "b");
+            load_immediate_8bit(gb, Reg8::B);
             cycles += 8;
         }
         0x07 => {
             // RLCA
+            rotate_accumulator(gb, rotate_left_circular);
             cycles += 4;
         }
         0x08 => {
@@ -57,126 +61,137 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
             // specified by the 16-bit immediate operand a16, and store the
             // upper byte of SP at address a16 + 1.
             let addr = gameboy::read_short(gb);
-            gb.ram[addr as usize] = gb.cpu.get_sp() as u8;
-            gb.ram[(addr + 1) as usize] = (gb.cpu.get_sp() >> 8) as u8;
+            gb.bus.write16(addr, gb.cpu.get_sp());
             cycles += 20;
         }
         0x09 => {
             // ADD HL BC
+            add_16bit(gb, Reg16::BC);
             cycles += 8;
         }
         0x0A => {
             // LD A BC
+            let addr = gb.cpu.get_bc();
+            gb.cpu.set_a(gb.bus.read8(addr));
             cycles += 8;
         }
         0x0B => {
             // DEC BC
-            decrement_16bit(gb, "bc");
+            decrement_16bit(gb, Reg16::BC);
             cycles += 8;
         }
         0x0C => {
             // INC C
+            increment_8bit(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x0D => {
             // DEC C
-            decrement_8bit(gb, "c");
+            decrement_8bit(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x0E => {
             // LD C n8
-            load_immediate_8bit(gb, "c");
+            load_immediate_8bit(gb, Reg8::C);
             cycles += 8;
         }
         0x0F => {
             // RRCA
+            rotate_accumulator(gb, rotate_right_circular);
             cycles += 4;
         }
         0x10 => {
             // STOP n8
+            // Enter the very low power mode until a button is pressed. The
+            // operand byte is ignored but still skipped.
+            gameboy::read_byte(gb);
+            gb.cpu.set_stopped(true);
             cycles += 4;
         }
         0x11 => {
             // LD DE n16
-            load_immediate_16bit(gb, "de");
+            load_immediate_16bit(gb, Reg16::DE);
             cycles += 12;
         }
         0x12 => {
             // LD DE A
             let addr = gb.cpu.get_de();
-            gb.ram[addr as usize] = gb.cpu.get_a();
+            gb.bus.write8(addr, gb.cpu.get_a());
             cycles += 8;
         }
         0x13 => {
             // INC DE
-            increment_16bit(gb, "de");
+            increment_16bit(gb, Reg16::DE);
             cycles += 8;
         }
         0x14 => {
             // INC D
-            increment_8bit(gb, "d");
+            increment_8bit(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x15 => {
             // DEC D
-            decrement_8bit(gb, "d");
+            decrement_8bit(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x16 => {
             // LD D n8
-            load_immediate_8bit(gb, "d");
+            load_immediate_8bit(gb, Reg8::D);
             cycles += 8;
         }
         0x17 => {
             // RLA
+            rotate_accumulator(gb, rotate_left);
             cycles += 4;
         }
         0x18 => {
             // JR e8
-            let value = gb.cpu.get_pc() as i8;
-            gb.cpu.increment_pc();
-            jump_relative(gb, i16::try_from(value).unwrap());
+            let value = gameboy::read_byte(gb) as i8;
+            jump_relative(gb, value);
             cycles += 12;
         }
         0x19 => {
             // ADD HL DE
+            add_16bit(gb, Reg16::DE);
             cycles += 8;
         }
         0x1A => {
             // LD A DE
+            let addr = gb.cpu.get_de();
+            gb.cpu.set_a(gb.bus.read8(addr));
             cycles += 8;
         }
         0x1B => {
             // DEC DE
-            decrement_16bit(gb, "de");
+            decrement_16bit(gb, Reg16::DE);
             cycles += 8;
         }
         0x1C => {
             // INC E
-            increment_8bit(gb, "e");
+            increment_8bit(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x1D => {
             // DEC E
-            decrement_8bit(gb, "e");
+            decrement_8bit(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x1E => {
             // LD E n8
-            load_immediate_8bit(gb, "e");
+            load_immediate_8bit(gb, Reg8::E);
             cycles += 8;
         }
         0x1F => {
             // RRA
+            rotate_accumulator(gb, rotate_right);
             cycles += 4;
         }
         0x20 => {
             // JR NZ e8
+            // the offset is read whether or not the jump is taken
+            let value = gameboy::read_byte(gb) as i8;
             if !gb.cpu.get_z_flag() {
-                let value = gameboy::read_byte(gb) as i8;
-                println!("Jumping {} steps", value);
-                let addr = jump_relative(gb, i16::try_from(value).unwrap());
-                println!("Jumped to {:04X}", addr);
+                jump_relative(gb, value);
                 cycles += 12;
             } else {
                 cycles += 8;
@@ -190,42 +205,42 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0x22 => {
             // LD HL+ A
-            let address = gb.cpu.get_hl();
-            gb.ram[address as usize] = gb.cpu.get_a();
-            let hl = gb.cpu.get_hl();
-            gb.cpu.set_hl(hl + 1);
-            load_16bit(gb, "hl", hl + 1);
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, gb.cpu.get_a());
+            increment_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x23 => {
             // INC HL
-            increment_16bit(gb, "hl");
+            increment_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x24 => {
             // INC H
-            increment_8bit(gb, "h");
+            increment_8bit(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x25 => {
             // DEC H
-            decrement_8bit(gb, "h");
+            decrement_8bit(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x26 => {
             // LD H n8
-            load_immediate_8bit(gb, "h");
+            load_immediate_8bit(gb, Reg8::H);
             cycles += 8;
         }
         0x27 => {
             // DAA
+            decimal_adjust(gb);
             cycles += 4;
         }
         0x28 => {
             // JR Z e8
+            // the offset is read whether or not the jump is taken
+            let value = gameboy::read_byte(gb) as i8;
             if gb.cpu.get_z_flag() {
-                let value = gameboy::read_byte(gb) as i8;
-                jump_relative(gb, i16::try_from(value).unwrap());
+                jump_relative(gb, value);
                 cycles += 12;
             } else {
                 cycles += 8;
@@ -233,34 +248,35 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0x29 => {
             // ADD HL HL
+            add_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x2A => {
             // LD A HL+
             // Load the contents of memory specified by register pair HL into register A, and then increment the contents of HL.
             let addr = gb.cpu.get_hl();
-            gb.cpu.set_a(gb.ram[addr as usize]);
-            increment_16bit(gb, "hl");
+            gb.cpu.set_a(gb.bus.read8(addr));
+            increment_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x2B => {
             // DEC HL
-            decrement_16bit(gb, "hl");
+            decrement_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x2C => {
             // INC L
-            increment_8bit(gb, "l");
+            increment_8bit(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x2D => {
             // DEC L
-            decrement_8bit(gb, "l");
+            decrement_8bit(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x2E => {
             // LD L n8
-            load_immediate_8bit(gb, "l");
+            load_immediate_8bit(gb, Reg8::L);
             cycles += 8;
         }
         0x2F => {
@@ -273,9 +289,10 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0x30 => {
             // JR NC e8
+            // the offset is read whether or not the jump is taken
+            let value = gameboy::read_byte(gb) as i8;
             if !gb.cpu.get_c_flag() {
-                let value = gameboy::read_byte(gb) as i8;
-                jump_relative(gb, i16::try_from(value).unwrap());
+                jump_relative(gb, value);
                 cycles += 12;
             } else {
                 cycles += 8;
@@ -284,42 +301,38 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         0x31 => {
             // LD SP n16
             let value = gameboy::read_short(gb);
-            load_16bit(gb, "sp", value);
+            load_16bit(gb, Reg16::SP, value);
             cycles += 12;
         }
         0x32 => {
             // LD HL- A
             // Load register A to the memory address pointed to by HL and then decrement the value of HL
-            let address = gb.cpu.get_hl();
-            gb.ram[address as usize] = gb.cpu.get_a();
-            let hl = gb.cpu.get_hl();
-            gb.cpu.set_hl(hl - 1);
-            load_16bit(gb, "hl", hl - 1);
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, gb.cpu.get_a());
+            decrement_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x33 => {
             // INC SP
-            increment_16bit(gb, "sp");
+            increment_16bit(gb, Reg16::SP);
             cycles += 8;
         }
         0x34 => {
             // INC HL
-            // increment_16bit(gb, "hl");
+            increment_8bit(gb, Operand::IndirectHL);
             cycles += 12;
-            panic!("Not implemented");
         }
         0x35 => {
             // DEC HL
-            // decrement_16bit(gb, "hl");
+            decrement_8bit(gb, Operand::IndirectHL);
             cycles += 12;
-            panic!("Not implemented");
         }
         0x36 => {
             // LD HL n8
             // Store the contents of 8-bit immediate operand d8 in the memory location specified by register pair HL.
             let value = gameboy::read_byte(gb) as u8;
             let hl = gb.cpu.get_hl();
-            gb.ram[hl as usize] = value;
+            gb.bus.write8(hl, value);
             cycles += 12;
         }
         0x37 => {
@@ -329,9 +342,10 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0x38 => {
             // JR C e8
+            // the offset is read whether or not the jump is taken
+            let value = gameboy::read_byte(gb) as i8;
             if gb.cpu.get_c_flag() {
-                let value = gameboy::read_byte(gb) as i8;
-                jump_relative(gb, i16::try_from(value).unwrap());
+                jump_relative(gb, value);
                 cycles += 12;
             } else {
                 cycles += 8;
@@ -339,34 +353,35 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0x39 => {
             // ADD HL SP
+            add_16bit(gb, Reg16::SP);
             cycles += 8;
         }
         0x3A => {
             // LD A HL-
             // Load the contents of memory specified by register pair HL into register A, and then decrement the contents of HL.
             let addr = gb.cpu.get_hl();
-            gb.cpu.set_a(gb.ram[addr as usize]);
-            decrement_16bit(gb, "hl");
+            gb.cpu.set_a(gb.bus.read8(addr));
+            decrement_16bit(gb, Reg16::HL);
             cycles += 8;
         }
         0x3B => {
             // DEC SP
-            decrement_16bit(gb, "sp");
+            decrement_16bit(gb, Reg16::SP);
             cycles += 8;
         }
         0x3C => {
             // INC A
-            increment_8bit(gb, "a");
+            increment_8bit(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0x3D => {
             // DEC A
-            decrement_8bit(gb, "a");
+            decrement_8bit(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0x3E => {
             // LD A n8
-            load_immediate_8bit(gb, "a");
+            load_immediate_8bit(gb, Reg8::A);
             cycles += 8;
         }
         0x3F => {
@@ -375,255 +390,256 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
             cycles += 4;
         }
         0x40 => {
-            // LD B B
-            load_register_8bit(gb, "b", "b");
+            // LD B B, also the breakpoint for Mooneye's and other test ROMs
+            load_register_8bit(gb, Reg8::B, Reg8::B);
+            gb.cpu.set_breakpoint(true);
             cycles += 4;
         }
         0x41 => {
             // LD B C
-            load_register_8bit(gb, "b", "c");
+            load_register_8bit(gb, Reg8::B, Reg8::C);
             cycles += 4;
         }
         0x42 => {
             // LD B D
-            load_register_8bit(gb, "b", "d");
+            load_register_8bit(gb, Reg8::B, Reg8::D);
             cycles += 4;
         }
         0x43 => {
             // LD B E
-            load_register_8bit(gb, "b", "e");
+            load_register_8bit(gb, Reg8::B, Reg8::E);
             cycles += 4;
         }
         0x44 => {
             // LD B H
-            load_register_8bit(gb, "b", "h");
+            load_register_8bit(gb, Reg8::B, Reg8::H);
             cycles += 4;
         }
         0x45 => {
             // LD B L
-            load_register_8bit(gb, "b", "l");
+            load_register_8bit(gb, Reg8::B, Reg8::L);
             cycles += 4;
         }
         0x46 => {
             // LD B HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "b", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::B, value);
             cycles += 8;
         }
         0x47 => {
             // LD B A
-            load_register_8bit(gb, "b", "a");
+            load_register_8bit(gb, Reg8::B, Reg8::A);
             cycles += 4;
         }
         0x48 => {
             // LD C B
-            load_register_8bit(gb, "c", "b");
+            load_register_8bit(gb, Reg8::C, Reg8::B);
             cycles += 4;
         }
         0x49 => {
             // LD C C
-            load_register_8bit(gb, "c", "c");
+            load_register_8bit(gb, Reg8::C, Reg8::C);
             cycles += 4;
         }
         0x4A => {
             // LD C D
-            load_register_8bit(gb, "c", "d");
+            load_register_8bit(gb, Reg8::C, Reg8::D);
             cycles += 4;
         }
         0x4B => {
             // LD C E
-            load_register_8bit(gb, "c", "e");
+            load_register_8bit(gb, Reg8::C, Reg8::E);
             cycles += 4;
         }
         0x4C => {
             // LD C H
-            load_register_8bit(gb, "c", "h");
+            load_register_8bit(gb, Reg8::C, Reg8::H);
             cycles += 4;
         }
         0x4D => {
             // LD C L
-            load_register_8bit(gb, "c", "l");
+            load_register_8bit(gb, Reg8::C, Reg8::L);
             cycles += 4;
         }
         0x4E => {
             // LD C HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "c", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::C, value);
             cycles += 8;
         }
         0x4F => {
             // LD C A
-            load_register_8bit(gb, "c", "a");
+            load_register_8bit(gb, Reg8::C, Reg8::A);
             cycles += 4;
         }
         0x50 => {
             // LD D B
-            load_register_8bit(gb, "d", "b");
+            load_register_8bit(gb, Reg8::D, Reg8::B);
             cycles += 4;
         }
         0x51 => {
             // LD D C
-            load_register_8bit(gb, "d", "c");
+            load_register_8bit(gb, Reg8::D, Reg8::C);
             cycles += 4;
         }
         0x52 => {
             // LD D D
-            load_register_8bit(gb, "d", "d");
+            load_register_8bit(gb, Reg8::D, Reg8::D);
             cycles += 4;
         }
         0x53 => {
             // LD D E
-            load_register_8bit(gb, "d", "e");
+            load_register_8bit(gb, Reg8::D, Reg8::E);
             cycles += 4;
         }
         0x54 => {
             // LD D H
-            load_register_8bit(gb, "d", "h");
+            load_register_8bit(gb, Reg8::D, Reg8::H);
             cycles += 4;
         }
         0x55 => {
             // LD D L
-            load_register_8bit(gb, "d", "l");
+            load_register_8bit(gb, Reg8::D, Reg8::L);
             cycles += 4;
         }
         0x56 => {
             // LD D HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "d", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::D, value);
             cycles += 8;
         }
         0x57 => {
             // LD D A
-            load_register_8bit(gb, "d", "a");
+            load_register_8bit(gb, Reg8::D, Reg8::A);
             cycles += 4;
         }
         0x58 => {
             // LD E B
-            load_register_8bit(gb, "e", "b");
+            load_register_8bit(gb, Reg8::E, Reg8::B);
             cycles += 4;
         }
         0x59 => {
             // LD E C
-            load_register_8bit(gb, "e", "c");
+            load_register_8bit(gb, Reg8::E, Reg8::C);
             cycles += 4;
         }
         0x5A => {
             // LD E D
-            load_register_8bit(gb, "e", "d");
+            load_register_8bit(gb, Reg8::E, Reg8::D);
             cycles += 4;
         }
         0x5B => {
             // LD E E
-            load_register_8bit(gb, "e", "e");
+            load_register_8bit(gb, Reg8::E, Reg8::E);
             cycles += 4;
         }
         0x5C => {
             // LD E H
-            load_register_8bit(gb, "e", "h");
+            load_register_8bit(gb, Reg8::E, Reg8::H);
             cycles += 4;
         }
         0x5D => {
             // LD E L
-            load_register_8bit(gb, "e", "l");
+            load_register_8bit(gb, Reg8::E, Reg8::L);
             cycles += 4;
         }
         0x5E => {
             // LD E HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "e", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::E, value);
             cycles += 8;
         }
         0x5F => {
             // LD E A
-            load_register_8bit(gb, "e", "a");
+            load_register_8bit(gb, Reg8::E, Reg8::A);
             cycles += 4;
         }
         0x60 => {
             // LD H B
-            load_register_8bit(gb, "h", "b");
+            load_register_8bit(gb, Reg8::H, Reg8::B);
             cycles += 4;
         }
         0x61 => {
             // LD H C
-            load_register_8bit(gb, "h", "c");
+            load_register_8bit(gb, Reg8::H, Reg8::C);
             cycles += 4;
         }
         0x62 => {
             // LD H D
-            load_register_8bit(gb, "h", "d");
+            load_register_8bit(gb, Reg8::H, Reg8::D);
             cycles += 4;
         }
         0x63 => {
             // LD H E
-            load_register_8bit(gb, "h", "e");
+            load_register_8bit(gb, Reg8::H, Reg8::E);
             cycles += 4;
         }
         0x64 => {
             // LD H H
-            load_register_8bit(gb, "h", "h");
+            load_register_8bit(gb, Reg8::H, Reg8::H);
             cycles += 4;
         }
         0x65 => {
             // LD H L
-            load_register_8bit(gb, "h", "l");
+            load_register_8bit(gb, Reg8::H, Reg8::L);
             cycles += 4;
         }
         0x66 => {
             // LD H HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "h", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::H, value);
             cycles += 8;
         }
         0x67 => {
             // LD H A
-            load_register_8bit(gb, "h", "a");
+            load_register_8bit(gb, Reg8::H, Reg8::A);
             cycles += 4;
         }
         0x68 => {
             // LD L B
-            load_register_8bit(gb, "l", "b");
+            load_register_8bit(gb, Reg8::L, Reg8::B);
             cycles += 4;
         }
         0x69 => {
             // LD L C
-            load_register_8bit(gb, "l", "c");
+            load_register_8bit(gb, Reg8::L, Reg8::C);
             cycles += 4;
         }
         0x6A => {
             // LD L D
-            load_register_8bit(gb, "l", "d");
+            load_register_8bit(gb, Reg8::L, Reg8::D);
             cycles += 4;
         }
         0x6B => {
             // LD L E
-            load_register_8bit(gb, "l", "e");
+            load_register_8bit(gb, Reg8::L, Reg8::E);
             cycles += 4;
         }
         0x6C => {
             // LD L H
-            load_register_8bit(gb, "l", "h");
+            load_register_8bit(gb, Reg8::L, Reg8::H);
             cycles += 4;
         }
         0x6D => {
             // LD L L
-            load_register_8bit(gb, "l", "l");
+            load_register_8bit(gb, Reg8::L, Reg8::L);
             cycles += 4;
         }
         0x6E => {
             // LD L HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "l", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::L, value);
             cycles += 8;
         }
         0x6F => {
             // LD L A
-            load_register_8bit(gb, "l", "a");
+            load_register_8bit(gb, Reg8::L, Reg8::A);
             cycles += 4;
         }
         0x70 => {
@@ -631,442 +647,444 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
             // Store the contents of register B in the memory location specified by register pair HL
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_b();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x71 => {
             // LD HL C
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_c();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x72 => {
             // LD HL D
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_d();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x73 => {
             // LD HL E
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_e();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x74 => {
             // LD HL H
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_h();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x75 => {
             // LD HL L
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_l();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x76 => {
             // HALT
+            // Sleep until an interrupt is pending. With IME off and an
+            // interrupt already pending the CPU doesn't halt, instead the
+            // next opcode fetch fails to increment PC (the HALT bug).
+            if !gb.cpu.get_ime() && interrupt::pending_interrupts(gb) != 0 {
+                gb.cpu.set_halt_bug(true);
+            } else {
+                gb.cpu.set_halted(true);
+            }
             cycles += 4;
         }
         0x77 => {
             // LD HL A
             let addr = gb.cpu.get_hl();
             let value = gb.cpu.get_a();
-            gb.ram[addr as usize] = value;
+            gb.bus.write8(addr, value);
             cycles += 8;
         }
         0x78 => {
             // LD A B
-            load_register_8bit(gb, "a", "b");
+            load_register_8bit(gb, Reg8::A, Reg8::B);
             cycles += 4;
         }
         0x79 => {
             // LD A C
-            load_register_8bit(gb, "a", "c");
+            load_register_8bit(gb, Reg8::A, Reg8::C);
             cycles += 4;
         }
         0x7A => {
             // LD A D
-            load_register_8bit(gb, "a", "d");
+            load_register_8bit(gb, Reg8::A, Reg8::D);
             cycles += 4;
         }
         0x7B => {
             // LD A E
-            load_register_8bit(gb, "a", "e");
+            load_register_8bit(gb, Reg8::A, Reg8::E);
             cycles += 4;
         }
         0x7C => {
             // LD A H
-            load_register_8bit(gb, "a", "h");
+            load_register_8bit(gb, Reg8::A, Reg8::H);
             cycles += 4;
         }
         0x7D => {
             // LD A L
-            load_register_8bit(gb, "a", "l");
+            load_register_8bit(gb, Reg8::A, Reg8::L);
             cycles += 4;
         }
         0x7E => {
             // LD A HL
             let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            load_8bit(gb, "a", value);
+            let value = gb.bus.read8(addr);
+            load_8bit(gb, Reg8::A, value);
             cycles += 8;
         }
         0x7F => {
             // LD A A
-            load_register_8bit(gb, "a", "a");
+            load_register_8bit(gb, Reg8::A, Reg8::A);
             cycles += 4;
         }
         0x80 => {
             // ADD A B
-            add(gb, "b");
+            add(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x81 => {
             // ADD A C
-            add(gb, "c");
+            add(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x82 => {
             // ADD A D
-            add(gb, "d");
+            add(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x83 => {
             // ADD A E
-            add(gb, "e");
+            add(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x84 => {
             // ADD A H
-            add(gb, "h");
+            add(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x85 => {
             // ADD A L
-            add(gb, "l");
+            add(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x86 => {
             // ADD A HL
-            let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            add_immediate_value(gb, value);
+            add(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0x87 => {
             // ADD A A
-            add(gb, "a");
+            add(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0x88 => {
             // ADC A B
-            add_with_carry(gb, "b");
+            add_with_carry(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x89 => {
             // ADC A C
-            add_with_carry(gb, "c");
+            add_with_carry(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x8A => {
             // ADC A D
-            add_with_carry(gb, "d");
+            add_with_carry(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x8B => {
             // ADC A E
-            add_with_carry(gb, "e");
+            add_with_carry(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x8C => {
             // ADC A H
-            add_with_carry(gb, "h");
+            add_with_carry(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x8D => {
             // ADC A L
-            add_with_carry(gb, "l");
+            add_with_carry(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x8E => {
             // ADC A HL
-            let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            add_with_carry_immediate_value(gb, value);
+            add_with_carry(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0x8F => {
             // ADC A A
-            add_with_carry(gb, "a");
+            add_with_carry(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0x90 => {
             // SUB B
-            sub(gb, "b");
+            sub(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x91 => {
             // SUB C
-            sub(gb, "c");
+            sub(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x92 => {
             // SUB D
-            sub(gb, "d");
+            sub(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x93 => {
             // SUB E
-            sub(gb, "e");
+            sub(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x94 => {
             // SUB H
-            sub(gb, "h");
+            sub(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x95 => {
             // SUB L
-            sub(gb, "l");
+            sub(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x96 => {
             // SUB HL
-            let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            sub_immediate_value(gb, value);
+            sub(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0x97 => {
             // SUB A
-            sub(gb, "a");
+            sub(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0x98 => {
             // SBC A B
-            sub_with_carry(gb, "b");
+            sub_with_carry(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0x99 => {
             // SBC A C
-            sub_with_carry(gb, "c");
+            sub_with_carry(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0x9A => {
             // SBC A D
-            sub_with_carry(gb, "d");
+            sub_with_carry(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0x9B => {
             // SBC A E
-            sub_with_carry(gb, "e");
+            sub_with_carry(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0x9C => {
             // SBC A H
-            sub_with_carry(gb, "h");
+            sub_with_carry(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0x9D => {
             // SBC A L
-            sub_with_carry(gb, "l");
+            sub_with_carry(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0x9E => {
             // SBC A HL
-            let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            sub_with_carry_immediate_value(gb, value);
+            sub_with_carry(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0x9F => {
             // SBC A A
-            sub_with_carry(gb, "a");
+            sub_with_carry(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0xA0 => {
             // AND B
-            and(gb, "b");
+            and(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0xA1 => {
             // AND C
-            and(gb, "c");
+            and(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0xA2 => {
             // AND D
-            and(gb, "d");
+            and(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0xA3 => {
             // AND E
-            and(gb, "e");
+            and(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0xA4 => {
             // AND H
-            and(gb, "h");
+            and(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0xA5 => {
             // AND L
-            and(gb, "l");
+            and(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0xA6 => {
             // AND HL
-            and(gb, "h");
-            and(gb, "l");
+            and(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0xA7 => {
             // AND A
-            and(gb, "a");
+            and(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0xA8 => {
             // XOR B
+            xor(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0xA9 => {
             // XOR C
+            xor(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0xAA => {
             // XOR D
+            xor(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0xAB => {
             // XOR E
+            xor(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0xAC => {
             // XOR H
-            xor(gb, "h");
+            xor(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0xAD => {
             // XOR L
-            xor(gb, "l");
+            xor(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0xAE => {
             // XOR HL
-            xor(gb, "h");
-            xor(gb, "l");
+            xor(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0xAF => {
             // XOR A
-            xor(gb, "a");
+            xor(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0xB0 => {
             // OR B
-            or(gb, "b");
+            or(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0xB1 => {
             // OR C
-            or(gb, "c");
+            or(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0xB2 => {
             // OR D
-            or(gb, "d");
+            or(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0xB3 => {
             // OR E
-            or(gb, "e");
+            or(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0xB4 => {
             // OR H
-            or(gb, "h");
+            or(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0xB5 => {
             // OR L
-            or(gb, "l");
+            or(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0xB6 => {
             // OR HL
-            or(gb, "h");
-            or(gb, "l");
+            or(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0xB7 => {
             // OR A
-            or(gb, "a");
+            or(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0xB8 => {
             // CP B
-            compare(gb, "b");
+            compare(gb, Operand::Register(Reg8::B));
             cycles += 4;
         }
         0xB9 => {
             // CP C
-            compare(gb, "c");
+            compare(gb, Operand::Register(Reg8::C));
             cycles += 4;
         }
         0xBA => {
             // CP D
-            compare(gb, "d");
+            compare(gb, Operand::Register(Reg8::D));
             cycles += 4;
         }
         0xBB => {
             // CP E
-            compare(gb, "e");
+            compare(gb, Operand::Register(Reg8::E));
             cycles += 4;
         }
         0xBC => {
             // CP H
-            compare(gb, "h");
+            compare(gb, Operand::Register(Reg8::H));
             cycles += 4;
         }
         0xBD => {
             // CP L
-            compare(gb, "l");
+            compare(gb, Operand::Register(Reg8::L));
             cycles += 4;
         }
         0xBE => {
             // CP HL
-            let addr = gb.cpu.get_hl();
-            let value = gb.ram[addr as usize];
-            compare_immediate_value(gb, value);
+            compare(gb, Operand::IndirectHL);
             cycles += 8;
         }
         0xBF => {
             // CP A
-            compare(gb, "a");
+            compare(gb, Operand::Register(Reg8::A));
             cycles += 4;
         }
         0xC0 => {
             // RET NZ
-            // if branch
-            cycles += 20;
-            // if not branch
-            cycles += 8;
+            if !gb.cpu.get_z_flag() {
+                ret(gb);
+                cycles += 20;
+            } else {
+                cycles += 8;
+            }
         }
         0xC1 => {
             // POP BC
-            pop(gb, "bc");
+            pop(gb, Reg16::BC);
             cycles += 12;
         }
         0xC2 => {
             // JP NZ a16
+            let addr = gameboy::read_short(gb);
             if !gb.cpu.get_z_flag() {
-                jump(gb, gb.cpu.get_pc());
+                jump(gb, addr);
                 cycles += 16;
             } else {
                 cycles += 12;
@@ -1074,45 +1092,54 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0xC3 => {
             // JP a16
-            jump(gb, gb.cpu.get_pc());
+            let addr = gameboy::read_short(gb);
+            jump(gb, addr);
             cycles += 16;
         }
         0xC4 => {
             // CALL NZ a16
-            // if branch
-            cycles += 24;
-            // if not branch
-            cycles += 12;
+            let addr = gameboy::read_short(gb);
+            if !gb.cpu.get_z_flag() {
+                call(gb, addr);
+                cycles += 24;
+            } else {
+                cycles += 12;
+            }
         }
         0xC5 => {
             // PUSH BC
-            push(gb, "bc");
+            push(gb, Reg16::BC);
             cycles += 16;
         }
         0xC6 => {
             // ADD A n8
-            add_immediate(gb);
+            add(gb, Operand::Immediate);
             cycles += 8;
         }
         0xC7 => {
             // RST 00H
+            call(gb, 0x00);
             cycles += 16;
         }
         0xC8 => {
             // RET Z
-            // if branch
-            cycles += 20;
-            // if not branch
-            cycles += 8;
+            if gb.cpu.get_z_flag() {
+                ret(gb);
+                cycles += 20;
+            } else {
+                cycles += 8;
+            }
         }
         0xC9 => {
             // RET
+            ret(gb);
             cycles += 16;
         }
         0xCA => {
             // JP Z a16
+            let addr = gameboy::read_short(gb);
             if gb.cpu.get_z_flag() {
-                jump(gb, gb.cpu.get_pc());
+                jump(gb, addr);
                 cycles += 16;
             } else {
                 cycles += 12;
@@ -1120,46 +1147,55 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0xCB => {
             // PREFIX
-            cycles += 4;
+            // the prefixed instruction's cycles include the prefix
             let opcode = gameboy::read_byte(gb);
             cycles += execute_prefixed(gb, opcode);
         }
         0xCC => {
             // CALL Z a16
-            // if branch
-            cycles += 24;
-            // if not branch
-            cycles += 12;
+            let addr = gameboy::read_short(gb);
+            if gb.cpu.get_z_flag() {
+                call(gb, addr);
+                cycles += 24;
+            } else {
+                cycles += 12;
+            }
         }
         0xCD => {
             // CALL a16
+            let addr = gameboy::read_short(gb);
+            call(gb, addr);
             cycles += 24;
         }
         0xCE => {
             // ADC A n8
-            add_with_carry_immediate(gb);
+            add_with_carry(gb, Operand::Immediate);
             cycles += 8;
         }
         0xCF => {
             // RST 08H
+            call(gb, 0x08);
             cycles += 16;
         }
         0xD0 => {
             // RET NC
-            // if branch
-            cycles += 20;
-            // if not branch
-            cycles += 8;
+            if !gb.cpu.get_c_flag() {
+                ret(gb);
+                cycles += 20;
+            } else {
+                cycles += 8;
+            }
         }
         0xD1 => {
             // POP DE
-            pop(gb, "de");
+            pop(gb, Reg16::DE);
             cycles += 12;
         }
         0xD2 => {
             // JP NC a16
+            let addr = gameboy::read_short(gb);
             if !gb.cpu.get_c_flag() {
-                jump(gb, gb.cpu.get_pc());
+                jump(gb, addr);
                 cycles += 16;
             } else {
                 cycles += 12;
@@ -1168,44 +1204,55 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         0xD3 => {
             // ILLEGAL_D3
             panic!("Illegal opcode: {:#04X}", opcode);
-            cycles += 4;
         }
         0xD4 => {
             // CALL NC a16
-            // if branch
-            cycles += 24;
-            // if not branch
-            cycles += 12;
+            let addr = gameboy::read_short(gb);
+            if !gb.cpu.get_c_flag() {
+                call(gb, addr);
+                cycles += 24;
+            } else {
+                cycles += 12;
+            }
         }
         0xD5 => {
             // PUSH DE
-            push(gb, "de");
+            push(gb, Reg16::DE);
             cycles += 16;
         }
         0xD6 => {
             // SUB n8
-            sub_immediate(gb);
+            sub(gb, Operand::Immediate);
             cycles += 8;
         }
         0xD7 => {
             // RST 10H
+            call(gb, 0x10);
             cycles += 16;
         }
         0xD8 => {
             // RET C
-            // if branch
-            cycles += 20;
-            // if not branch
-            cycles += 8;
+            if gb.cpu.get_c_flag() {
+                ret(gb);
+                cycles += 20;
+            } else {
+                cycles += 8;
+            }
         }
         0xD9 => {
             // RETI
+            // Return from subroutine and enable interrupts straight away,
+            // without the one instruction delay of EI.
+            let addr = pop_value(gb);
+            gb.cpu.set_pc(addr);
+            gb.cpu.set_ime(true);
             cycles += 16;
         }
         0xDA => {
             // JP C a16
+            let addr = gameboy::read_short(gb);
             if gb.cpu.get_c_flag() {
-                jump(gb, gb.cpu.get_pc());
+                jump(gb, addr);
                 cycles += 16;
             } else {
                 cycles += 12;
@@ -1213,81 +1260,85 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
         0xDB => {
             // ILLEGAL_DB
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xDC => {
             // CALL C a16
-            // if branch
-            cycles += 24;
-            // if not branch
-            cycles += 12;
+            let addr = gameboy::read_short(gb);
+            if gb.cpu.get_c_flag() {
+                call(gb, addr);
+                cycles += 24;
+            } else {
+                cycles += 12;
+            }
         }
         0xDD => {
             // ILLEGAL_DD
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xDE => {
             // SBC A n8
-            sub_with_carry_immediate(gb);
+            sub_with_carry(gb, Operand::Immediate);
             cycles += 8;
         }
         0xDF => {
             // RST 18H
+            call(gb, 0x18);
             cycles += 16;
         }
         0xE0 => {
             // LDH a8 A
             // Load to the address specified by the 8-bit immediate operand + 0xFF00, data from the 8-bit A register.
-            let addr = 0xFF00 + gameboy::read_byte(gb) as u16;
+            let addr = 0xFF00 + gameboy::read_byte(gb);
             let data = gb.cpu.get_a();
-            gb.ram[addr as usize] = data;
+            gb.bus.write8(addr, data);
             cycles += 12;
         }
         0xE1 => {
             // POP HL
-            pop(gb, "hl");
+            pop(gb, Reg16::HL);
             cycles += 12;
         }
         0xE2 => {
             // LD C A
-            // Load to the address specified by the 8-bit C register, data from the 8-bit A register.
-            let addr = gb.cpu.get_c();
+            // Load to the address specified by the 8-bit C register + 0xFF00, data from the 8-bit A register.
+            let addr = 0xFF00 + gb.cpu.get_c() as u16;
             let data = gb.cpu.get_a();
-            gb.ram[addr as usize] = data;
+            gb.bus.write8(addr, data);
             cycles += 8;
         }
         0xE3 => {
             // ILLEGAL_E3
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xE4 => {
             // ILLEGAL_E4
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xE5 => {
             // PUSH HL
-            push(gb, "hl");
+            push(gb, Reg16::HL);
             cycles += 16;
         }
         0xE6 => {
             // AND n8
-            and_immediate(gb);
+            and(gb, Operand::Immediate);
             cycles += 8;
         }
         0xE7 => {
             // RST 20H
+            call(gb, 0x20);
             cycles += 16;
         }
         0xE8 => {
             // ADD SP e8
+            let result = add_sp_signed(gb);
+            gb.cpu.set_sp(result);
             cycles += 16;
         }
         0xE9 => {
             // JP HL
+            jump(gb, gb.cpu.get_hl());
             cycles += 4;
         }
         0xEA => {
@@ -1295,113 +1346,121 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
             // Store the contents of register A in the internal RAM or register specified by the 16-bit immediate operand a16.
             // Load to the absolute address specified by the 16-bit operand nn, data from the 8-bit A register.
             let a16 = gameboy::read_short(gb);
-            gb.ram[a16 as usize] = gb.cpu.get_a();
+            gb.bus.write8(a16, gb.cpu.get_a());
             cycles += 16;
         }
         0xEB => {
             // ILLEGAL_EB
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xEC => {
             // ILLEGAL_EC
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xED => {
             // ILLEGAL_ED
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xEE => {
             // XOR n8
-            xor_immediate(gb);
+            xor(gb, Operand::Immediate);
             cycles += 8;
         }
         0xEF => {
             // RST 28H
+            call(gb, 0x28);
             cycles += 16;
         }
         0xF0 => {
             // LDH A a8
             // Load to the 8-bit A register, data from the address specified by the 8-bit immediate operand + 0xFF00.
-            let addr = 0xFF00 + gameboy::read_byte(gb) as u16;
-            let data = gb.ram[addr as usize];
+            let addr = 0xFF00 + gameboy::read_byte(gb);
+            let data = gb.bus.read8(addr);
             gb.cpu.set_a(data);
             cycles += 12;
         }
         0xF1 => {
-            // POP AF
-            pop(gb, "af");
+            // POP AF, the low 4 bits of F don't exist
+            let value = pop_value(gb);
+            gb.cpu.set_af(value & 0xFFF0);
             cycles += 12;
         }
         0xF2 => {
             // LD A C
-            // Load to the 8-bit A register, data from the address specified by the 8-bit C register.
-            let addr = gb.cpu.get_c();
-            let data = gb.ram[addr as usize];
+            // Load to the 8-bit A register, data from the address specified by the 8-bit C register + 0xFF00.
+            let addr = 0xFF00 + gb.cpu.get_c() as u16;
+            let data = gb.bus.read8(addr);
             gb.cpu.set_a(data);
             cycles += 8;
         }
         0xF3 => {
             // DI
+            // Also cancels an EI that has not taken effect yet.
+            gb.cpu.set_ime(false);
+            gb.cpu.set_ei_delay(0);
             cycles += 4;
         }
         0xF4 => {
             // ILLEGAL_F4
-            cycles += 4;
+            panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xF5 => {
             // PUSH AF
-            push(gb, "af");
+            push(gb, Reg16::AF);
             cycles += 16;
         }
         0xF6 => {
             // OR n8
-            or_immediate(gb);
+            or(gb, Operand::Immediate);
             cycles += 8;
         }
         0xF7 => {
             // RST 30H
+            call(gb, 0x30);
             cycles += 16;
         }
         0xF8 => {
             // LD HL SP e8
+            let result = add_sp_signed(gb);
+            gb.cpu.set_hl(result);
             cycles += 12;
         }
         0xF9 => {
             // LD SP HL
-            load_register_16bit(gb, "sp", "hl");
+            load_register_16bit(gb, Reg16::SP, Reg16::HL);
             cycles += 8;
         }
         0xFA => {
             // LD A a16
             // Load to the 8-bit A register, data from the absolute address specified by the 16-bit operand nn.
             let a16 = gameboy::read_short(gb);
-            gb.cpu.set_a(gb.ram[a16 as usize]);
+            gb.cpu.set_a(gb.bus.read8(a16));
             cycles += 16;
         }
         0xFB => {
             // EI
+            // IME is only set after the instruction following EI, see step_cpu
+            if !gb.cpu.get_ime() {
+                gb.cpu.set_ei_delay(2);
+            }
             cycles += 4;
         }
         0xFC => {
             // ILLEGAL_FC
-            cycles += 4;
             panic!("Illegal opcode: {:#04X}", opcode);
         }
         0xFD => {
             // ILLEGAL_FD
             panic!("Illegal opcode: {:#04X}", opcode);
-            cycles += 4;
         }
         0xFE => {
             // CP n8
-            compare_immediate(gb);
+            compare(gb, Operand::Immediate);
             cycles += 8;
         }
         0xFF => {
             // RST 38H
+            call(gb, 0x38);
             cycles += 16;
         }
         _ => {
@@ -1409,7 +1468,7 @@ pub fn execute_instruction(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
         }
     }
 
-    return cycles;
+    cycles
 }
 
 fn execute_prefixed(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
@@ -1417,1282 +1476,1679 @@ fn execute_prefixed(gb: &mut gameboy::Gameboy, opcode: u16) -> i64 {
     match opcode {
         0x00 => {
             // RLC B
+            let value = rotate_left_circular(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x01 => {
             // RLC C
+            let value = rotate_left_circular(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x02 => {
             // RLC D
+            let value = rotate_left_circular(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x03 => {
             // RLC E
+            let value = rotate_left_circular(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x04 => {
             // RLC H
+            let value = rotate_left_circular(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x05 => {
             // RLC L
+            let value = rotate_left_circular(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x06 => {
             // RLC HL
+            let addr = gb.cpu.get_hl();
+            let value = rotate_left_circular(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x07 => {
             // RLC A
+            let value = rotate_left_circular(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x08 => {
             // RRC B
+            let value = rotate_right_circular(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x09 => {
             // RRC C
+            let value = rotate_right_circular(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x0A => {
             // RRC D
+            let value = rotate_right_circular(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x0B => {
             // RRC E
+            let value = rotate_right_circular(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x0C => {
             // RRC H
+            let value = rotate_right_circular(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x0D => {
             // RRC L
+            let value = rotate_right_circular(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x0E => {
             // RRC HL
+            let addr = gb.cpu.get_hl();
+            let value = rotate_right_circular(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x0F => {
             // RRC A
+            let value = rotate_right_circular(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x10 => {
             // RL B
+            let value = rotate_left(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x11 => {
             // RL C
+            let value = rotate_left(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x12 => {
             // RL D
+            let value = rotate_left(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x13 => {
             // RL E
+            let value = rotate_left(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x14 => {
             // RL H
+            let value = rotate_left(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x15 => {
             // RL L
+            let value = rotate_left(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x16 => {
             // RL HL
+            let addr = gb.cpu.get_hl();
+            let value = rotate_left(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x17 => {
             // RL A
+            let value = rotate_left(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x18 => {
             // RR B
+            let value = rotate_right(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x19 => {
             // RR C
+            let value = rotate_right(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x1A => {
             // RR D
+            let value = rotate_right(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x1B => {
             // RR E
+            let value = rotate_right(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x1C => {
             // RR H
+            let value = rotate_right(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x1D => {
             // RR L
+            let value = rotate_right(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x1E => {
             // RR HL
+            let addr = gb.cpu.get_hl();
+            let value = rotate_right(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x1F => {
             // RR A
+            let value = rotate_right(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x20 => {
             // SLA B
+            let value = shift_left_arithmetic(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x21 => {
             // SLA C
+            let value = shift_left_arithmetic(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x22 => {
             // SLA D
+            let value = shift_left_arithmetic(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x23 => {
             // SLA E
+            let value = shift_left_arithmetic(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x24 => {
             // SLA H
+            let value = shift_left_arithmetic(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x25 => {
             // SLA L
+            let value = shift_left_arithmetic(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x26 => {
             // SLA HL
+            let addr = gb.cpu.get_hl();
+            let value = shift_left_arithmetic(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x27 => {
             // SLA A
+            let value = shift_left_arithmetic(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x28 => {
             // SRA B
+            let value = shift_right_arithmetic(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x29 => {
             // SRA C
+            let value = shift_right_arithmetic(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x2A => {
             // SRA D
+            let value = shift_right_arithmetic(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x2B => {
             // SRA E
+            let value = shift_right_arithmetic(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x2C => {
             // SRA H
+            let value = shift_right_arithmetic(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x2D => {
             // SRA L
+            let value = shift_right_arithmetic(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x2E => {
             // SRA HL
+            let addr = gb.cpu.get_hl();
+            let value = shift_right_arithmetic(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x2F => {
             // SRA A
+            let value = shift_right_arithmetic(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x30 => {
             // SWAP B
+            let value = swap(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x31 => {
             // SWAP C
+            let value = swap(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x32 => {
             // SWAP D
+            let value = swap(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x33 => {
             // SWAP E
+            let value = swap(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x34 => {
             // SWAP H
+            let value = swap(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x35 => {
             // SWAP L
+            let value = swap(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x36 => {
             // SWAP HL
+            let addr = gb.cpu.get_hl();
+            let value = swap(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x37 => {
             // SWAP A
+            let value = swap(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x38 => {
             // SRL B
+            let value = shift_right_logical(gb, gb.cpu.get_b());
+            gb.cpu.set_b(value);
             cycles += 8;
         }
         0x39 => {
             // SRL C
+            let value = shift_right_logical(gb, gb.cpu.get_c());
+            gb.cpu.set_c(value);
             cycles += 8;
         }
         0x3A => {
             // SRL D
+            let value = shift_right_logical(gb, gb.cpu.get_d());
+            gb.cpu.set_d(value);
             cycles += 8;
         }
         0x3B => {
             // SRL E
+            let value = shift_right_logical(gb, gb.cpu.get_e());
+            gb.cpu.set_e(value);
             cycles += 8;
         }
         0x3C => {
             // SRL H
+            let value = shift_right_logical(gb, gb.cpu.get_h());
+            gb.cpu.set_h(value);
             cycles += 8;
         }
         0x3D => {
             // SRL L
+            let value = shift_right_logical(gb, gb.cpu.get_l());
+            gb.cpu.set_l(value);
             cycles += 8;
         }
         0x3E => {
             // SRL HL
+            let addr = gb.cpu.get_hl();
+            let value = shift_right_logical(gb, gb.bus.read8(addr));
+            gb.bus.write8(addr, value);
             cycles += 16;
         }
         0x3F => {
             // SRL A
+            let value = shift_right_logical(gb, gb.cpu.get_a());
+            gb.cpu.set_a(value);
             cycles += 8;
         }
         0x40 => {
             // BIT 0 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_b()));
+            test_bit(gb, 0, gb.cpu.get_b());
             cycles += 8;
         }
         0x41 => {
             // BIT 0 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_c()));
+            test_bit(gb, 0, gb.cpu.get_c());
             cycles += 8;
         }
         0x42 => {
             // BIT 0 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_d()));
+            test_bit(gb, 0, gb.cpu.get_d());
             cycles += 8;
         }
         0x43 => {
             // BIT 0 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_e()));
+            test_bit(gb, 0, gb.cpu.get_e());
             cycles += 8;
         }
         0x44 => {
             // BIT 0 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_h()));
+            test_bit(gb, 0, gb.cpu.get_h());
             cycles += 8;
         }
         0x45 => {
             // BIT 0 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_l()));
+            test_bit(gb, 0, gb.cpu.get_l());
             cycles += 8;
         }
         0x46 => {
             // BIT 0 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(0, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 0, gb.bus.read8(addr));
             cycles += 12;
         }
         0x47 => {
             // BIT 0 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(0, gb.cpu.get_a()));
+            test_bit(gb, 0, gb.cpu.get_a());
             cycles += 8;
         }
         0x48 => {
             // BIT 1 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_b()));
+            test_bit(gb, 1, gb.cpu.get_b());
             cycles += 8;
         }
         0x49 => {
             // BIT 1 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_c()));
+            test_bit(gb, 1, gb.cpu.get_c());
             cycles += 8;
         }
         0x4A => {
             // BIT 1 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_d()));
+            test_bit(gb, 1, gb.cpu.get_d());
             cycles += 8;
         }
         0x4B => {
             // BIT 1 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_e()));
+            test_bit(gb, 1, gb.cpu.get_e());
             cycles += 8;
         }
         0x4C => {
             // BIT 1 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_h()));
+            test_bit(gb, 1, gb.cpu.get_h());
             cycles += 8;
         }
         0x4D => {
             // BIT 1 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_l()));
+            test_bit(gb, 1, gb.cpu.get_l());
             cycles += 8;
         }
         0x4E => {
             // BIT 1 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(1, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 1, gb.bus.read8(addr));
             cycles += 12;
         }
         0x4F => {
             // BIT 1 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(1, gb.cpu.get_a()));
+            test_bit(gb, 1, gb.cpu.get_a());
             cycles += 8;
         }
         0x50 => {
             // BIT 2 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_b()));
+            test_bit(gb, 2, gb.cpu.get_b());
             cycles += 8;
         }
         0x51 => {
             // BIT 2 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_c()));
+            test_bit(gb, 2, gb.cpu.get_c());
             cycles += 8;
         }
         0x52 => {
             // BIT 2 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_d()));
+            test_bit(gb, 2, gb.cpu.get_d());
             cycles += 8;
         }
         0x53 => {
             // BIT 2 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_e()));
+            test_bit(gb, 2, gb.cpu.get_e());
             cycles += 8;
         }
         0x54 => {
             // BIT 2 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_h()));
+            test_bit(gb, 2, gb.cpu.get_h());
             cycles += 8;
         }
         0x55 => {
             // BIT 2 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_l()));
+            test_bit(gb, 2, gb.cpu.get_l());
             cycles += 8;
         }
         0x56 => {
             // BIT 2 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(2, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 2, gb.bus.read8(addr));
             cycles += 12;
         }
         0x57 => {
             // BIT 2 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(2, gb.cpu.get_a()));
+            test_bit(gb, 2, gb.cpu.get_a());
             cycles += 8;
         }
         0x58 => {
             // BIT 3 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_b()));
+            test_bit(gb, 3, gb.cpu.get_b());
             cycles += 8;
         }
         0x59 => {
             // BIT 3 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_c()));
+            test_bit(gb, 3, gb.cpu.get_c());
             cycles += 8;
         }
         0x5A => {
             // BIT 3 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_d()));
+            test_bit(gb, 3, gb.cpu.get_d());
             cycles += 8;
         }
         0x5B => {
             // BIT 3 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_e()));
+            test_bit(gb, 3, gb.cpu.get_e());
             cycles += 8;
         }
         0x5C => {
             // BIT 3 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_h()));
+            test_bit(gb, 3, gb.cpu.get_h());
             cycles += 8;
         }
         0x5D => {
             // BIT 3 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_l()));
+            test_bit(gb, 3, gb.cpu.get_l());
             cycles += 8;
         }
         0x5E => {
             // BIT 3 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(3, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 3, gb.bus.read8(addr));
             cycles += 12;
         }
         0x5F => {
             // BIT 3 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(3, gb.cpu.get_a()));
+            test_bit(gb, 3, gb.cpu.get_a());
             cycles += 8;
         }
         0x60 => {
             // BIT 4 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_b()));
+            test_bit(gb, 4, gb.cpu.get_b());
             cycles += 8;
         }
         0x61 => {
             // BIT 4 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_c()));
+            test_bit(gb, 4, gb.cpu.get_c());
             cycles += 8;
         }
         0x62 => {
             // BIT 4 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_d()));
+            test_bit(gb, 4, gb.cpu.get_d());
             cycles += 8;
         }
         0x63 => {
             // BIT 4 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_e()));
+            test_bit(gb, 4, gb.cpu.get_e());
             cycles += 8;
         }
         0x64 => {
             // BIT 4 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_h()));
+            test_bit(gb, 4, gb.cpu.get_h());
             cycles += 8;
         }
         0x65 => {
             // BIT 4 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_l()));
+            test_bit(gb, 4, gb.cpu.get_l());
             cycles += 8;
         }
         0x66 => {
             // BIT 4 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(4, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 4, gb.bus.read8(addr));
             cycles += 12;
         }
         0x67 => {
             // BIT 4 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(4, gb.cpu.get_a()));
+            test_bit(gb, 4, gb.cpu.get_a());
             cycles += 8;
         }
         0x68 => {
             // BIT 5 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_b()));
+            test_bit(gb, 5, gb.cpu.get_b());
             cycles += 8;
         }
         0x69 => {
             // BIT 5 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_c()));
+            test_bit(gb, 5, gb.cpu.get_c());
             cycles += 8;
         }
         0x6A => {
             // BIT 5 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_d()));
+            test_bit(gb, 5, gb.cpu.get_d());
             cycles += 8;
         }
         0x6B => {
             // BIT 5 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_e()));
+            test_bit(gb, 5, gb.cpu.get_e());
             cycles += 8;
         }
         0x6C => {
             // BIT 5 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_h()));
+            test_bit(gb, 5, gb.cpu.get_h());
             cycles += 8;
         }
         0x6D => {
             // BIT 5 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_l()));
+            test_bit(gb, 5, gb.cpu.get_l());
             cycles += 8;
         }
         0x6E => {
             // BIT 5 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(5, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 5, gb.bus.read8(addr));
             cycles += 12;
         }
         0x6F => {
             // BIT 5 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(5, gb.cpu.get_a()));
+            test_bit(gb, 5, gb.cpu.get_a());
             cycles += 8;
         }
         0x70 => {
             // BIT 6 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_b()));
+            test_bit(gb, 6, gb.cpu.get_b());
             cycles += 8;
         }
         0x71 => {
             // BIT 6 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_c()));
+            test_bit(gb, 6, gb.cpu.get_c());
             cycles += 8;
         }
         0x72 => {
             // BIT 6 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_d()));
+            test_bit(gb, 6, gb.cpu.get_d());
             cycles += 8;
         }
         0x73 => {
             // BIT 6 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_e()));
+            test_bit(gb, 6, gb.cpu.get_e());
             cycles += 8;
         }
         0x74 => {
             // BIT 6 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_h()));
+            test_bit(gb, 6, gb.cpu.get_h());
             cycles += 8;
         }
         0x75 => {
             // BIT 6 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_l()));
+            test_bit(gb, 6, gb.cpu.get_l());
             cycles += 8;
         }
         0x76 => {
             // BIT 6 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(6, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 6, gb.bus.read8(addr));
             cycles += 12;
         }
         0x77 => {
             // BIT 6 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(6, gb.cpu.get_a()));
+            test_bit(gb, 6, gb.cpu.get_a());
             cycles += 8;
         }
         0x78 => {
             // BIT 7 B
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_b()));
+            test_bit(gb, 7, gb.cpu.get_b());
             cycles += 8;
         }
         0x79 => {
             // BIT 7 C
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_c()));
+            test_bit(gb, 7, gb.cpu.get_c());
             cycles += 8;
         }
         0x7A => {
             // BIT 7 D
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_d()));
+            test_bit(gb, 7, gb.cpu.get_d());
             cycles += 8;
         }
         0x7B => {
             // BIT 7 E
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_e()));
+            test_bit(gb, 7, gb.cpu.get_e());
             cycles += 8;
         }
         0x7C => {
             // BIT 7 H
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_h()));
+            test_bit(gb, 7, gb.cpu.get_h());
             cycles += 8;
         }
         0x7D => {
             // BIT 7 L
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_l()));
+            test_bit(gb, 7, gb.cpu.get_l());
             cycles += 8;
         }
         0x7E => {
             // BIT 7 HL
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_16bit(7, gb.cpu.get_hl()));
+            let addr = gb.cpu.get_hl();
+            test_bit(gb, 7, gb.bus.read8(addr));
             cycles += 12;
         }
         0x7F => {
             // BIT 7 A
-            gb.cpu
-                .set_z_flag(!get_bit_at_position_8bit(7, gb.cpu.get_a()));
+            test_bit(gb, 7, gb.cpu.get_a());
             cycles += 8;
         }
         0x80 => {
             // RES 0 B
+            gb.cpu.set_b(reset_bit(0, gb.cpu.get_b()));
             cycles += 8;
         }
         0x81 => {
             // RES 0 C
+            gb.cpu.set_c(reset_bit(0, gb.cpu.get_c()));
             cycles += 8;
         }
         0x82 => {
             // RES 0 D
+            gb.cpu.set_d(reset_bit(0, gb.cpu.get_d()));
             cycles += 8;
         }
         0x83 => {
             // RES 0 E
+            gb.cpu.set_e(reset_bit(0, gb.cpu.get_e()));
             cycles += 8;
         }
         0x84 => {
             // RES 0 H
+            gb.cpu.set_h(reset_bit(0, gb.cpu.get_h()));
             cycles += 8;
         }
         0x85 => {
             // RES 0 L
+            gb.cpu.set_l(reset_bit(0, gb.cpu.get_l()));
             cycles += 8;
         }
         0x86 => {
             // RES 0 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(0, gb.bus.read8(addr)));
             cycles += 16;
         }
         0x87 => {
             // RES 0 A
+            gb.cpu.set_a(reset_bit(0, gb.cpu.get_a()));
             cycles += 8;
         }
         0x88 => {
             // RES 1 B
+            gb.cpu.set_b(reset_bit(1, gb.cpu.get_b()));
             cycles += 8;
         }
         0x89 => {
             // RES 1 C
+            gb.cpu.set_c(reset_bit(1, gb.cpu.get_c()));
             cycles += 8;
         }
         0x8A => {
             // RES 1 D
+            gb.cpu.set_d(reset_bit(1, gb.cpu.get_d()));
             cycles += 8;
         }
         0x8B => {
             // RES 1 E
+            gb.cpu.set_e(reset_bit(1, gb.cpu.get_e()));
             cycles += 8;
         }
         0x8C => {
             // RES 1 H
+            gb.cpu.set_h(reset_bit(1, gb.cpu.get_h()));
             cycles += 8;
         }
         0x8D => {
             // RES 1 L
+            gb.cpu.set_l(reset_bit(1, gb.cpu.get_l()));
             cycles += 8;
         }
         0x8E => {
             // RES 1 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(1, gb.bus.read8(addr)));
             cycles += 16;
         }
         0x8F => {
             // RES 1 A
+            gb.cpu.set_a(reset_bit(1, gb.cpu.get_a()));
             cycles += 8;
         }
         0x90 => {
             // RES 2 B
+            gb.cpu.set_b(reset_bit(2, gb.cpu.get_b()));
             cycles += 8;
         }
         0x91 => {
             // RES 2 C
+            gb.cpu.set_c(reset_bit(2, gb.cpu.get_c()));
             cycles += 8;
         }
         0x92 => {
             // RES 2 D
+            gb.cpu.set_d(reset_bit(2, gb.cpu.get_d()));
             cycles += 8;
         }
         0x93 => {
             // RES 2 E
+            gb.cpu.set_e(reset_bit(2, gb.cpu.get_e()));
             cycles += 8;
         }
         0x94 => {
             // RES 2 H
+            gb.cpu.set_h(reset_bit(2, gb.cpu.get_h()));
             cycles += 8;
         }
         0x95 => {
             // RES 2 L
+            gb.cpu.set_l(reset_bit(2, gb.cpu.get_l()));
             cycles += 8;
         }
         0x96 => {
             // RES 2 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(2, gb.bus.read8(addr)));
             cycles += 16;
         }
         0x97 => {
             // RES 2 A
+            gb.cpu.set_a(reset_bit(2, gb.cpu.get_a()));
             cycles += 8;
         }
         0x98 => {
             // RES 3 B
+            gb.cpu.set_b(reset_bit(3, gb.cpu.get_b()));
             cycles += 8;
         }
         0x99 => {
             // RES 3 C
+            gb.cpu.set_c(reset_bit(3, gb.cpu.get_c()));
             cycles += 8;
         }
         0x9A => {
             // RES 3 D
+            gb.cpu.set_d(reset_bit(3, gb.cpu.get_d()));
             cycles += 8;
         }
         0x9B => {
             // RES 3 E
+            gb.cpu.set_e(reset_bit(3, gb.cpu.get_e()));
             cycles += 8;
         }
         0x9C => {
             // RES 3 H
+            gb.cpu.set_h(reset_bit(3, gb.cpu.get_h()));
             cycles += 8;
         }
         0x9D => {
             // RES 3 L
+            gb.cpu.set_l(reset_bit(3, gb.cpu.get_l()));
             cycles += 8;
         }
         0x9E => {
             // RES 3 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(3, gb.bus.read8(addr)));
             cycles += 16;
         }
         0x9F => {
             // RES 3 A
+            gb.cpu.set_a(reset_bit(3, gb.cpu.get_a()));
             cycles += 8;
         }
         0xA0 => {
             // RES 4 B
+            gb.cpu.set_b(reset_bit(4, gb.cpu.get_b()));
             cycles += 8;
         }
         0xA1 => {
             // RES 4 C
+            gb.cpu.set_c(reset_bit(4, gb.cpu.get_c()));
             cycles += 8;
         }
         0xA2 => {
             // RES 4 D
+            gb.cpu.set_d(reset_bit(4, gb.cpu.get_d()));
             cycles += 8;
         }
         0xA3 => {
             // RES 4 E
+            gb.cpu.set_e(reset_bit(4, gb.cpu.get_e()));
             cycles += 8;
         }
         0xA4 => {
             // RES 4 H
+            gb.cpu.set_h(reset_bit(4, gb.cpu.get_h()));
             cycles += 8;
         }
         0xA5 => {
             // RES 4 L
+            gb.cpu.set_l(reset_bit(4, gb.cpu.get_l()));
             cycles += 8;
         }
         0xA6 => {
             // RES 4 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(4, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xA7 => {
             // RES 4 A
+            gb.cpu.set_a(reset_bit(4, gb.cpu.get_a()));
             cycles += 8;
         }
         0xA8 => {
             // RES 5 B
+            gb.cpu.set_b(reset_bit(5, gb.cpu.get_b()));
             cycles += 8;
         }
         0xA9 => {
             // RES 5 C
+            gb.cpu.set_c(reset_bit(5, gb.cpu.get_c()));
             cycles += 8;
         }
         0xAA => {
             // RES 5 D
+            gb.cpu.set_d(reset_bit(5, gb.cpu.get_d()));
             cycles += 8;
         }
         0xAB => {
             // RES 5 E
+            gb.cpu.set_e(reset_bit(5, gb.cpu.get_e()));
             cycles += 8;
         }
         0xAC => {
             // RES 5 H
+            gb.cpu.set_h(reset_bit(5, gb.cpu.get_h()));
             cycles += 8;
         }
         0xAD => {
             // RES 5 L
+            gb.cpu.set_l(reset_bit(5, gb.cpu.get_l()));
             cycles += 8;
         }
         0xAE => {
             // RES 5 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(5, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xAF => {
             // RES 5 A
+            gb.cpu.set_a(reset_bit(5, gb.cpu.get_a()));
             cycles += 8;
         }
         0xB0 => {
             // RES 6 B
+            gb.cpu.set_b(reset_bit(6, gb.cpu.get_b()));
             cycles += 8;
         }
         0xB1 => {
             // RES 6 C
+            gb.cpu.set_c(reset_bit(6, gb.cpu.get_c()));
             cycles += 8;
         }
         0xB2 => {
             // RES 6 D
+            gb.cpu.set_d(reset_bit(6, gb.cpu.get_d()));
             cycles += 8;
         }
         0xB3 => {
             // RES 6 E
+            gb.cpu.set_e(reset_bit(6, gb.cpu.get_e()));
             cycles += 8;
         }
         0xB4 => {
             // RES 6 H
+            gb.cpu.set_h(reset_bit(6, gb.cpu.get_h()));
             cycles += 8;
         }
         0xB5 => {
             // RES 6 L
+            gb.cpu.set_l(reset_bit(6, gb.cpu.get_l()));
             cycles += 8;
         }
         0xB6 => {
             // RES 6 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(6, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xB7 => {
             // RES 6 A
+            gb.cpu.set_a(reset_bit(6, gb.cpu.get_a()));
             cycles += 8;
         }
         0xB8 => {
             // RES 7 B
+            gb.cpu.set_b(reset_bit(7, gb.cpu.get_b()));
             cycles += 8;
         }
         0xB9 => {
             // RES 7 C
+            gb.cpu.set_c(reset_bit(7, gb.cpu.get_c()));
             cycles += 8;
         }
         0xBA => {
             // RES 7 D
+            gb.cpu.set_d(reset_bit(7, gb.cpu.get_d()));
             cycles += 8;
         }
         0xBB => {
             // RES 7 E
+            gb.cpu.set_e(reset_bit(7, gb.cpu.get_e()));
             cycles += 8;
         }
         0xBC => {
             // RES 7 H
+            gb.cpu.set_h(reset_bit(7, gb.cpu.get_h()));
             cycles += 8;
         }
         0xBD => {
             // RES 7 L
+            gb.cpu.set_l(reset_bit(7, gb.cpu.get_l()));
             cycles += 8;
         }
         0xBE => {
             // RES 7 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, reset_bit(7, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xBF => {
             // RES 7 A
+            gb.cpu.set_a(reset_bit(7, gb.cpu.get_a()));
             cycles += 8;
         }
         0xC0 => {
             // SET 0 B
+            gb.cpu.set_b(set_bit(0, gb.cpu.get_b()));
             cycles += 8;
         }
         0xC1 => {
             // SET 0 C
+            gb.cpu.set_c(set_bit(0, gb.cpu.get_c()));
             cycles += 8;
         }
         0xC2 => {
             // SET 0 D
+            gb.cpu.set_d(set_bit(0, gb.cpu.get_d()));
             cycles += 8;
         }
         0xC3 => {
             // SET 0 E
+            gb.cpu.set_e(set_bit(0, gb.cpu.get_e()));
             cycles += 8;
         }
         0xC4 => {
             // SET 0 H
+            gb.cpu.set_h(set_bit(0, gb.cpu.get_h()));
             cycles += 8;
         }
         0xC5 => {
             // SET 0 L
+            gb.cpu.set_l(set_bit(0, gb.cpu.get_l()));
             cycles += 8;
         }
         0xC6 => {
             // SET 0 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(0, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xC7 => {
             // SET 0 A
+            gb.cpu.set_a(set_bit(0, gb.cpu.get_a()));
             cycles += 8;
         }
         0xC8 => {
             // SET 1 B
+            gb.cpu.set_b(set_bit(1, gb.cpu.get_b()));
             cycles += 8;
         }
         0xC9 => {
             // SET 1 C
+            gb.cpu.set_c(set_bit(1, gb.cpu.get_c()));
             cycles += 8;
         }
         0xCA => {
             // SET 1 D
+            gb.cpu.set_d(set_bit(1, gb.cpu.get_d()));
             cycles += 8;
         }
         0xCB => {
             // SET 1 E
+            gb.cpu.set_e(set_bit(1, gb.cpu.get_e()));
             cycles += 8;
         }
         0xCC => {
             // SET 1 H
+            gb.cpu.set_h(set_bit(1, gb.cpu.get_h()));
             cycles += 8;
         }
         0xCD => {
             // SET 1 L
+            gb.cpu.set_l(set_bit(1, gb.cpu.get_l()));
             cycles += 8;
         }
         0xCE => {
             // SET 1 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(1, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xCF => {
             // SET 1 A
+            gb.cpu.set_a(set_bit(1, gb.cpu.get_a()));
             cycles += 8;
         }
         0xD0 => {
             // SET 2 B
+            gb.cpu.set_b(set_bit(2, gb.cpu.get_b()));
             cycles += 8;
         }
         0xD1 => {
             // SET 2 C
+            gb.cpu.set_c(set_bit(2, gb.cpu.get_c()));
             cycles += 8;
         }
         0xD2 => {
             // SET 2 D
+            gb.cpu.set_d(set_bit(2, gb.cpu.get_d()));
             cycles += 8;
         }
         0xD3 => {
             // SET 2 E
+            gb.cpu.set_e(set_bit(2, gb.cpu.get_e()));
             cycles += 8;
         }
         0xD4 => {
             // SET 2 H
+            gb.cpu.set_h(set_bit(2, gb.cpu.get_h()));
             cycles += 8;
         }
         0xD5 => {
             // SET 2 L
+            gb.cpu.set_l(set_bit(2, gb.cpu.get_l()));
             cycles += 8;
         }
         0xD6 => {
             // SET 2 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(2, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xD7 => {
             // SET 2 A
+            gb.cpu.set_a(set_bit(2, gb.cpu.get_a()));
             cycles += 8;
         }
         0xD8 => {
             // SET 3 B
+            gb.cpu.set_b(set_bit(3, gb.cpu.get_b()));
             cycles += 8;
         }
         0xD9 => {
             // SET 3 C
+            gb.cpu.set_c(set_bit(3, gb.cpu.get_c()));
             cycles += 8;
         }
         0xDA => {
             // SET 3 D
+            gb.cpu.set_d(set_bit(3, gb.cpu.get_d()));
             cycles += 8;
         }
         0xDB => {
             // SET 3 E
+            gb.cpu.set_e(set_bit(3, gb.cpu.get_e()));
             cycles += 8;
         }
         0xDC => {
             // SET 3 H
+            gb.cpu.set_h(set_bit(3, gb.cpu.get_h()));
             cycles += 8;
         }
         0xDD => {
             // SET 3 L
+            gb.cpu.set_l(set_bit(3, gb.cpu.get_l()));
             cycles += 8;
         }
         0xDE => {
             // SET 3 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(3, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xDF => {
             // SET 3 A
+            gb.cpu.set_a(set_bit(3, gb.cpu.get_a()));
             cycles += 8;
         }
         0xE0 => {
             // SET 4 B
+            gb.cpu.set_b(set_bit(4, gb.cpu.get_b()));
             cycles += 8;
         }
         0xE1 => {
             // SET 4 C
+            gb.cpu.set_c(set_bit(4, gb.cpu.get_c()));
             cycles += 8;
         }
         0xE2 => {
             // SET 4 D
+            gb.cpu.set_d(set_bit(4, gb.cpu.get_d()));
             cycles += 8;
         }
         0xE3 => {
             // SET 4 E
+            gb.cpu.set_e(set_bit(4, gb.cpu.get_e()));
             cycles += 8;
         }
         0xE4 => {
             // SET 4 H
+            gb.cpu.set_h(set_bit(4, gb.cpu.get_h()));
             cycles += 8;
         }
         0xE5 => {
             // SET 4 L
+            gb.cpu.set_l(set_bit(4, gb.cpu.get_l()));
             cycles += 8;
         }
         0xE6 => {
             // SET 4 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(4, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xE7 => {
             // SET 4 A
+            gb.cpu.set_a(set_bit(4, gb.cpu.get_a()));
             cycles += 8;
         }
         0xE8 => {
             // SET 5 B
+            gb.cpu.set_b(set_bit(5, gb.cpu.get_b()));
             cycles += 8;
         }
         0xE9 => {
             // SET 5 C
+            gb.cpu.set_c(set_bit(5, gb.cpu.get_c()));
             cycles += 8;
         }
         0xEA => {
             // SET 5 D
+            gb.cpu.set_d(set_bit(5, gb.cpu.get_d()));
             cycles += 8;
         }
         0xEB => {
             // SET 5 E
+            gb.cpu.set_e(set_bit(5, gb.cpu.get_e()));
             cycles += 8;
         }
         0xEC => {
             // SET 5 H
+            gb.cpu.set_h(set_bit(5, gb.cpu.get_h()));
             cycles += 8;
         }
         0xED => {
             // SET 5 L
+            gb.cpu.set_l(set_bit(5, gb.cpu.get_l()));
             cycles += 8;
         }
         0xEE => {
             // SET 5 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(5, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xEF => {
             // SET 5 A
+            gb.cpu.set_a(set_bit(5, gb.cpu.get_a()));
             cycles += 8;
         }
         0xF0 => {
             // SET 6 B
+            gb.cpu.set_b(set_bit(6, gb.cpu.get_b()));
             cycles += 8;
         }
         0xF1 => {
             // SET 6 C
+            gb.cpu.set_c(set_bit(6, gb.cpu.get_c()));
             cycles += 8;
         }
         0xF2 => {
             // SET 6 D
+            gb.cpu.set_d(set_bit(6, gb.cpu.get_d()));
             cycles += 8;
         }
         0xF3 => {
             // SET 6 E
+            gb.cpu.set_e(set_bit(6, gb.cpu.get_e()));
             cycles += 8;
         }
         0xF4 => {
             // SET 6 H
+            gb.cpu.set_h(set_bit(6, gb.cpu.get_h()));
             cycles += 8;
         }
         0xF5 => {
             // SET 6 L
+            gb.cpu.set_l(set_bit(6, gb.cpu.get_l()));
             cycles += 8;
         }
         0xF6 => {
             // SET 6 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(6, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xF7 => {
             // SET 6 A
+            gb.cpu.set_a(set_bit(6, gb.cpu.get_a()));
             cycles += 8;
         }
         0xF8 => {
             // SET 7 B
+            gb.cpu.set_b(set_bit(7, gb.cpu.get_b()));
             cycles += 8;
         }
         0xF9 => {
             // SET 7 C
+            gb.cpu.set_c(set_bit(7, gb.cpu.get_c()));
             cycles += 8;
         }
         0xFA => {
             // SET 7 D
+            gb.cpu.set_d(set_bit(7, gb.cpu.get_d()));
             cycles += 8;
         }
         0xFB => {
             // SET 7 E
+            gb.cpu.set_e(set_bit(7, gb.cpu.get_e()));
             cycles += 8;
         }
         0xFC => {
             // SET 7 H
+            gb.cpu.set_h(set_bit(7, gb.cpu.get_h()));
             cycles += 8;
         }
         0xFD => {
             // SET 7 L
+            gb.cpu.set_l(set_bit(7, gb.cpu.get_l()));
             cycles += 8;
         }
         0xFE => {
             // SET 7 HL
+            let addr = gb.cpu.get_hl();
+            gb.bus.write8(addr, set_bit(7, gb.bus.read8(addr)));
             cycles += 16;
         }
         0xFF => {
             // SET 7 A
+            gb.cpu.set_a(set_bit(7, gb.cpu.get_a()));
             cycles += 8;
         }
         _ => {
             println!("Unknown opcode: {:#04X}", opcode);
         }
     }
-    return cycles;
+    cycles
 }
 
 // Jump/Call functions
-fn jump_relative(gb: &mut gameboy::Gameboy, value: i16) -> u16 {
-    let signed_pc = i16::try_from(gb.cpu.get_pc()).unwrap();
-    let jump_pc = i16::try_from(signed_pc + value).unwrap();
-    gb.cpu.set_pc(u16::try_from(jump_pc).unwrap());
-    return u16::try_from(jump_pc).unwrap();
+// the offset is relative to the address after the instruction
+fn jump_relative(gb: &mut gameboy::Gameboy, value: i8) -> u16 {
+    let addr = gb.cpu.get_pc().wrapping_add(value as u16);
+    gb.cpu.set_pc(addr);
+    addr
 }
 
 fn jump(gb: &mut gameboy::Gameboy, value: u16) -> u16 {
     gb.cpu.set_pc(value);
-    return value;
+    value
+}
+
+fn call(gb: &mut gameboy::Gameboy, addr: u16) {
+    let pc = gb.cpu.get_pc();
+    push_value(gb, pc);
+    gb.cpu.set_pc(addr);
+}
+
+fn ret(gb: &mut gameboy::Gameboy) {
+    let addr = pop_value(gb);
+    gb.cpu.set_pc(addr);
 }
 
 // bit and shift functions
+// bit 0 is the least significant bit
 fn get_bit_at_position_8bit(n: u32, num: u8) -> bool {
-    return (num & (1 << (7 - n))) != 0;
+    (num & (1 << n)) != 0
+}
+
+// sets the flags shared by every CB rotate/shift: Z from the result, N and H
+// cleared, C from the bit that was shifted out
+fn set_shift_flags(gb: &mut gameboy::Gameboy, result: u8, carry: bool) {
+    gb.cpu.set_z_flag(result == 0);
+    gb.cpu.set_n_flag(false);
+    gb.cpu.set_h_flag(false);
+    gb.cpu.set_c_flag(carry);
+}
+
+// RLC: bit 7 goes to both carry and bit 0
+fn rotate_left_circular(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = value.rotate_left(1);
+    set_shift_flags(gb, result, value & 0x80 != 0);
+    result
+}
+
+// RRC: bit 0 goes to both carry and bit 7
+fn rotate_right_circular(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = value.rotate_right(1);
+    set_shift_flags(gb, result, value & 0x01 != 0);
+    result
+}
+
+// RL: rotate left through the carry flag
+fn rotate_left(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = (value << 1) | gb.cpu.get_c_flag() as u8;
+    set_shift_flags(gb, result, value & 0x80 != 0);
+    result
+}
+
+// RR: rotate right through the carry flag
+fn rotate_right(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = (value >> 1) | ((gb.cpu.get_c_flag() as u8) << 7);
+    set_shift_flags(gb, result, value & 0x01 != 0);
+    result
+}
+
+// SLA: bit 0 is cleared
+fn shift_left_arithmetic(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = value << 1;
+    set_shift_flags(gb, result, value & 0x80 != 0);
+    result
 }
 
-fn get_bit_at_position_16bit(n: u32, num: u16) -> bool {
-    return (num & (1 << (15 - n))) != 0;
+// SRA: bit 7 keeps its value
+fn shift_right_arithmetic(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = (value >> 1) | (value & 0x80);
+    set_shift_flags(gb, result, value & 0x01 != 0);
+    result
+}
+
+// RLCA/RRCA/RLA/RRA: the CB rotates applied to A, except Z is always cleared
+fn rotate_accumulator(gb: &mut gameboy::Gameboy, rotate: fn(&mut gameboy::Gameboy, u8) -> u8) {
+    let result = rotate(gb, gb.cpu.get_a());
+    gb.cpu.set_a(result);
+    gb.cpu.set_z_flag(false);
+}
+
+// SWAP: exchange the upper and lower nibbles
+fn swap(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = value.rotate_left(4);
+    set_shift_flags(gb, result, false);
+    result
+}
+
+// SRL: bit 7 is cleared
+fn shift_right_logical(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
+    let result = value >> 1;
+    set_shift_flags(gb, result, value & 0x01 != 0);
+    result
+}
+
+// BIT: Z is set when the tested bit is 0, C is left untouched
+fn test_bit(gb: &mut gameboy::Gameboy, n: u32, value: u8) {
+    gb.cpu.set_z_flag(!get_bit_at_position_8bit(n, value));
+    gb.cpu.set_n_flag(false);
+    gb.cpu.set_h_flag(true);
+}
+
+// RES: no flags affected
+fn reset_bit(n: u32, value: u8) -> u8 {
+    value & !(1 << n)
+}
+
+// SET: no flags affected
+fn set_bit(n: u32, value: u8) -> u8 {
+    value | (1 << n)
+}
+
+// Operand functions
+fn read_operand(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
+    match operand {
+        Operand::Register(register) => gb.cpu.get_register_8bit(register),
+        Operand::IndirectHL => gb.bus.read8(gb.cpu.get_hl()),
+        Operand::Immediate => gameboy::read_byte(gb) as u8,
+    }
+}
+
+fn write_operand(gb: &mut gameboy::Gameboy, operand: Operand, value: u8) {
+    match operand {
+        Operand::Register(register) => gb.cpu.set_register_8bit(register, value),
+        Operand::IndirectHL => gb.bus.write8(gb.cpu.get_hl(), value),
+        Operand::Immediate => panic!("Can't write to an immediate operand"),
+    }
 }
 
 // Load functions
-fn load_8bit(gb: &mut gameboy::Gameboy, register: &str, value: u8) {
+fn load_8bit(gb: &mut gameboy::Gameboy, register: Reg8, value: u8) {
     gb.cpu.set_register_8bit(register, value);
 }
 
-fn load_16bit(gb: &mut gameboy::Gameboy, register: &str, value: u16) {
+fn load_16bit(gb: &mut gameboy::Gameboy, register: Reg16, value: u16) {
     gb.cpu.set_register_16bit(register, value);
 }
 
-fn load_immediate_8bit(gb: &mut gameboy::Gameboy, register: &str) {
-    let value = u8::try_from(gameboy::read_byte(gb)).unwrap();
+fn load_immediate_8bit(gb: &mut gameboy::Gameboy, register: Reg8) {
+    let value = read_operand(gb, Operand::Immediate);
     load_8bit(gb, register, value);
 }
 
-fn load_immediate_16bit(gb: &mut gameboy::Gameboy, register: &str) {
-    let value = u16::try_from(gameboy::read_short(gb)).unwrap();
+fn load_immediate_16bit(gb: &mut gameboy::Gameboy, register: Reg16) {
+    let value = gameboy::read_short(gb);
     load_16bit(gb, register, value);
 }
 
-fn load_register_8bit(gb: &mut gameboy::Gameboy, dest: &str, src: &str) {
+fn load_register_8bit(gb: &mut gameboy::Gameboy, dest: Reg8, src: Reg8) {
     let src_value = gb.cpu.get_register_8bit(src);
     load_8bit(gb, dest, src_value);
 }
 
-fn load_register_16bit(gb: &mut gameboy::Gameboy, dest: &str, src: &str) {
+fn load_register_16bit(gb: &mut gameboy::Gameboy, dest: Reg16, src: Reg16) {
     let src_value = gb.cpu.get_register_16bit(src);
     load_16bit(gb, dest, src_value);
 }
 
 // Stack functions
-fn push(gb: &mut gameboy::Gameboy, register: &str) {
+fn push(gb: &mut gameboy::Gameboy, register: Reg16) {
     let value = gb.cpu.get_register_16bit(register);
-    let addr = gb.cpu.get_sp() - 2;
+    push_value(gb, value);
+}
+
+pub fn push_value(gb: &mut gameboy::Gameboy, value: u16) {
+    let addr = gb.cpu.get_sp().wrapping_sub(2);
     gb.cpu.set_sp(addr);
-    gb.ram[addr as usize] = (value & 0xFF) as u8;
-    gb.ram[(addr + 1) as usize] = ((value >> 8) & 0xFF) as u8;
+    gb.bus.write16(addr, value);
 }
 
-fn pop(gb: &mut gameboy::Gameboy, register: &str) {
-    let addr = gb.cpu.get_sp();
-    let value = (gb.ram[(addr + 1) as usize] as u16) << 8 | gb.ram[addr as usize] as u16;
-    gb.cpu.set_sp(addr + 2);
+fn pop(gb: &mut gameboy::Gameboy, register: Reg16) {
+    let value = pop_value(gb);
     load_16bit(gb, register, value);
 }
 
+pub fn pop_value(gb: &mut gameboy::Gameboy) -> u16 {
+    let addr = gb.cpu.get_sp();
+    let value = gb.bus.read16(addr);
+    gb.cpu.set_sp(addr.wrapping_add(2));
+    value
+}
+
 // ALU functions
-fn increment_8bit(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
-    let current = gb.cpu.get_register_8bit(register);
+// SP plus a signed immediate, the flags come from adding the low byte as
+// unsigned
+fn add_sp_signed(gb: &mut gameboy::Gameboy) -> u16 {
+    let sp = gb.cpu.get_sp();
+    let value = gameboy::read_byte(gb) as u8;
+    gb.cpu.set_z_flag(false);
+    gb.cpu.set_n_flag(false);
+    gb.cpu
+        .set_h_flag((sp & 0x0F) + (value as u16 & 0x0F) > 0x0F);
+    gb.cpu.set_c_flag((sp & 0xFF) + value as u16 > 0xFF);
+    sp.wrapping_add(value as i8 as u16)
+}
+
+// turns A back into binary coded decimal after an addition or subtraction
+fn decimal_adjust(gb: &mut gameboy::Gameboy) {
+    let mut a = gb.cpu.get_a();
+    let mut carry = gb.cpu.get_c_flag();
+    if gb.cpu.get_n_flag() {
+        if carry {
+            a = a.wrapping_sub(0x60);
+        }
+        if gb.cpu.get_h_flag() {
+            a = a.wrapping_sub(0x06);
+        }
+    } else {
+        if carry || a > 0x99 {
+            a = a.wrapping_add(0x60);
+            carry = true;
+        }
+        if gb.cpu.get_h_flag() || a & 0x0F > 0x09 {
+            a = a.wrapping_add(0x06);
+        }
+    }
+    gb.cpu.set_a(a);
+    gb.cpu.set_z_flag(a == 0);
+    gb.cpu.set_h_flag(false);
+    gb.cpu.set_c_flag(carry);
+}
+
+fn increment_8bit(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
+    let current = read_operand(gb, operand);
     let result = current.wrapping_add(1);
+    // C is left untouched
     gb.cpu.set_z_flag(result == 0);
-    gb.cpu.set_n_flag(true);
-    gb.cpu.set_h_flag(result & 0xF == 0xF);
-
-    load_8bit(gb, register, result);
-    return result;
+    gb.cpu.set_n_flag(false);
+    gb.cpu.set_h_flag(current & 0xF == 0xF);
+    write_operand(gb, operand, result);
+    result
 }
 
-fn decrement_8bit(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
-    let current = gb.cpu.get_register_8bit(register);
+fn decrement_8bit(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
+    let current = read_operand(gb, operand);
     let result = current.wrapping_sub(1);
+    // C is left untouched
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_n_flag(true);
-    gb.cpu.set_h_flag(result & 0xF == 0xF);
-    gb.cpu.set_c_flag(current == 0);
-    load_8bit(gb, register, result);
-    return result;
+    gb.cpu.set_h_flag(current & 0xF == 0);
+    write_operand(gb, operand, result);
+    result
 }
 
-fn increment_16bit(gb: &mut gameboy::Gameboy, register: &str) -> u16 {
+fn increment_16bit(gb: &mut gameboy::Gameboy, register: Reg16) -> u16 {
     let current = gb.cpu.get_register_16bit(register);
     let result = current.wrapping_add(1);
     load_16bit(gb, register, result);
-    return result;
+    result
 }
 
-fn decrement_16bit(gb: &mut gameboy::Gameboy, register: &str) -> u16 {
+fn decrement_16bit(gb: &mut gameboy::Gameboy, register: Reg16) -> u16 {
     let current = gb.cpu.get_register_16bit(register);
     let result = current.wrapping_sub(1);
     load_16bit(gb, register, result);
-    return result;
+    result
 }
 
-fn add(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn add(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let (result, overflow) = a.overflowing_add(value);
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_n_flag(false);
     gb.cpu.set_h_flag((a & 0xF) + (value & 0xF) > 0xF);
     gb.cpu.set_c_flag(overflow);
     gb.cpu.set_a(result);
-    return result;
+    result
+}
+
+// ADD HL: Z is left untouched, H and C come from bits 11 and 15
+fn add_16bit(gb: &mut gameboy::Gameboy, register: Reg16) -> u16 {
+    let hl = gb.cpu.get_hl();
+    let value = gb.cpu.get_register_16bit(register);
+    let (result, overflow) = hl.overflowing_add(value);
+    gb.cpu.set_n_flag(false);
+    gb.cpu.set_h_flag((hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF);
+    gb.cpu.set_c_flag(overflow);
+    gb.cpu.set_hl(result);
+    result
 }
 
-fn add_with_carry(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn add_with_carry(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let carry = if gb.cpu.get_c_flag() { 1 } else { 0 };
     let result = a.wrapping_add(value).wrapping_add(carry);
     gb.cpu.set_z_flag(result == 0);
@@ -2701,97 +3157,37 @@ fn add_with_carry(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
     gb.cpu
         .set_c_flag(a as u16 + value as u16 + carry as u16 > 0xFF);
     gb.cpu.set_a(result);
-    return result;
-}
-
-fn add_immediate_value(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
-    // cheat and reuse the logic from add
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = add(gb, "b");
-    gb.cpu.set_b(temp);
-    gb.cpu.set_a(result);
-    return result;
-}
-
-fn add_with_carry_immediate_value(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
-    // cheat and reuse the logic from add
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = add_with_carry(gb, "b");
-    gb.cpu.set_b(temp);
-    gb.cpu.set_a(result);
-    return result;
-}
-
-fn add_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    return add_immediate_value(gb, value);
-}
-
-fn add_with_carry_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    return add_with_carry_immediate_value(gb, value);
+    result
 }
 
-fn sub(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn sub(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let (result, overflow) = a.overflowing_sub(value);
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_n_flag(true);
     gb.cpu.set_h_flag((a & 0xF) < (value & 0xF));
     gb.cpu.set_c_flag(overflow);
     gb.cpu.set_a(result);
-    return result;
+    result
 }
 
-fn sub_with_carry(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn sub_with_carry(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let carry = if gb.cpu.get_c_flag() { 1 } else { 0 };
     let result = a.wrapping_sub(value).wrapping_sub(carry);
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_n_flag(true);
     gb.cpu.set_h_flag((a & 0xF) < (value & 0xF) + carry);
-    gb.cpu.set_c_flag(a < value + carry);
-    gb.cpu.set_a(result);
-    return result;
-}
-
-fn sub_immediate_value(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
-    // cheat and reuse the logic from add
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = sub(gb, "b");
-    gb.cpu.set_b(temp);
+    gb.cpu.set_c_flag((a as u16) < value as u16 + carry as u16);
     gb.cpu.set_a(result);
-    return result;
-}
-
-fn sub_with_carry_immediate_value(gb: &mut gameboy::Gameboy, value: u8) -> u8 {
-    // cheat and reuse the logic from add
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = sub_with_carry(gb, "b");
-    gb.cpu.set_b(temp);
-    gb.cpu.set_a(result);
-    return result;
-}
-
-fn sub_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    return sub_immediate_value(gb, value);
+    result
 }
 
-fn sub_with_carry_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    return sub_with_carry_immediate_value(gb, value);
-}
-
-fn compare(gb: &mut gameboy::Gameboy, register: &str) {
+fn compare(gb: &mut gameboy::Gameboy, operand: Operand) {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let (result, overflow) = a.overflowing_sub(value);
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_n_flag(true);
@@ -2799,82 +3195,43 @@ fn compare(gb: &mut gameboy::Gameboy, register: &str) {
     gb.cpu.set_c_flag(overflow);
 }
 
-fn compare_immediate_value(gb: &mut gameboy::Gameboy, value: u8) {
-    // cheat and reuse the logic from add
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    compare(gb, "b");
-    gb.cpu.set_b(temp);
-}
-
-fn compare_immediate(gb: &mut gameboy::Gameboy) {
-    let value = gameboy::read_byte(gb) as u8;
-    compare_immediate_value(gb, value);
-}
-
-fn xor(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn xor(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let result = a ^ value;
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_h_flag(false);
     gb.cpu.set_c_flag(false);
     gb.cpu.set_n_flag(false);
-    gb.cpu.set_register_8bit("a", result);
-    return result;
-}
-
-fn xor_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = xor(gb, "b");
-    gb.cpu.set_b(temp);
-    return result;
+    gb.cpu.set_a(result);
+    result
 }
 
-fn or(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn or(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let result = a | value;
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_h_flag(false);
     gb.cpu.set_c_flag(false);
     gb.cpu.set_n_flag(false);
-    gb.cpu.set_register_8bit("a", result);
-    return result;
-}
-
-fn or_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = or(gb, "b");
-    gb.cpu.set_b(temp);
-    return result;
+    gb.cpu.set_a(result);
+    result
 }
 
-fn and(gb: &mut gameboy::Gameboy, register: &str) -> u8 {
+fn and(gb: &mut gameboy::Gameboy, operand: Operand) -> u8 {
     let a = gb.cpu.get_a();
-    let value = gb.cpu.get_register_8bit(register);
+    let value = read_operand(gb, operand);
     let result = a & value;
     gb.cpu.set_z_flag(result == 0);
     gb.cpu.set_h_flag(true);
     gb.cpu.set_c_flag(false);
     gb.cpu.set_n_flag(false);
-    gb.cpu.set_register_8bit("a", result);
-    return result;
-}
-
-fn and_immediate(gb: &mut gameboy::Gameboy) -> u8 {
-    let value = gameboy::read_byte(gb) as u8;
-    let temp = gb.cpu.get_b();
-    gb.cpu.set_b(value);
-    let result = and(gb, "b");
-    gb.cpu.set_b(temp);
-    return result;
+    gb.cpu.set_a(result);
+    result
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -2895,7 +3252,7 @@ mod tests {
         // base case
         gb.cpu.set_a(0b1111_1111);
         gb.cpu.set_b(0b0000_0000);
-        xor(&mut gb, "b");
+        xor(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b1111_1111);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2905,7 +3262,7 @@ mod tests {
         // zero flag, all zero
         gb.cpu.set_a(0b0000_0000);
         gb.cpu.set_b(0b0000_0000);
-        xor(&mut gb, "b");
+        xor(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b0000_0000);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2915,7 +3272,7 @@ mod tests {
         // zero flag, all one
         gb.cpu.set_a(0b1111_1111);
         gb.cpu.set_b(0b1111_1111);
-        xor(&mut gb, "b");
+        xor(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b0000_0000);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2930,7 +3287,7 @@ mod tests {
         // normal case
         gb.cpu.set_a(0b1111_1111);
         gb.cpu.set_b(0b1111_1111);
-        and(&mut gb, "b");
+        and(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b1111_1111);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2940,7 +3297,7 @@ mod tests {
         // zero flag
         gb.cpu.set_a(0b1010_1010);
         gb.cpu.set_b(0b0101_0101);
-        and(&mut gb, "b");
+        and(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b0000_0000);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2955,7 +3312,7 @@ mod tests {
         // normal case
         gb.cpu.set_a(0b1111_1111);
         gb.cpu.set_b(0b1111_1111);
-        or(&mut gb, "b");
+        or(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b1111_1111);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2965,7 +3322,7 @@ mod tests {
         // zero flag
         gb.cpu.set_a(0b0000_0000);
         gb.cpu.set_b(0b0000_0000);
-        or(&mut gb, "b");
+        or(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b0000_0000);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -2973,35 +3330,219 @@ mod tests {
         assert_eq!(gb.cpu.get_c_flag(), false);
     }
 
+    #[test]
+    fn test_operand() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_hl(0xC000);
+        gb.cpu.set_pc(0xC100);
+        gb.bus.write8(0xC100, 0x42);
+
+        write_operand(&mut gb, Operand::IndirectHL, 0x12);
+        assert_eq!(gb.bus.read8(0xC000), 0x12);
+        assert_eq!(read_operand(&mut gb, Operand::IndirectHL), 0x12);
+        write_operand(&mut gb, Operand::Register(Reg8::E), 0x34);
+        assert_eq!(read_operand(&mut gb, Operand::Register(Reg8::E)), 0x34);
+        assert_eq!(read_operand(&mut gb, Operand::Immediate), 0x42);
+        assert_eq!(gb.cpu.get_pc(), 0xC101);
+    }
+
     #[test]
     fn test_get_bit_at() {
         // 0b11111110
         let val: u8 = 0xFE;
-        let bit = get_bit_at_position_8bit(7, val) as u8;
+        let bit = get_bit_at_position_8bit(0, val) as u8;
         assert_eq!(bit, 0);
+        let bit = get_bit_at_position_8bit(7, val) as u8;
+        assert_eq!(bit, 1);
+    }
+
+    #[test]
+    fn test_rotate_left_circular() {
+        let mut gb = gameboy::create_gameboy();
+        let result = rotate_left_circular(&mut gb, 0b1000_0101);
+        assert_eq!(result, 0b0000_1011);
+        assert!(!gb.cpu.get_z_flag());
+        assert!(!gb.cpu.get_n_flag());
+        assert!(!gb.cpu.get_h_flag());
+        assert!(gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_rotate_through_carry() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_c_flag(false);
+        let result = rotate_left(&mut gb, 0b1000_0000);
+        assert_eq!(result, 0x00);
+        assert!(gb.cpu.get_z_flag());
+        assert!(gb.cpu.get_c_flag());
+
+        // the carry shifted out above comes back in on the other side
+        let result = rotate_right(&mut gb, 0b0000_0010);
+        assert_eq!(result, 0b1000_0001);
+        assert!(!gb.cpu.get_z_flag());
+        assert!(!gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_shifts() {
+        let mut gb = gameboy::create_gameboy();
+        assert_eq!(shift_left_arithmetic(&mut gb, 0b1100_0001), 0b1000_0010);
+        assert!(gb.cpu.get_c_flag());
+        assert_eq!(shift_right_arithmetic(&mut gb, 0b1000_0010), 0b1100_0001);
+        assert!(!gb.cpu.get_c_flag());
+        assert_eq!(shift_right_logical(&mut gb, 0b1000_0001), 0b0100_0000);
+        assert!(gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_c_flag(true);
+        assert_eq!(swap(&mut gb, 0xAB), 0xBA);
+        assert!(!gb.cpu.get_z_flag());
+        assert!(!gb.cpu.get_c_flag());
+        assert_eq!(swap(&mut gb, 0x00), 0x00);
+        assert!(gb.cpu.get_z_flag());
+    }
+
+    #[test]
+    fn test_bit_flags() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_c_flag(true);
+        test_bit(&mut gb, 7, 0x7F);
+        assert!(gb.cpu.get_z_flag());
+        assert!(!gb.cpu.get_n_flag());
+        assert!(gb.cpu.get_h_flag());
+        assert!(gb.cpu.get_c_flag());
+        test_bit(&mut gb, 0, 0x01);
+        assert!(!gb.cpu.get_z_flag());
+    }
+
+    #[test]
+    fn test_reset_and_set_bit() {
+        assert_eq!(reset_bit(3, 0xFF), 0xF7);
+        assert_eq!(set_bit(7, 0x00), 0x80);
+    }
+
+    #[test]
+    fn test_execute_prefixed_hl() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_hl(0xC000);
+        gb.bus.write8(0xC000, 0x0F);
+
+        // SWAP HL
+        assert_eq!(execute_prefixed(&mut gb, 0x36), 16);
+        assert_eq!(gb.bus.read8(0xC000), 0xF0);
+
+        // BIT 7 HL
+        assert_eq!(execute_prefixed(&mut gb, 0x7E), 12);
+        assert!(!gb.cpu.get_z_flag());
+
+        // RES 7 HL
+        assert_eq!(execute_prefixed(&mut gb, 0xBE), 16);
+        assert_eq!(gb.bus.read8(0xC000), 0x70);
+
+        // SET 0 B
+        gb.cpu.set_b(0x00);
+        assert_eq!(execute_prefixed(&mut gb, 0xC0), 8);
+        assert_eq!(gb.cpu.get_b(), 0x01);
     }
 
     #[test]
     fn test_increment_8bit() {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_a(0x00);
-        increment_8bit(&mut gb, "a");
+        increment_8bit(&mut gb, Operand::Register(Reg8::A));
         assert_eq!(gb.cpu.get_a(), 0x01);
+
+        // half carry from bit 3, C is untouched
+        gb.cpu.set_a(0x0F);
+        gb.cpu.set_c_flag(true);
+        increment_8bit(&mut gb, Operand::Register(Reg8::A));
+        assert!(gb.cpu.get_h_flag());
+        assert!(!gb.cpu.get_n_flag());
+        assert!(gb.cpu.get_c_flag());
     }
 
     #[test]
     fn test_decrement_8bit() {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_a(0x01);
-        decrement_8bit(&mut gb, "a");
+        gb.cpu.set_c_flag(false);
+        decrement_8bit(&mut gb, Operand::Register(Reg8::A));
         assert_eq!(gb.cpu.get_a(), 0x00);
+        assert!(gb.cpu.get_z_flag());
+        assert!(!gb.cpu.get_h_flag());
+
+        // borrow from bit 4, C is untouched
+        decrement_8bit(&mut gb, Operand::Register(Reg8::A));
+        assert_eq!(gb.cpu.get_a(), 0xFF);
+        assert!(gb.cpu.get_h_flag());
+        assert!(gb.cpu.get_n_flag());
+        assert!(!gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_add_16bit() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_hl(0x0FFF);
+        gb.cpu.set_bc(0x0001);
+        gb.cpu.set_z_flag(true);
+        add_16bit(&mut gb, Reg16::BC);
+        assert_eq!(gb.cpu.get_hl(), 0x1000);
+        assert!(gb.cpu.get_h_flag());
+        assert!(!gb.cpu.get_c_flag());
+        assert!(gb.cpu.get_z_flag());
+
+        gb.cpu.set_de(0xF000);
+        add_16bit(&mut gb, Reg16::DE);
+        assert_eq!(gb.cpu.get_hl(), 0x0000);
+        assert!(gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_jr() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // JR NZ -2, not taken, the offset is still skipped
+        gb.bus.write8(0xC000, 0x20);
+        gb.bus.write8(0xC001, 0xFE);
+        gb.cpu.set_z_flag(true);
+        let opcode = gameboy::read_byte(&mut gb);
+        assert_eq!(execute_instruction(&mut gb, opcode), 8);
+        assert_eq!(gb.cpu.get_pc(), 0xC002);
+
+        // taken, back to itself
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_z_flag(false);
+        let opcode = gameboy::read_byte(&mut gb);
+        assert_eq!(execute_instruction(&mut gb, opcode), 12);
+        assert_eq!(gb.cpu.get_pc(), 0xC000);
+    }
+
+    #[test]
+    fn test_call_ret() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0xFFFE);
+        // CALL C123, then RET
+        gb.bus.write8(0xC000, 0xCD);
+        gb.bus.write16(0xC001, 0xC123);
+        gb.bus.write8(0xC123, 0xC9);
+
+        gameboy::step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0xC123);
+        assert_eq!(gb.cpu.get_sp(), 0xFFFC);
+        gameboy::step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0xC003);
+        assert_eq!(gb.cpu.get_sp(), 0xFFFE);
     }
 
     #[test]
     fn test_increment_16bit() {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_bc(0x0000);
-        increment_16bit(&mut gb, "bc");
+        increment_16bit(&mut gb, Reg16::BC);
         assert_eq!(gb.cpu.get_bc(), 0x0001);
     }
 
@@ -3009,7 +3550,7 @@ mod tests {
     fn test_decrement_16bit() {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_bc(0x0001);
-        decrement_16bit(&mut gb, "bc");
+        decrement_16bit(&mut gb, Reg16::BC);
         assert_eq!(gb.cpu.get_bc(), 0x0000);
     }
 
@@ -3020,7 +3561,7 @@ mod tests {
         // normal case
         gb.cpu.set_a(0x01);
         gb.cpu.set_b(0x01);
-        add(&mut gb, "b");
+        add(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0x02);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -3030,7 +3571,7 @@ mod tests {
         // zero flag
         gb.cpu.set_a(0x00);
         gb.cpu.set_b(0x00);
-        add(&mut gb, "b");
+        add(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0x00);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -3040,7 +3581,7 @@ mod tests {
         // half carry flag
         gb.cpu.set_a(0b1000_1111);
         gb.cpu.set_b(0b0000_0001);
-        add(&mut gb, "b");
+        add(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b1001_0000);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -3050,7 +3591,7 @@ mod tests {
         // carry flag
         gb.cpu.set_a(0xFF);
         gb.cpu.set_b(0x01);
-        add(&mut gb, "b");
+        add(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0x00);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), false);
@@ -3065,7 +3606,7 @@ mod tests {
         // normal case
         gb.cpu.set_a(0x02);
         gb.cpu.set_b(0x01);
-        sub(&mut gb, "b");
+        sub(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0x01);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), true);
@@ -3075,7 +3616,7 @@ mod tests {
         // zero flag
         gb.cpu.set_a(0x01);
         gb.cpu.set_b(0x01);
-        sub(&mut gb, "b");
+        sub(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0x00);
         assert_eq!(gb.cpu.get_z_flag(), true);
         assert_eq!(gb.cpu.get_n_flag(), true);
@@ -3085,7 +3626,7 @@ mod tests {
         // half carry flag
         gb.cpu.set_a(0b1000_0000);
         gb.cpu.set_b(0b0000_0001);
-        sub(&mut gb, "b");
+        sub(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0b0111_1111);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), true);
@@ -3095,7 +3636,7 @@ mod tests {
         // carry flag
         gb.cpu.set_a(0x00);
         gb.cpu.set_b(0x01);
-        sub(&mut gb, "b");
+        sub(&mut gb, Operand::Register(Reg8::B));
         assert_eq!(gb.cpu.get_a(), 0xFF);
         assert_eq!(gb.cpu.get_z_flag(), false);
         assert_eq!(gb.cpu.get_n_flag(), true);
@@ -3103,16 +3644,33 @@ mod tests {
         assert_eq!(gb.cpu.get_c_flag(), true);
     }
 
+    #[test]
+    fn test_sub_with_carry() {
+        let mut gb = gameboy::create_gameboy();
+        // SBC A 0xFF with the carry set borrows all the way through
+        gb.cpu.set_pc(0xC000);
+        gb.bus.write8(0xC000, 0xFF);
+        gb.cpu.set_a(0x00);
+        gb.cpu.set_c_flag(true);
+        execute_instruction(&mut gb, 0xDE);
+        assert_eq!(gb.cpu.get_a(), 0x00);
+        assert!(gb.cpu.get_z_flag());
+        assert!(gb.cpu.get_n_flag());
+        assert!(gb.cpu.get_h_flag());
+        assert!(gb.cpu.get_c_flag());
+        assert_eq!(gb.cpu.get_pc(), 0xC001);
+    }
+
     #[test]
     fn test_push() {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_sp(0xFFFE);
         gb.cpu.set_bc(0x1234);
-        push(&mut gb, "bc");
+        push(&mut gb, Reg16::BC);
         assert_eq!(gb.cpu.get_sp(), 0xFFFC);
         let addr = gb.cpu.get_sp();
-        assert_eq!(gb.ram[(addr + 1) as usize], 0x12);
-        assert_eq!(gb.ram[addr as usize], 0x34);
+        assert_eq!(gb.bus.read8(addr + 1), 0x12);
+        assert_eq!(gb.bus.read8(addr), 0x34);
     }
 
     #[test]
@@ -3120,9 +3678,105 @@ mod tests {
         let mut gb = gameboy::create_gameboy();
         gb.cpu.set_sp(0xFFFE);
         gb.cpu.set_bc(0x1234);
-        push(&mut gb, "bc");
-        pop(&mut gb, "hl");
+        push(&mut gb, Reg16::BC);
+        pop(&mut gb, Reg16::HL);
         assert_eq!(gb.cpu.get_sp(), 0xFFFE);
         assert_eq!(gb.cpu.get_hl(), 0x1234);
     }
+
+    #[test]
+    fn test_decimal_adjust() {
+        let mut gb = gameboy::create_gameboy();
+        // 0x19 + 0x28 = 0x41, which is 47 in BCD
+        gb.cpu.set_a(0x19);
+        gb.cpu.set_b(0x28);
+        add(&mut gb, Operand::Register(Reg8::B));
+        decimal_adjust(&mut gb);
+        assert_eq!(gb.cpu.get_a(), 0x47);
+        assert!(!gb.cpu.get_c_flag());
+
+        // 0x99 + 0x01 wraps to 00 with a carry
+        gb.cpu.set_a(0x99);
+        gb.cpu.set_b(0x01);
+        add(&mut gb, Operand::Register(Reg8::B));
+        decimal_adjust(&mut gb);
+        assert_eq!(gb.cpu.get_a(), 0x00);
+        assert!(gb.cpu.get_z_flag());
+        assert!(gb.cpu.get_c_flag());
+
+        // 0x42 - 0x13 = 29
+        gb.cpu.set_a(0x42);
+        gb.cpu.set_b(0x13);
+        sub(&mut gb, Operand::Register(Reg8::B));
+        decimal_adjust(&mut gb);
+        assert_eq!(gb.cpu.get_a(), 0x29);
+        assert!(gb.cpu.get_n_flag());
+        assert!(!gb.cpu.get_h_flag());
+    }
+
+    #[test]
+    fn test_add_sp_signed() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0x00FF);
+        // ADD SP,1
+        gb.bus.write8(0xC000, 0xE8);
+        gb.bus.write8(0xC001, 0x01);
+        assert_eq!(gameboy::step_cpu(&mut gb), 16);
+        assert_eq!(gb.cpu.get_sp(), 0x0100);
+        assert!(gb.cpu.get_h_flag() && gb.cpu.get_c_flag());
+        assert!(!gb.cpu.get_z_flag() && !gb.cpu.get_n_flag());
+
+        // LD HL,SP-2, the flags still come from the unsigned low byte
+        gb.bus.write8(0xC002, 0xF8);
+        gb.bus.write8(0xC003, 0xFE);
+        assert_eq!(gameboy::step_cpu(&mut gb), 12);
+        assert_eq!(gb.cpu.get_hl(), 0x00FE);
+        assert_eq!(gb.cpu.get_sp(), 0x0100);
+        assert!(!gb.cpu.get_h_flag() && !gb.cpu.get_c_flag());
+    }
+
+    #[test]
+    fn test_rst_and_jp_hl() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0xD000);
+        // RST 28H
+        gb.bus.write8(0xC000, 0xEF);
+        assert_eq!(gameboy::step_cpu(&mut gb), 16);
+        assert_eq!(gb.cpu.get_pc(), 0x0028);
+        assert_eq!(pop_value(&mut gb), 0xC001);
+
+        // JP HL
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_hl(0x1234);
+        gb.bus.write8(0xC000, 0xE9);
+        assert_eq!(gameboy::step_cpu(&mut gb), 4);
+        assert_eq!(gb.cpu.get_pc(), 0x1234);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // LD B,C then LD B,B
+        gb.bus.write8(0xC000, 0x41);
+        gb.bus.write8(0xC001, 0x40);
+        gameboy::step_cpu(&mut gb);
+        assert!(!gb.cpu.get_breakpoint());
+        gameboy::step_cpu(&mut gb);
+        assert!(gb.cpu.get_breakpoint());
+    }
+
+    #[test]
+    fn test_pop_af() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0xD000);
+        push_value(&mut gb, 0x12FF);
+        // POP AF
+        gb.bus.write8(0xC000, 0xF1);
+        gameboy::step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_af(), 0x12F0);
+    }
 }
@@ -0,0 +1,172 @@
+// Reference: https://gbdev.io/pandocs/Interrupts.html
+
+use crate::{gameboy, instruction};
+
+// interrupt registers
+pub const IE_REGISTER: u16 = 0xFFFF; // Interrupt enable
+pub const IF_REGISTER: u16 = 0xFF0F; // Interrupt flag (requested interrupts)
+
+// interrupt bits, lower bits have higher priority
+pub const VBLANK: u8 = 0;
+pub const LCD_STAT: u8 = 1;
+pub const TIMER: u8 = 2;
+pub const SERIAL: u8 = 3;
+pub const JOYPAD: u8 = 4;
+
+// 2 wait states, push PC (2 m-cycles), then set PC (1 m-cycle)
+const DISPATCH_CYCLES: i64 = 20;
+
+// the handler address for an interrupt bit
+pub fn vector(interrupt: u8) -> u16 {
+    0x40 + (interrupt as u16) * 8
+}
+
+// sets the interrupt's bit in IF, used by the hardware raising it
+pub fn request_interrupt(gb: &mut gameboy::Gameboy, interrupt: u8) {
+    gb.bus.request_interrupt(interrupt);
+}
+
+// interrupts that are both requested and enabled, regardless of IME
+pub fn pending_interrupts(gb: &gameboy::Gameboy) -> u8 {
+    gb.bus.read8(IE_REGISTER) & gb.bus.read8(IF_REGISTER) & 0x1F
+}
+
+// dispatches the highest priority pending interrupt if IME is set,
+// returns the cycles spent (0 when nothing was dispatched)
+pub fn handle_interrupts(gb: &mut gameboy::Gameboy) -> i64 {
+    if !gb.cpu.get_ime() {
+        return 0;
+    }
+    let pending = pending_interrupts(gb);
+    if pending == 0 {
+        return 0;
+    }
+
+    let interrupt = pending.trailing_zeros() as u8;
+    gb.cpu.set_ime(false);
+    let flags = gb.bus.read8(IF_REGISTER);
+    gb.bus.write8(IF_REGISTER, flags & !(1 << interrupt));
+    let mut pc = gb.cpu.get_pc();
+    if gb.cpu.get_halt_bug() {
+        // EI then HALT with an interrupt pending, the handler returns to
+        // the HALT, which then halts normally
+        gb.cpu.set_halt_bug(false);
+        pc = pc.wrapping_sub(1);
+    }
+    instruction::push_value(gb, pc);
+    gb.cpu.set_pc(vector(interrupt));
+    DISPATCH_CYCLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector() {
+        assert_eq!(vector(VBLANK), 0x40);
+        assert_eq!(vector(LCD_STAT), 0x48);
+        assert_eq!(vector(TIMER), 0x50);
+        assert_eq!(vector(SERIAL), 0x58);
+        assert_eq!(vector(JOYPAD), 0x60);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0x1234);
+        gb.cpu.set_sp(0xFFFE);
+        gb.cpu.set_ime(true);
+        gb.bus.write8(IE_REGISTER, 1 << TIMER);
+        request_interrupt(&mut gb, TIMER);
+
+        assert_eq!(handle_interrupts(&mut gb), 20);
+        assert_eq!(gb.cpu.get_pc(), 0x50);
+        assert_eq!(gb.cpu.get_sp(), 0xFFFC);
+        assert_eq!(gb.bus.read8(0xFFFD), 0x12);
+        assert_eq!(gb.bus.read8(0xFFFC), 0x34);
+        assert!(!gb.cpu.get_ime());
+        assert_eq!(gb.bus.read8(IF_REGISTER) & (1 << TIMER), 0);
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_sp(0xFFFE);
+        gb.cpu.set_ime(true);
+        gb.bus.write8(IE_REGISTER, 0x1F);
+        request_interrupt(&mut gb, JOYPAD);
+        request_interrupt(&mut gb, LCD_STAT);
+
+        handle_interrupts(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0x48);
+        // the lower priority request stays pending
+        assert_eq!(pending_interrupts(&gb), 1 << JOYPAD);
+    }
+
+    #[test]
+    fn test_no_dispatch() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0x1234);
+
+        // requested and enabled, but IME is off
+        gb.bus.write8(IE_REGISTER, 1 << VBLANK);
+        request_interrupt(&mut gb, VBLANK);
+        assert_eq!(handle_interrupts(&mut gb), 0);
+
+        // IME is on, but the interrupt is not enabled in IE
+        gb.cpu.set_ime(true);
+        gb.bus.write8(IE_REGISTER, 0);
+        assert_eq!(handle_interrupts(&mut gb), 0);
+        assert_eq!(gb.cpu.get_pc(), 0x1234);
+    }
+
+    #[test]
+    fn test_ei_delay() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // EI, SCF, SCF
+        gb.bus.write8(0xC000, 0xFB);
+        gb.bus.write8(0xC001, 0x37);
+        gb.bus.write8(0xC002, 0x37);
+        gb.bus.write8(IE_REGISTER, 1 << VBLANK);
+        request_interrupt(&mut gb, VBLANK);
+
+        gameboy::step_cpu(&mut gb);
+        assert!(!gb.cpu.get_ime());
+        // the instruction after EI still runs before the interrupt
+        gameboy::step_cpu(&mut gb);
+        assert!(gb.cpu.get_ime());
+        assert_eq!(gb.cpu.get_pc(), 0xC002);
+        gameboy::step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0x40);
+    }
+
+    #[test]
+    fn test_ei_di() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        // EI, DI
+        gb.bus.write8(0xC000, 0xFB);
+        gb.bus.write8(0xC001, 0xF3);
+
+        gameboy::step_cpu(&mut gb);
+        gameboy::step_cpu(&mut gb);
+        assert!(!gb.cpu.get_ime());
+    }
+
+    #[test]
+    fn test_reti() {
+        let mut gb = gameboy::create_gameboy();
+        gb.cpu.set_pc(0xC000);
+        gb.cpu.set_sp(0xFFFE);
+        instruction::push_value(&mut gb, 0x1234);
+        // RETI
+        gb.bus.write8(0xC000, 0xD9);
+
+        gameboy::step_cpu(&mut gb);
+        assert_eq!(gb.cpu.get_pc(), 0x1234);
+        assert_eq!(gb.cpu.get_sp(), 0xFFFE);
+        assert!(gb.cpu.get_ime());
+    }
+}
@@ -0,0 +1,214 @@
+// Reference: https://gbdev.io/pandocs/Joypad_Input.html
+
+pub mod script;
+
+pub const P1_REGISTER: u16 = 0xFF00;
+
+// writing 0 to one of these bits selects its half of the buttons, the low 4
+// bits then read 0 for each pressed button
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_BUTTONS: u8 = 0x20;
+const SELECT_MASK: u8 = SELECT_DIRECTIONS | SELECT_BUTTONS;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // the name in any case, like "a" or "Start"
+    pub fn from_name(name: &str) -> Option<Button> {
+        match name.to_ascii_lowercase().as_str() {
+            "right" => Some(Button::Right),
+            "left" => Some(Button::Left),
+            "up" => Some(Button::Up),
+            "down" => Some(Button::Down),
+            "a" => Some(Button::A),
+            "b" => Some(Button::B),
+            "select" => Some(Button::Select),
+            "start" => Some(Button::Start),
+            _ => None,
+        }
+    }
+}
+
+// which buttons are held down, set by the frontend or a test each frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputState {
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+}
+
+impl InputState {
+    pub fn pressed(&self, button: Button) -> bool {
+        match button {
+            Button::Right => self.right,
+            Button::Left => self.left,
+            Button::Up => self.up,
+            Button::Down => self.down,
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::Select => self.select,
+            Button::Start => self.start,
+        }
+    }
+
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        let state = match button {
+            Button::Right => &mut self.right,
+            Button::Left => &mut self.left,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::Select => &mut self.select,
+            Button::Start => &mut self.start,
+        };
+        *state = pressed;
+    }
+
+    // the directions in the low nibble and the buttons in the high one, in
+    // the order P1 reports them, 1 for pressed
+    fn bits(&self) -> u8 {
+        Button::ALL
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &button)| {
+                bits | (self.pressed(button) as u8) << i
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    // bits 4 and 5 as last written
+    select: u8,
+    state: InputState,
+    // a joypad interrupt for the bus to raise
+    interrupt: bool,
+}
+
+impl Joypad {
+    pub fn state(&self) -> InputState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: InputState) {
+        self.update(|joypad| joypad.state = state);
+    }
+
+    // the low 4 bits of P1, 0 for each selected button that is pressed
+    fn lines(&self) -> u8 {
+        let bits = self.state.bits();
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= bits & 0x0F;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= bits >> 4;
+        }
+        !pressed & 0x0F
+    }
+
+    // the interrupt fires when any line goes from high to low, which can
+    // come from a press or from selecting a held button
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        change(self);
+        if before & !self.lines() != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    // true once for every joypad interrupt
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    // the top 2 bits are unused and read as 1
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.update(|joypad| joypad.select = value & SELECT_MASK);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let mut joypad = Joypad::default();
+        joypad.set_state(InputState {
+            a: true,
+            down: true,
+            start: true,
+            ..InputState::default()
+        });
+        // nothing selected
+        joypad.write(0x30);
+        assert_eq!(joypad.read(), 0xFF);
+        joypad.write(SELECT_BUTTONS);
+        assert_eq!(joypad.read(), 0xE7);
+        joypad.write(SELECT_DIRECTIONS);
+        assert_eq!(joypad.read(), 0xD6);
+        // both halves at once are ANDed together
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC6);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_BUTTONS);
+        assert!(!joypad.take_interrupt());
+
+        let mut state = InputState::default();
+        state.set(Button::Left, true);
+        joypad.set_state(state);
+        assert!(joypad.take_interrupt());
+        assert!(!joypad.take_interrupt());
+
+        // a button on the other half doesn't change the lines
+        state.set(Button::Start, true);
+        joypad.set_state(state);
+        assert!(!joypad.take_interrupt());
+        // until it's selected
+        joypad.write(SELECT_DIRECTIONS);
+        assert!(joypad.take_interrupt());
+
+        // releasing is a rising edge
+        joypad.set_state(InputState::default());
+        assert!(!joypad.take_interrupt());
+        assert!(!joypad.state().pressed(Button::Start));
+    }
+}
@@ -0,0 +1,143 @@
+// input scripts drive games without anyone at the controls, one press per
+// line:
+//
+//   # skip the title screen
+//   120 start
+//   200 a+right 30
+//
+// is <frame> <buttons joined by +> [<frames held, 1 by default>], frames
+// count from 0
+
+use std::{fs, io, path::Path};
+
+use super::{Button, InputState};
+
+struct Press {
+    frame: u64,
+    buttons: Vec<Button>,
+    frames: u64,
+}
+
+#[derive(Default)]
+pub struct InputScript {
+    presses: Vec<Press>,
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> io::Result<InputScript> {
+        InputScript::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<InputScript> {
+        let mut presses = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (frame, buttons, frames) = match fields[..] {
+                [] => continue,
+                [frame, buttons] => (frame, buttons, "1"),
+                [frame, buttons, frames] => (frame, buttons, frames),
+                _ => {
+                    return Err(invalid(
+                        line_number,
+                        "expected <frame> <buttons> [<frames>]",
+                    ))
+                }
+            };
+            let frame = frame
+                .parse()
+                .map_err(|_| invalid(line_number, "bad frame number"))?;
+            let frames = frames
+                .parse()
+                .map_err(|_| invalid(line_number, "bad frame count"))?;
+            let buttons = buttons
+                .split('+')
+                .map(|name| {
+                    Button::from_name(name)
+                        .ok_or_else(|| invalid(line_number, &format!("unknown button {}", name)))
+                })
+                .collect::<io::Result<_>>()?;
+            presses.push(Press {
+                frame,
+                buttons,
+                frames,
+            });
+        }
+        Ok(InputScript { presses })
+    }
+
+    // the buttons held during a frame
+    pub fn state_at(&self, frame: u64) -> InputState {
+        let mut state = InputState::default();
+        for press in &self.presses {
+            if (press.frame..press.frame.saturating_add(press.frames)).contains(&frame) {
+                for &button in &press.buttons {
+                    state.set(button, true);
+                }
+            }
+        }
+        state
+    }
+
+    // the first frame after every press has been released
+    pub fn end_frame(&self) -> u64 {
+        self.presses
+            .iter()
+            .map(|press| press.frame.saturating_add(press.frames))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script = InputScript::parse(
+            "# comment\n\
+             \n\
+             2 start\n\
+             4 A+Right 3 # held\n\
+             5 b 2\n",
+        )
+        .unwrap();
+        assert_eq!(script.state_at(0), InputState::default());
+        assert!(script.state_at(2).start);
+        assert!(!script.state_at(3).start);
+        assert!(!script.state_at(3).a);
+        let state = script.state_at(5);
+        assert!(state.a && state.right && state.b);
+        assert!(script.state_at(6).a);
+        assert!(!script.state_at(7).a && !script.state_at(7).b);
+        assert_eq!(script.end_frame(), 7);
+
+        // held until the end of time
+        let script = InputScript::parse(&format!("10 a {}", u64::MAX)).unwrap();
+        assert!(script.state_at(u64::MAX - 1).a);
+        assert_eq!(script.end_frame(), u64::MAX);
+    }
+
+    #[test]
+    fn test_errors() {
+        for (text, message) in [
+            ("x a", "line 1: bad frame number"),
+            ("\n1 a x", "line 2: bad frame count"),
+            ("1 a+jump", "line 1: unknown button jump"),
+            ("1 a 2 3", "line 1: expected <frame> <buttons> [<frames>]"),
+        ] {
+            let err = InputScript::parse(text).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), message);
+        }
+    }
+}
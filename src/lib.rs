@@ -0,0 +1,18 @@
+// the original code spells out its returns and compares flags with
+// assert_eq!
+#![allow(clippy::needless_return, clippy::bool_assert_comparison)]
+
+pub mod apu;
+pub mod bus;
+pub mod cartridge;
+pub mod cpu;
+pub mod gameboy;
+pub mod instruction;
+pub mod interrupt;
+pub mod joypad;
+pub mod mbc;
+pub mod ppu;
+pub mod screenshot;
+pub mod serial;
+pub mod timer;
+pub mod wav;
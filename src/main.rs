@@ -1,38 +1,428 @@
-use std::fs::File;
-use std::io::Read;
 use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
-mod cpu;
-mod gameboy;
-mod instruction;
+use emulator::{apu, cartridge, gameboy, joypad::script::InputScript, ppu, serial::link, wav};
+
+// how often battery RAM is written back while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+type WavFile = wav::WavWriter<io::BufWriter<fs::File>>;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+fn write_save(gameboy: &mut gameboy::Gameboy, path: &Path) {
+    if let Some(cartridge) = gameboy.bus.cartridge_mut() {
+        if let Err(err) = cartridge.write_save_file(path) {
+            eprintln!("{}: {}", path.display(), err);
+        }
+    }
+}
+
+// a WAV file being written, of the mix or of one channel
+struct Track {
+    channel: Option<usize>,
+    path: PathBuf,
+    writer: WavFile,
+}
+
+// the sound being saved while running
+#[derive(Default)]
+struct Recording {
+    tracks: Vec<Track>,
+    register_log: Option<(PathBuf, io::BufWriter<fs::File>)>,
+}
+
+impl Recording {
+    fn add_track(&mut self, channel: Option<usize>, path: PathBuf, sample_rate: u32) {
+        match wav::WavWriter::create(&path, sample_rate) {
+            Ok(writer) => self.tracks.push(Track {
+                channel,
+                path,
+                writer,
+            }),
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    // drains the APU into the files, dropping any that fail
+    fn write(&mut self, gameboy: &mut gameboy::Gameboy) {
+        let apu = gameboy.bus.apu_mut();
+        self.tracks.retain_mut(|track| {
+            let samples = match track.channel {
+                Some(channel) => apu.take_channel_samples(channel),
+                None => apu.take_samples(),
+            };
+            let result = track.writer.write_samples(&samples);
+            if let Err(err) = &result {
+                eprintln!("{}: {}", track.path.display(), err);
+            }
+            result.is_ok()
+        });
+        if let Some((path, log)) = &mut self.register_log {
+            let result = apu
+                .take_register_log()
+                .iter()
+                .try_for_each(|write| writeln!(log, "{}", write));
+            if let Err(err) = result {
+                eprintln!("{}: {}", path.display(), err);
+                self.register_log = None;
+                apu.set_register_logging(false);
+            }
+        }
+    }
+
+    fn finish(self) {
+        for track in self.tracks {
+            if let Err(err) = track.writer.finish() {
+                eprintln!("{}: {}", track.path.display(), err);
+            }
+        }
+        if let Some((path, mut log)) = self.register_log {
+            if let Err(err) = log.flush() {
+                eprintln!("{}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+fn write_serial(gameboy: &mut gameboy::Gameboy) {
+    let output = gameboy::take_serial_output(gameboy);
+    if !output.is_empty() {
+        let mut stdout = io::stdout().lock();
+        // stdout going away isn't worth stopping the game for
+        let _ = stdout.write_all(&output).and_then(|_| stdout.flush());
+    }
+}
+
+fn play_input(gameboy: &mut gameboy::Gameboy, script: &Option<InputScript>, frame: u64) {
+    if let Some(script) = script {
+        gameboy::set_input(gameboy, script.state_at(frame));
+    }
+}
+
+// set once q is entered on stdin, so the loop can stop and save instead of
+// being killed
+fn watch_for_quit() -> Arc<AtomicBool> {
+    let quit = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&quit);
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            match line {
+                Ok(line) if line.trim() == "q" => {
+                    flag.store(true, Ordering::Relaxed);
+                    break;
+                }
+                Ok(_) => {}
+                // stdin can't be read, only a kill stops it then
+                Err(_) => break,
+            }
+        }
+    });
+    quit
+}
+
+// the value after a flag, leaving it out is a usage error
+fn flag_value<'a>(iter: &mut impl Iterator<Item = &'a String>, usage: &str) -> &'a str {
+    match iter.next() {
+        Some(value) => value,
+        None => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    }
+}
+
+// "1,3" to channel indexes 0 and 2
+fn parse_channels(list: &str) -> Option<Vec<usize>> {
+    list.split(',')
+        .map(|channel| match channel.parse::<usize>() {
+            Ok(n @ 1..=apu::CHANNELS) => Some(n - 1),
+            _ => None,
+        })
+        .collect()
+}
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = format!(
+        "usage: {} [--rtc-wall-clock] [--boot-rom <file>] [--model dmg0|dmg|mgb|sgb|sgb2] [--frames <n>] [--screenshot <file>] [--pixel-fifo] [--wav <file>] [--wav-channels] [--sample-rate <hz>] [--mute <1-4,...>] [--apu-log <file>] [--input <file>] [--serial] [--link-listen <address>] [--link-connect <address>] <rom>",
+        args[0]
+    );
+    let mut rom_path = None;
+    let mut rtc_wall_clock = false;
+    let mut boot_rom_path = None;
+    let mut model = gameboy::Model::Dmg;
+    let mut frames = None;
+    let mut screenshot_path = None;
+    let mut renderer = ppu::Renderer::Scanline;
+    let mut wav_path = None;
+    let mut wav_channels = false;
+    let mut muted = Vec::new();
+    let mut apu_log_path = None;
+    let mut input_path = None;
+    let mut print_serial = false;
+    let mut link_address = None;
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // run the cartridge clock off the host's time instead of emulated cycles
+            "--rtc-wall-clock" => rtc_wall_clock = true,
+            // without a boot ROM the emulator starts in the model's post-boot state
+            "--boot-rom" => boot_rom_path = Some(flag_value(&mut iter, &usage)),
+            "--model" => {
+                model = match iter.next().and_then(|name| gameboy::Model::from_name(name)) {
+                    Some(model) => model,
+                    None => {
+                        eprintln!("{}", usage);
+                        process::exit(1);
+                    }
+                }
+            }
+            // run this many frames as fast as possible and exit, for machines
+            // without a display
+            "--frames" => {
+                frames = match iter.next().and_then(|n| n.parse::<u64>().ok()) {
+                    Some(n) => Some(n),
+                    None => {
+                        eprintln!("{}", usage);
+                        process::exit(1);
+                    }
+                }
+            }
+            // saved when the run ends, after --frames or when q is entered,
+            // PNG unless the file ends in .ppm
+            "--screenshot" => screenshot_path = Some(Path::new(flag_value(&mut iter, &usage))),
+            // draw a pixel per dot, for games that change registers mid-line
+            "--pixel-fifo" => renderer = ppu::Renderer::PixelFifo,
+            // records the sound to a 16 bit stereo WAV file, which is only
+            // complete once the run ends, after --frames or when q is entered
+            "--wav" => wav_path = Some(Path::new(flag_value(&mut iter, &usage))),
+            // also writes each channel to <file>.ch1.wav to <file>.ch4.wav
+            "--wav-channels" => wav_channels = true,
+            // channels left out of the mix, like 1,3
+            "--mute" => {
+                muted = match iter.next().map(|list| parse_channels(list)) {
+                    Some(Some(channels)) => channels,
+                    _ => {
+                        eprintln!("{}", usage);
+                        process::exit(1);
+                    }
+                }
+            }
+            // every sound register write, timestamped in cycles, flushed when
+            // the run ends like --wav
+            "--apu-log" => apu_log_path = Some(Path::new(flag_value(&mut iter, &usage))),
+            // presses buttons from a script, see joypad::script for the format,
+            // without --frames the run ends after the script's last press
+            "--input" => input_path = Some(Path::new(flag_value(&mut iter, &usage))),
+            // prints what the game sends over the link cable
+            "--serial" => print_serial = true,
+            // a link cable to another emulator, over host:port or unix:<path>
+            "--link-listen" => link_address = Some((true, flag_value(&mut iter, &usage))),
+            "--link-connect" => link_address = Some((false, flag_value(&mut iter, &usage))),
+            "--sample-rate" => {
+                sample_rate = match iter.next().and_then(|n| n.parse::<u32>().ok()) {
+                    Some(n) if n > 0 && n <= apu::MAX_SAMPLE_RATE => n,
+                    _ => {
+                        eprintln!("{}", usage);
+                        process::exit(1);
+                    }
+                }
+            }
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg),
+            _ => {
+                eprintln!("unknown argument: {}", arg);
+                process::exit(1);
+            }
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        eprintln!("{}", usage);
+        process::exit(1);
+    };
+
+    let mut cartridge = match cartridge::Cartridge::from_file(rom_path) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            eprintln!("{}: {}", rom_path, err);
+            process::exit(1);
+        }
+    };
+    if let Some(rtc) = cartridge.rtc_mut() {
+        rtc.set_wall_clock(rtc_wall_clock);
+    }
+    let save_path = cartridge
+        .has_battery()
+        .then(|| cartridge::save_path(rom_path));
+    if let Some(path) = &save_path {
+        if let Err(err) = cartridge.load_save_file(path) {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+    if let Err(err) = cartridge.verify_global_checksum() {
+        eprintln!("warning: {}", err);
+    }
+    let header = &cartridge.header;
+    println!(
+        "{} ({:?}, {} KiB ROM, {} KiB RAM)",
+        header.title,
+        header.cartridge_type.mbc,
+        header.rom_size / 1024,
+        header.ram_size / 1024
+    );
+
     let mut gameboy = gameboy::create_gameboy();
-    let mut bootloader_file = File::open("bootloader.bin").unwrap();
-    bootloader_file.read(&mut gameboy.ram).unwrap();
-    println!("{:?}", gameboy.ram);
+    gameboy.bus.insert_cartridge(cartridge);
+    gameboy.bus.ppu_mut().set_renderer(renderer);
+    match boot_rom_path {
+        Some(path) => match fs::read(path) {
+            Ok(boot_rom) => gameboy::load_boot_rom(&mut gameboy, boot_rom),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => gameboy::skip_boot_rom(&mut gameboy, model),
+    }
 
-    // loop at 4.2 MHz
-    loop {
-        let start_time = Instant::now();
-        // 16.6 ms as nanoseconds
-        let frame_time = Duration::new(0, 16600000);
-        // cycles are in t-cycles
-        // reference: http://www.codeslinger.co.uk/pages/projects/gameboy/opcodes.html
-        let cycles_per_frame = (cpu::CPU_FREQUENCY / 60.0) as i64;
-        let mut emulated_cycles: i64 = 0;
+    for &channel in &muted {
+        gameboy.bus.apu_mut().set_channel_muted(channel, true);
+    }
+    if print_serial {
+        gameboy::set_serial_capture(&mut gameboy, true);
+    }
+    if wav_channels && wav_path.is_none() {
+        eprintln!("--wav-channels needs --wav");
+        process::exit(1);
+    }
+    let mut recording = Recording::default();
+    if let Some(path) = wav_path {
+        recording.add_track(None, path.to_path_buf(), sample_rate);
+        if wav_channels {
+            for channel in 0..apu::CHANNELS {
+                let path = path.with_extension(format!("ch{}.wav", channel + 1));
+                recording.add_track(Some(channel), path, sample_rate);
+            }
+            gameboy.bus.apu_mut().set_channel_capture(true);
+        }
+        gameboy::set_sample_rate(&mut gameboy, sample_rate);
+    }
+    if let Some(path) = apu_log_path {
+        match fs::File::create(path) {
+            Ok(file) => {
+                recording.register_log = Some((path.to_path_buf(), io::BufWriter::new(file)));
+                gameboy.bus.apu_mut().set_register_logging(true);
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some((listening, address)) = link_address {
+        if listening {
+            println!("waiting for the other Game Boy on {}", address);
+        }
+        let result = if listening {
+            link::listen(address)
+        } else {
+            link::connect(address)
+        };
+        match result {
+            Ok(link) => gameboy::connect_link(&mut gameboy, link),
+            Err(err) => {
+                eprintln!("{}: {}", address, err);
+                process::exit(1);
+            }
+        }
+    }
 
-        while emulated_cycles < cycles_per_frame {
-            emulated_cycles += gameboy::step_cpu(&mut gameboy);
+    let input_script = input_path.map(|path| match InputScript::load(path) {
+        Ok(script) => script,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
         }
+    });
+    // frames run by the loops below, which is what the input script counts
+    let mut frame = 0;
 
-        let elapsed_time = start_time.elapsed();
-        if !(elapsed_time > frame_time) {
-            let remaining_time = frame_time - elapsed_time;
-            thread::sleep(remaining_time);
+    if let Some(frames) = frames {
+        for _ in 0..frames {
+            play_input(&mut gameboy, &input_script, frame);
+            gameboy::run_frame(&mut gameboy);
+            recording.write(&mut gameboy);
+            if print_serial {
+                write_serial(&mut gameboy);
+            }
+            frame += 1;
         }
+    } else {
+        // runs until q is entered, the save is also written as it goes in
+        // case the process is killed instead
+        println!("enter q to quit");
+        let quit = watch_for_quit();
+        let mut last_save = Instant::now();
+        let script_done = |frame| {
+            input_script
+                .as_ref()
+                .is_some_and(|script| frame >= script.end_frame())
+        };
+        // one PPU frame at a time like --frames, so input scripts see the
+        // same frames either way
+        while !quit.load(Ordering::Relaxed) && !script_done(frame) {
+            let start_time = Instant::now();
+            // 16.6 ms as nanoseconds
+            let frame_time = Duration::new(0, 16600000);
+
+            play_input(&mut gameboy, &input_script, frame);
+            gameboy::run_frame(&mut gameboy);
+            recording.write(&mut gameboy);
+            if print_serial {
+                write_serial(&mut gameboy);
+            }
+            frame += 1;
+
+            let elapsed_time = start_time.elapsed();
+            if elapsed_time <= frame_time {
+                let remaining_time = frame_time - elapsed_time;
+                thread::sleep(remaining_time);
+            }
+
+            if let Some(path) = &save_path {
+                let dirty = gameboy.bus.cartridge().is_some_and(|c| c.ram_dirty());
+                if dirty && last_save.elapsed() >= SAVE_INTERVAL {
+                    write_save(&mut gameboy, path);
+                    last_save = Instant::now();
+                }
+            }
+        }
+    }
+
+    recording.finish();
+    if let Some(path) = screenshot_path {
+        if let Err(err) = gameboy::save_screenshot(&gameboy, path) {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+    if let Some(path) = &save_path {
+        write_save(&mut gameboy, path);
     }
 }
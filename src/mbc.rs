@@ -0,0 +1,158 @@
+// Reference: https://gbdev.io/pandocs/MBCs.html
+
+use crate::cartridge::{Header, Mbc};
+
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+// the memory bank controller inside a cartridge, it decides which part of
+// the ROM and RAM chips the CPU sees
+pub enum Controller {
+    RomOnly,
+    Mbc1(mbc1::Mbc1),
+    Mbc2(mbc2::Mbc2),
+    Mbc3(mbc3::Mbc3),
+    Mbc5(mbc5::Mbc5),
+}
+
+impl Controller {
+    // None when the header names a controller that isn't emulated
+    pub fn new(header: &Header, rom: &[u8]) -> Option<Controller> {
+        match header.cartridge_type.mbc {
+            Mbc::None => Some(Controller::RomOnly),
+            Mbc::Mbc1 => Some(Controller::Mbc1(mbc1::Mbc1::new(rom))),
+            Mbc::Mbc2 => Some(Controller::Mbc2(mbc2::Mbc2::default())),
+            Mbc::Mbc3 => Some(Controller::Mbc3(mbc3::Mbc3::new(
+                header.cartridge_type.timer,
+            ))),
+            Mbc::Mbc5 => Some(Controller::Mbc5(mbc5::Mbc5::new(
+                header.cartridge_type.rumble,
+            ))),
+        }
+    }
+
+    // the RAM the cartridge needs, MBC2 has its own which the header doesn't count
+    pub fn ram_size(&self, header: &Header) -> usize {
+        match self {
+            Controller::Mbc2(_) => mbc2::RAM_SIZE,
+            _ => header.ram_size,
+        }
+    }
+
+    // advances anything on the cartridge that runs off the clock
+    pub fn tick(&mut self, cycles: i64) {
+        if let Controller::Mbc3(mbc) = self {
+            mbc.tick(cycles);
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&mbc3::Rtc> {
+        match self {
+            Controller::Mbc3(mbc) => mbc.rtc(),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut mbc3::Rtc> {
+        match self {
+            Controller::Mbc3(mbc) => mbc.rtc_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn rumble_active(&self) -> bool {
+        match self {
+            Controller::Mbc5(mbc) => mbc.rumble_active(),
+            _ => false,
+        }
+    }
+
+    // 0x0000-0x7FFF
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match self {
+            Controller::RomOnly => rom_byte(rom, (addr as usize) / ROM_BANK_SIZE, addr),
+            Controller::Mbc1(mbc) => mbc.read_rom(rom, addr),
+            Controller::Mbc2(mbc) => mbc.read_rom(rom, addr),
+            Controller::Mbc3(mbc) => mbc.read_rom(rom, addr),
+            Controller::Mbc5(mbc) => mbc.read_rom(rom, addr),
+        }
+    }
+
+    // writes to ROM set the controller's registers
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match self {
+            Controller::RomOnly => {}
+            Controller::Mbc1(mbc) => mbc.write_rom(addr, value),
+            Controller::Mbc2(mbc) => mbc.write_rom(addr, value),
+            Controller::Mbc3(mbc) => mbc.write_rom(addr, value),
+            Controller::Mbc5(mbc) => mbc.write_rom(addr, value),
+        }
+    }
+
+    // 0xA000-0xBFFF
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self {
+            Controller::RomOnly => ram_byte(ram, 0, addr).copied().unwrap_or(0xFF),
+            Controller::Mbc1(mbc) => mbc.read_ram(ram, addr),
+            Controller::Mbc2(mbc) => mbc.read_ram(ram, addr),
+            Controller::Mbc3(mbc) => mbc.read_ram(ram, addr),
+            Controller::Mbc5(mbc) => mbc.read_ram(ram, addr),
+        }
+    }
+
+    // true when the write changed the RAM, not when it's disabled or the
+    // write went to a clock register
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self {
+            Controller::RomOnly => write_ram_byte(ram, 0, addr, value),
+            Controller::Mbc1(mbc) => mbc.write_ram(ram, addr, value),
+            Controller::Mbc2(mbc) => mbc.write_ram(ram, addr, value),
+            Controller::Mbc3(mbc) => mbc.write_ram(ram, addr, value),
+            Controller::Mbc5(mbc) => mbc.write_ram(ram, addr, value),
+        }
+    }
+}
+
+// the byte at addr within a ROM bank, bank numbers past the end of the ROM
+// wrap around like the unconnected address lines on a real cartridge
+pub fn rom_byte(rom: &[u8], bank: usize, addr: u16) -> u8 {
+    if rom.is_empty() {
+        return 0xFF;
+    }
+    let offset = bank * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1));
+    rom[offset % rom.len()]
+}
+
+// the byte at addr within a RAM bank, None when the cartridge has no RAM
+pub fn ram_byte(ram: &[u8], bank: usize, addr: u16) -> Option<&u8> {
+    if ram.is_empty() {
+        return None;
+    }
+    let offset = bank * RAM_BANK_SIZE + (addr as usize & (RAM_BANK_SIZE - 1));
+    ram.get(offset % ram.len())
+}
+
+pub fn ram_byte_mut(ram: &mut [u8], bank: usize, addr: u16) -> Option<&mut u8> {
+    if ram.is_empty() {
+        return None;
+    }
+    let offset = bank * RAM_BANK_SIZE + (addr as usize & (RAM_BANK_SIZE - 1));
+    let len = ram.len();
+    ram.get_mut(offset % len)
+}
+
+// true when the byte had a different value
+pub fn write_ram_byte(ram: &mut [u8], bank: usize, addr: u16, value: u8) -> bool {
+    match ram_byte_mut(ram, bank, addr) {
+        Some(byte) if *byte != value => {
+            *byte = value;
+            true
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,218 @@
+// Reference: https://gbdev.io/pandocs/MBC1.html
+
+use crate::mbc::{ram_byte, rom_byte, write_ram_byte};
+
+// where the Nintendo logo sits in a cartridge header
+const LOGO_START: usize = 0x0104;
+const LOGO_END: usize = 0x0134;
+
+pub struct Mbc1 {
+    ram_enabled: bool,
+    // BANK1, the 5 bit ROM bank register at 0x2000-0x3FFF
+    bank1: u8,
+    // BANK2, the 2 bit register at 0x4000-0x5FFF, the upper ROM bank bits
+    // or the RAM bank
+    bank2: u8,
+    // 0x6000-0x7FFF, in mode 1 BANK2 also applies to 0x0000-0x3FFF and RAM
+    mode: u8,
+    // MBC1M multicarts wire BANK2 to ROM bank bit 4 instead of bit 5
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: &[u8]) -> Mbc1 {
+        Mbc1 {
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: 0,
+            multicart: is_multicart(rom),
+        }
+    }
+
+    // how far BANK2 is shifted when forming a ROM bank number
+    fn bank2_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn bank1_mask(&self) -> u8 {
+        if self.multicart {
+            0x0F
+        } else {
+            0x1F
+        }
+    }
+
+    pub fn rom_bank_low(&self) -> usize {
+        if self.mode == 1 {
+            (self.bank2 << self.bank2_shift()) as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn rom_bank_high(&self) -> usize {
+        ((self.bank2 << self.bank2_shift()) | (self.bank1 & self.bank1_mask())) as usize
+    }
+
+    pub fn ram_bank(&self) -> usize {
+        if self.mode == 1 {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => rom_byte(rom, self.rom_bank_low(), addr),
+            _ => rom_byte(rom, self.rom_bank_high(), addr),
+        }
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // 0 is treated as 1, this check ignores the ROM size so
+                // bank 0x20 etc. can still be reached through BANK2
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01,
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram_byte(ram, self.ram_bank(), addr)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        self.ram_enabled && write_ram_byte(ram, self.ram_bank(), addr, value)
+    }
+}
+
+// MBC1M carts are 1 MiB with a game (and its own header) every 256 KiB
+pub fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != 0x100000 {
+        return false;
+    }
+    let logo = &rom[LOGO_START..LOGO_END];
+    if logo.iter().all(|&byte| byte == 0) {
+        return false;
+    }
+    // the first game after the menu is at bank 0x10
+    let offset = 0x10 * 0x4000;
+    &rom[offset + LOGO_START..offset + LOGO_END] == logo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a ROM where the first byte of every bank is the bank number
+    fn banked_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * 0x4000];
+        for bank in 0..banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let rom = banked_rom(32);
+        let mut mbc = Mbc1::new(&rom);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 5);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+
+        // bank 0 selects bank 1
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+
+        // only 5 bits are used, then masked down to the ROM size
+        mbc.write_rom(0x3FFF, 0xE3);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 3);
+        mbc.write_rom(0x2000, 0x10);
+        let small = banked_rom(8);
+        assert_eq!(mbc.read_rom(&small, 0x4000), 0);
+    }
+
+    #[test]
+    fn test_large_rom() {
+        // 2 MiB, BANK2 selects bits 5-6 of the ROM bank
+        let rom = banked_rom(128);
+        let mut mbc = Mbc1::new(&rom);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x2000, 0x04);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x44);
+        // 0x20/0x40/0x60 can't be selected, they read as the next bank
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x41);
+
+        // mode 1 also moves the 0x0000-0x3FFF area
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let rom = banked_rom(4);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc1::new(&rom);
+
+        // disabled by default
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        assert_eq!(ram[0], 0x00);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x12);
+
+        // mode 0 always uses RAM bank 0
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x12);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_ram(&mut ram, 0xA000, 0x34);
+        assert_eq!(ram[0x4000], 0x34);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_multicart() {
+        let mut rom = banked_rom(64);
+        for game in 0..4 {
+            let offset = game * 0x40000;
+            rom[offset + LOGO_START..offset + LOGO_END].copy_from_slice(&[0xCE; 0x30]);
+        }
+        let mut mbc = Mbc1::new(&rom);
+        assert!(mbc.multicart);
+
+        // BANK2 is bits 4-5 and BANK1 loses its top bit
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x13);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x13);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x10);
+    }
+}
@@ -0,0 +1,102 @@
+// Reference: https://gbdev.io/pandocs/MBC2.html
+
+use crate::mbc::rom_byte;
+
+// 512 half-bytes of RAM built into the controller
+pub const RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mbc2 {
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr),
+        }
+    }
+
+    // both registers live in 0x0000-0x3FFF, address bit 8 picks which one
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        if addr > 0x3FFF {
+            return;
+        }
+        if addr & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            self.rom_bank = value & 0x0F;
+            if self.rom_bank == 0 {
+                self.rom_bank = 1;
+            }
+        }
+    }
+
+    // only the lower nibble is stored, the upper one reads as 1s and the
+    // 512 bytes repeat through 0xA000-0xBFFF
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram[(addr as usize) & (RAM_SIZE - 1)] | 0xF0
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        let byte = &mut ram[(addr as usize) & (RAM_SIZE - 1)];
+        let changed = self.ram_enabled && *byte != value & 0x0F;
+        if changed {
+            *byte = value & 0x0F;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers() {
+        let mut rom = vec![0; 16 * 0x4000];
+        rom[5 * 0x4000] = 0x05;
+        let mut mbc = Mbc2::default();
+
+        // bit 8 clear, RAM enable
+        mbc.write_rom(0x0000, 0x0A);
+        assert!(mbc.ram_enabled);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x00);
+
+        // bit 8 set, ROM bank
+        mbc.write_rom(0x2100, 0xF5);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x05);
+        assert!(mbc.ram_enabled);
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.rom_bank, 1);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut ram = vec![0; RAM_SIZE];
+        let mut mbc = Mbc2::default();
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[0], 0x02);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xF2);
+        // echoed every 512 bytes
+        assert_eq!(mbc.read_ram(&ram, 0xA200), 0xF2);
+        assert_eq!(mbc.read_ram(&ram, 0xBE00), 0xF2);
+    }
+}
@@ -0,0 +1,514 @@
+// Reference: https://gbdev.io/pandocs/MBC3.html
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cpu::CPU_FREQUENCY;
+use crate::mbc::{ram_byte, rom_byte, write_ram_byte};
+
+// RTC register numbers, selected by writing them to 0x4000-0x5FFF
+pub const RTC_SECONDS: u8 = 0x08;
+pub const RTC_MINUTES: u8 = 0x09;
+pub const RTC_HOURS: u8 = 0x0A;
+pub const RTC_DAYS_LOW: u8 = 0x0B;
+pub const RTC_DAYS_HIGH: u8 = 0x0C;
+
+// the footer other emulators append to MBC3 saves, the live then latched
+// registers as 32 bit little endian words and a 64 bit unix timestamp, older
+// saves have a 32 bit timestamp
+pub const RTC_SAVE_SIZE: usize = 48;
+pub const RTC_SAVE_SIZE_32BIT: usize = 44;
+
+// bits of the day counter's upper register
+const DAYS_HIGH_BIT: u8 = 0x01;
+const HALT_BIT: u8 = 0x40;
+const CARRY_BIT: u8 = 0x80;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// the real time clock, by default it counts emulated cycles so the same
+// inputs always give the same time
+#[derive(Default)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    // 9 bit day counter
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    // what the CPU reads, copied from the counters by a latch
+    latched: [u8; 5],
+    // cycles towards the next second
+    cycles: i64,
+    // when syncing to the host clock, the time the counters were last updated
+    wall_clock: Option<SystemTime>,
+}
+
+impl Rtc {
+    // follow the host's clock instead of emulated cycles
+    pub fn set_wall_clock(&mut self, enabled: bool) {
+        self.wall_clock = if enabled {
+            Some(SystemTime::now())
+        } else {
+            None
+        };
+        self.cycles = 0;
+    }
+
+    pub fn is_wall_clock(&self) -> bool {
+        self.wall_clock.is_some()
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        if self.wall_clock.is_some() || self.halted {
+            return;
+        }
+        let cycles_per_second = CPU_FREQUENCY as i64;
+        self.cycles += cycles;
+        while self.cycles >= cycles_per_second {
+            self.cycles -= cycles_per_second;
+            self.advance_second();
+        }
+    }
+
+    // catches the counters up with the host clock, a no-op in emulated mode
+    pub fn sync(&mut self) {
+        let Some(last) = self.wall_clock else {
+            return;
+        };
+        let now = SystemTime::now();
+        let elapsed = now.duration_since(last).unwrap_or_default().as_secs();
+        if self.halted {
+            self.wall_clock = Some(now);
+            return;
+        }
+        self.advance(elapsed);
+        // keep the part of a second that hasn't passed yet
+        self.wall_clock = Some(last + Duration::from_secs(elapsed));
+    }
+
+    pub fn advance(&mut self, mut seconds: u64) {
+        // counters set past their limit don't carry, so step until they've
+        // rolled over
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.advance_second();
+            seconds -= 1;
+        }
+        let time = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600;
+        let total = time + seconds;
+        let time = total % SECONDS_PER_DAY;
+        self.seconds = (time % 60) as u8;
+        self.minutes = (time / 60 % 60) as u8;
+        self.hours = (time / 3600) as u8;
+        let days = self.days as u64 + total / SECONDS_PER_DAY;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    // each counter only wraps at its limit, a counter set past its limit
+    // counts up to its bit width and rolls over without a carry
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days = (self.days + 1) & 0x1FF;
+        if self.days == 0 {
+            self.day_carry = true;
+        }
+    }
+
+    pub fn latch(&mut self) {
+        self.sync();
+        for (i, register) in (RTC_SECONDS..=RTC_DAYS_HIGH).enumerate() {
+            self.latched[i] = self.register(register);
+        }
+    }
+
+    // the live value of a register
+    pub fn register(&self, register: u8) -> u8 {
+        match register {
+            RTC_SECONDS => self.seconds,
+            RTC_MINUTES => self.minutes,
+            RTC_HOURS => self.hours,
+            RTC_DAYS_LOW => self.days as u8,
+            RTC_DAYS_HIGH => {
+                let mut value = (self.days >> 8) as u8 & DAYS_HIGH_BIT;
+                if self.halted {
+                    value |= HALT_BIT;
+                }
+                if self.day_carry {
+                    value |= CARRY_BIT;
+                }
+                value
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            RTC_SECONDS..=RTC_DAYS_HIGH => self.latched[(register - RTC_SECONDS) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    // writes set the counters directly, the latched copy follows so the
+    // new value can be read back straight away
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.sync();
+        match register {
+            RTC_SECONDS => {
+                self.seconds = value & 0x3F;
+                // writing the seconds restarts the current second
+                self.cycles = 0;
+            }
+            RTC_MINUTES => self.minutes = value & 0x3F,
+            RTC_HOURS => self.hours = value & 0x1F,
+            RTC_DAYS_LOW => self.days = (self.days & 0x100) | value as u16,
+            RTC_DAYS_HIGH => {
+                self.days = (self.days & 0xFF) | ((value & DAYS_HIGH_BIT) as u16) << 8;
+                self.halted = value & HALT_BIT != 0;
+                self.day_carry = value & CARRY_BIT != 0;
+            }
+            _ => return,
+        }
+        self.latched[(register - RTC_SECONDS) as usize] = self.register(register);
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+        for register in RTC_SECONDS..=RTC_DAYS_HIGH {
+            data.extend_from_slice(&(self.register(register) as u32).to_le_bytes());
+        }
+        for value in self.latched {
+            data.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        // in wall clock mode the registers hold the time of the last sync
+        let time = self.wall_clock.unwrap_or_else(SystemTime::now);
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        data
+    }
+
+    // restores a save footer, the clock catches up on the time since the
+    // save when it follows the host's clock
+    pub fn load(&mut self, data: &[u8]) {
+        // only the low byte of each word is used
+        for (i, register) in (RTC_SECONDS..=RTC_DAYS_HIGH).enumerate() {
+            self.write(register, data[i * 4]);
+        }
+        for (i, value) in self.latched.iter_mut().enumerate() {
+            *value = data[(5 + i) * 4];
+        }
+        self.cycles = 0;
+
+        let mut timestamp = [0; 8];
+        let len = data.len().min(RTC_SAVE_SIZE) - 40;
+        timestamp[..len].copy_from_slice(&data[40..40 + len]);
+        let timestamp = u64::from_le_bytes(timestamp);
+        if self.wall_clock.is_some() {
+            self.wall_clock = Some(UNIX_EPOCH + Duration::from_secs(timestamp));
+            self.sync();
+        }
+    }
+}
+
+pub struct Mbc3 {
+    // enables both the RAM and the RTC registers
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x07 maps a RAM bank, 0x08-0x0C an RTC register
+    ram_select: u8,
+    // the previous write to 0x6000-0x7FFF, writing 0 then 1 latches the clock
+    latch: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(timer: bool) -> Mbc3 {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch: 0xFF,
+            rtc: if timer { Some(Rtc::default()) } else { None },
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => rom_byte(rom, 0, addr),
+            _ => rom_byte(rom, self.rom_bank as usize, addr),
+        }
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_select = value,
+            _ => {
+                if self.latch == 0x00 && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch = value;
+            }
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match (self.ram_select, &self.rtc) {
+            (0x00..=0x07, _) => ram_byte(ram, self.ram_select as usize, addr)
+                .copied()
+                .unwrap_or(0xFF),
+            (RTC_SECONDS..=RTC_DAYS_HIGH, Some(rtc)) => rtc.read(self.ram_select),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match (self.ram_select, &mut self.rtc) {
+            (0x00..=0x07, _) => write_ram_byte(ram, self.ram_select as usize, addr, value),
+            (RTC_SECONDS..=RTC_DAYS_HIGH, Some(rtc)) => {
+                rtc.write(self.ram_select, value);
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = CPU_FREQUENCY as i64;
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+    }
+
+    fn read_rtc(mbc: &mut Mbc3, register: u8) -> u8 {
+        mbc.write_rom(0x4000, register);
+        mbc.read_ram(&[], 0xA000)
+    }
+
+    fn write_rtc(mbc: &mut Mbc3, register: u8, value: u8) {
+        mbc.write_rom(0x4000, register);
+        mbc.write_ram(&mut [], 0xA000, value);
+    }
+
+    #[test]
+    fn test_banking() {
+        let mut rom = vec![0; 128 * 0x4000];
+        rom[0x7F * 0x4000] = 0x7F;
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc3::new(false);
+
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank, 1);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[0x6000], 0x12);
+        // no clock on this cartridge
+        mbc.write_rom(0x4000, RTC_SECONDS);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_latch() {
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.tick(3 * SECOND);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 0);
+
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 3);
+        // the latched value holds until the next latch
+        mbc.tick(SECOND);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 3);
+        // writing 1 again without a 0 first does nothing
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 3);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 4);
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+        write_rtc(&mut mbc, RTC_SECONDS, 59);
+        write_rtc(&mut mbc, RTC_MINUTES, 59);
+        write_rtc(&mut mbc, RTC_HOURS, 23);
+        write_rtc(&mut mbc, RTC_DAYS_LOW, 0xFF);
+        write_rtc(&mut mbc, RTC_DAYS_HIGH, 0x00);
+        mbc.tick(SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 0);
+        assert_eq!(read_rtc(&mut mbc, RTC_MINUTES), 0);
+        assert_eq!(read_rtc(&mut mbc, RTC_HOURS), 0);
+        assert_eq!(read_rtc(&mut mbc, RTC_DAYS_LOW), 0x00);
+        assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), 0x01);
+
+        // day 511 overflows into the carry bit, which stays set
+        write_rtc(&mut mbc, RTC_SECONDS, 59);
+        write_rtc(&mut mbc, RTC_MINUTES, 59);
+        write_rtc(&mut mbc, RTC_HOURS, 23);
+        write_rtc(&mut mbc, RTC_DAYS_LOW, 0xFF);
+        write_rtc(&mut mbc, RTC_DAYS_HIGH, 0x01);
+        mbc.tick(2 * SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 1);
+        assert_eq!(read_rtc(&mut mbc, RTC_DAYS_LOW), 0x00);
+        assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), CARRY_BIT);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut rtc = Rtc::default();
+        // counts up to the register's width without carrying
+        rtc.write(RTC_SECONDS, 62);
+        rtc.advance(2);
+        assert_eq!(rtc.register(RTC_SECONDS), 0);
+        assert_eq!(rtc.register(RTC_MINUTES), 0);
+        rtc.write(RTC_HOURS, 0xFF);
+        assert_eq!(rtc.register(RTC_HOURS), 0x1F);
+
+        // an hour to roll over from 31 without a carry, then a day and a minute
+        rtc.advance(3600 + 24 * 3600 + 60);
+        assert_eq!(rtc.register(RTC_HOURS), 0);
+        assert_eq!(rtc.register(RTC_MINUTES), 1);
+        assert_eq!(rtc.register(RTC_DAYS_LOW), 1);
+    }
+
+    #[test]
+    fn test_advance_days() {
+        let mut rtc = Rtc::default();
+        rtc.write(RTC_SECONDS, 30);
+        rtc.write(RTC_HOURS, 23);
+        // 600 days and 45 seconds wraps the day counter once
+        rtc.advance(600 * 24 * 3600 + 45);
+        assert_eq!(rtc.register(RTC_SECONDS), 15);
+        assert_eq!(rtc.register(RTC_MINUTES), 1);
+        assert_eq!(rtc.register(RTC_HOURS), 23);
+        assert_eq!(rtc.register(RTC_DAYS_LOW), (600 - 512) as u8);
+        assert_eq!(rtc.register(RTC_DAYS_HIGH), CARRY_BIT);
+
+        // a century is still quick
+        rtc.advance(100 * 365 * 24 * 3600);
+        assert_eq!(rtc.register(RTC_SECONDS), 15);
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+        write_rtc(&mut mbc, RTC_DAYS_HIGH, HALT_BIT);
+        mbc.tick(10 * SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 0);
+        assert_eq!(read_rtc(&mut mbc, RTC_DAYS_HIGH), HALT_BIT);
+
+        write_rtc(&mut mbc, RTC_DAYS_HIGH, 0x00);
+        mbc.tick(SECOND);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, RTC_SECONDS), 1);
+    }
+
+    #[test]
+    fn test_save() {
+        let mut rtc = Rtc::default();
+        rtc.write(RTC_MINUTES, 12);
+        rtc.write(RTC_DAYS_HIGH, 0x81);
+        rtc.latch();
+        rtc.write(RTC_SECONDS, 34);
+        let data = rtc.save();
+        assert_eq!(data.len(), RTC_SAVE_SIZE);
+        assert_eq!(data[0..4], [34, 0, 0, 0]);
+        assert_eq!(data[16..20], [0x81, 0, 0, 0]);
+
+        let mut loaded = Rtc::default();
+        loaded.load(&data);
+        for register in RTC_SECONDS..=RTC_DAYS_HIGH {
+            assert_eq!(loaded.register(register), rtc.register(register));
+            assert_eq!(loaded.read(register), rtc.read(register));
+        }
+
+        // the clock was off for an hour
+        let mut data = data[..RTC_SAVE_SIZE_32BIT].to_vec();
+        let timestamp = SystemTime::now() - Duration::from_secs(3600);
+        let timestamp = timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        data[40..44].copy_from_slice(&(timestamp as u32).to_le_bytes());
+        let mut loaded = Rtc::default();
+        loaded.set_wall_clock(true);
+        loaded.load(&data);
+        assert_eq!(loaded.register(RTC_MINUTES), 12);
+        assert_eq!(loaded.register(RTC_HOURS), 1);
+    }
+
+    #[test]
+    fn test_wall_clock() {
+        let mut rtc = Rtc::default();
+        rtc.set_wall_clock(true);
+        // emulated cycles are ignored
+        rtc.tick(10 * SECOND);
+        rtc.latch();
+        assert_eq!(rtc.read(RTC_SECONDS), 0);
+
+        // pretend the host clock moved on by 90 seconds
+        rtc.wall_clock = Some(SystemTime::now() - Duration::from_secs(90));
+        rtc.latch();
+        assert_eq!(rtc.read(RTC_SECONDS), 30);
+        assert_eq!(rtc.read(RTC_MINUTES), 1);
+    }
+}
@@ -0,0 +1,121 @@
+// Reference: https://gbdev.io/pandocs/MBC5.html
+
+use crate::mbc::{ram_byte, rom_byte, write_ram_byte};
+
+// on rumble carts bit 3 of the RAM bank register drives the motor
+const RUMBLE_BIT: u8 = 0x08;
+
+pub struct Mbc5 {
+    ram_enabled: bool,
+    // 9 bits, split over 0x2000-0x2FFF (low 8) and 0x3000-0x3FFF (bit 8)
+    rom_bank: u16,
+    ram_bank: u8,
+    rumble: bool,
+    rumble_active: bool,
+}
+
+impl Mbc5 {
+    pub fn new(rumble: bool) -> Mbc5 {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble,
+            rumble_active: false,
+        }
+    }
+
+    // whether the game has the rumble motor switched on
+    pub fn rumble_active(&self) -> bool {
+        self.rumble_active
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => rom_byte(rom, 0, addr),
+            // unlike earlier controllers bank 0 can be mapped here
+            _ => rom_byte(rom, self.rom_bank as usize, addr),
+        }
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value & 0x01) as u16) << 8
+            }
+            0x4000..=0x5FFF => {
+                if self.rumble {
+                    self.rumble_active = value & RUMBLE_BIT != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram_byte(ram, self.ram_bank as usize, addr)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        self.ram_enabled && write_ram_byte(ram, self.ram_bank as usize, addr, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_banking() {
+        let mut rom = vec![0; 512 * 0x4000];
+        for bank in [0x000, 0x0FF, 0x100, 0x1FF] {
+            rom[bank * 0x4000] = (bank >> 1) as u8;
+        }
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0xFF);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x80);
+        // bank 0 is not remapped
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(mbc.rom_bank, 0);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mbc5::new(false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[0x1E000], 0x12);
+        assert!(!mbc.rumble_active());
+    }
+
+    #[test]
+    fn test_rumble() {
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc5::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(mbc.rumble_active());
+        // the motor bit isn't part of the RAM bank
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[0x6000], 0x12);
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble_active());
+    }
+}
@@ -0,0 +1,1040 @@
+// Reference: https://gbdev.io/pandocs/Rendering.html
+// Reference: https://gbdev.io/pandocs/STAT.html
+// Reference: https://gbdev.io/pandocs/Tile_Data.html
+
+use crate::interrupt;
+
+mod fifo;
+
+// LCD registers
+pub const LCDC_REGISTER: u16 = 0xFF40; // LCD control
+pub const STAT_REGISTER: u16 = 0xFF41; // LCD status
+pub const SCY_REGISTER: u16 = 0xFF42; // background scroll
+pub const SCX_REGISTER: u16 = 0xFF43;
+pub const LY_REGISTER: u16 = 0xFF44; // current line, read only
+pub const LYC_REGISTER: u16 = 0xFF45; // line compare
+pub const BGP_REGISTER: u16 = 0xFF47; // palettes
+pub const OBP0_REGISTER: u16 = 0xFF48;
+pub const OBP1_REGISTER: u16 = 0xFF49;
+pub const WY_REGISTER: u16 = 0xFF4A; // window position
+pub const WX_REGISTER: u16 = 0xFF4B;
+
+// LCDC bits
+const LCDC_ENABLE: u8 = 0x80;
+const LCDC_WINDOW_MAP: u8 = 0x40;
+const LCDC_WINDOW_ENABLE: u8 = 0x20;
+// tile data at 0x8000 with unsigned tile numbers, otherwise signed from 0x9000
+const LCDC_TILE_DATA: u8 = 0x10;
+const LCDC_BG_MAP: u8 = 0x08;
+const LCDC_OBJ_SIZE: u8 = 0x04;
+const LCDC_OBJ_ENABLE: u8 = 0x02;
+// on the DMG this turns off both the background and the window
+const LCDC_BG_ENABLE: u8 = 0x01;
+
+// object attribute bits
+const OBJ_BG_PRIORITY: u8 = 0x80;
+const OBJ_Y_FLIP: u8 = 0x40;
+const OBJ_X_FLIP: u8 = 0x20;
+const OBJ_PALETTE: u8 = 0x10;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// one shade per pixel, 0 (white) to 3 (black), after the palettes
+pub type Framebuffer = [u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+// STAT interrupt sources
+const STAT_HBLANK: u8 = 0x08;
+const STAT_VBLANK: u8 = 0x10;
+const STAT_OAM: u8 = 0x20;
+const STAT_LYC: u8 = 0x40;
+
+// timing, in dots (one per t-cycle)
+pub const DOTS_PER_LINE: u16 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+pub const VISIBLE_LINES: u8 = 144;
+const OAM_SCAN_DOTS: u16 = 80;
+// the shortest mode 3, with no scrolling, window or objects
+const DRAWING_DOTS: u16 = 172;
+
+// at most 10 objects are drawn on a line
+const MAX_LINE_OBJECTS: usize = 10;
+
+// how lines are drawn, the scanline renderer draws each line in one go at
+// the end of mode 3, the pixel FIFO draws a pixel per dot so registers
+// changed part way through a line show up where they would on hardware
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Renderer {
+    #[default]
+    Scanline,
+    PixelFifo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+pub struct Ppu {
+    vram: [u8; 0x2000],
+    oam: [u8; 0xA0],
+    lcdc: u8,
+    // only the interrupt enable bits, the rest of STAT is computed
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    mode: Mode,
+    // the line being drawn, which LY stops following part way into line 153
+    line: u8,
+    // dots into the current line
+    dot: u16,
+    // the length of this line's mode 3
+    drawing_dots: u16,
+    // the objects picked by the OAM scan, as OAM indices
+    line_objects: [u8; MAX_LINE_OBJECTS],
+    line_object_count: usize,
+    // the STAT interrupt fires on the rising edge of this, so a source that
+    // is already active blocks the others
+    stat_line: bool,
+    // interrupts for the bus to raise, as IF bits
+    interrupts: u8,
+    framebuffer: Framebuffer,
+    // frames finished so far, counted at the start of VBlank
+    frame: u64,
+    // LY has matched WY this frame, the window can be drawn from now on
+    window_triggered: bool,
+    // the window's own line counter, it only advances on lines that draw it
+    window_line: u8,
+    renderer: Renderer,
+    fifo: fifo::PixelFifo,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu {
+            vram: [0; 0x2000],
+            oam: [0; 0xA0],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            mode: Mode::HBlank,
+            line: 0,
+            dot: 0,
+            drawing_dots: DRAWING_DOTS,
+            line_objects: [0; MAX_LINE_OBJECTS],
+            line_object_count: 0,
+            stat_line: false,
+            interrupts: 0,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame: 0,
+            window_triggered: false,
+            window_line: 0,
+            renderer: Renderer::default(),
+            fifo: fifo::PixelFifo::default(),
+        }
+    }
+}
+
+impl Ppu {
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & LCDC_ENABLE != 0
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    // true once for each interrupt, as IF bits
+    pub fn take_interrupts(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+        interrupts
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        for _ in 0..cycles {
+            self.step_dot();
+        }
+    }
+
+    fn step_dot(&mut self) {
+        if !self.lcd_enabled() {
+            return;
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.line = (self.line + 1) % LINES_PER_FRAME;
+            self.ly = self.line;
+            if self.line == 0 {
+                self.window_triggered = false;
+                self.window_line = 0;
+            }
+        }
+
+        if self.line < VISIBLE_LINES {
+            if self.dot == 0 {
+                self.mode = Mode::OamScan;
+            } else if self.dot == OAM_SCAN_DOTS {
+                if self.ly == self.wy {
+                    self.window_triggered = true;
+                }
+                self.scan_oam();
+                self.drawing_dots = self.drawing_length();
+                self.mode = Mode::Drawing;
+                if self.renderer == Renderer::PixelFifo {
+                    self.start_fifo();
+                }
+            } else if self.mode == Mode::Drawing && self.drawing_finished() {
+                self.mode = Mode::HBlank;
+            }
+        } else if self.line == VISIBLE_LINES && self.dot == 0 {
+            self.mode = Mode::VBlank;
+            self.frame += 1;
+            self.interrupts |= 1 << interrupt::VBLANK;
+        } else if self.line == LINES_PER_FRAME - 1 && self.dot == 4 {
+            // LY wraps to 0 early, during the last line of VBlank
+            self.ly = 0;
+        }
+
+        self.update_stat_line();
+    }
+
+    fn drawing_finished(&mut self) -> bool {
+        match self.renderer {
+            Renderer::Scanline => {
+                if self.dot == OAM_SCAN_DOTS + self.drawing_dots {
+                    self.render_line();
+                    return true;
+                }
+                false
+            }
+            Renderer::PixelFifo => {
+                // the length of mode 3 falls out of the fetcher's timing
+                let finished = self.step_fifo();
+                if finished {
+                    self.drawing_dots = self.dot - OAM_SCAN_DOTS;
+                }
+                finished
+            }
+        }
+    }
+
+    // picks the first 10 objects in OAM that cover the current line
+    fn scan_oam(&mut self) {
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        self.line_object_count = 0;
+        for index in 0..40 {
+            if self.line_object_count == MAX_LINE_OBJECTS {
+                break;
+            }
+            // the Y position is offset by 16 so objects can be partly above
+            // the screen
+            let y = self.oam[index * 4] as u16;
+            let line = self.line as u16 + 16;
+            if line >= y && line < y + height {
+                self.line_objects[self.line_object_count] = index as u8;
+                self.line_object_count += 1;
+            }
+        }
+    }
+
+    // mode 3 gets longer for fine scrolling, the window and each object,
+    // the object penalty is the usual approximation of the fetcher stalling
+    // until the background fetch for that tile is done
+    fn drawing_length(&self) -> u16 {
+        let mut dots = DRAWING_DOTS + (self.scx % 8) as u16;
+        if self.window_visible() {
+            dots += 6;
+        }
+        for &index in &self.line_objects[..self.line_object_count] {
+            let x = self.oam[index as usize * 4 + 1];
+            let offset = x.wrapping_add(self.scx) % 8;
+            dots += 6 + 5u16.saturating_sub(offset as u16);
+        }
+        dots
+    }
+
+    fn window_visible(&self) -> bool {
+        self.lcdc & LCDC_WINDOW_ENABLE != 0 && self.window_triggered && self.wx <= 166
+    }
+
+    // the color number (0-3) of a pixel in a tile
+    fn tile_pixel(&self, tile_addr: usize, x: u8, y: u8) -> u8 {
+        let low = self.vram[tile_addr + y as usize * 2];
+        let high = self.vram[tile_addr + y as usize * 2 + 1];
+        let bit = 7 - x;
+        (((high >> bit) & 1) << 1) | ((low >> bit) & 1)
+    }
+
+    // the color number at a position in the background or window tile map
+    fn tile_map_pixel(&self, map_select: u8, x: u8, y: u8) -> u8 {
+        let map = if self.lcdc & map_select != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        let tile = self.vram[map + (y as usize / 8) * 32 + x as usize / 8];
+        let tile_addr = if self.lcdc & LCDC_TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
+        self.tile_pixel(tile_addr, x % 8, y % 8)
+    }
+
+    // draws the current line into the framebuffer with the registers as
+    // they are at the end of mode 3
+    fn render_line(&mut self) {
+        let row = self.line as usize * SCREEN_WIDTH;
+        // color numbers before the palette, objects need them for priority
+        let mut bg_colors = [0u8; SCREEN_WIDTH];
+
+        if self.lcdc & LCDC_BG_ENABLE != 0 {
+            let y = self.line.wrapping_add(self.scy);
+            for (x, color) in bg_colors.iter_mut().enumerate() {
+                *color = self.tile_map_pixel(LCDC_BG_MAP, (x as u8).wrapping_add(self.scx), y);
+            }
+
+            if self.window_visible() {
+                // WX is offset by 7, values below 7 start off the left edge
+                let start = self.wx as isize - 7;
+                for (x, color) in bg_colors.iter_mut().enumerate().skip(start.max(0) as usize) {
+                    let window_x = (x as isize - start) as u8;
+                    *color = self.tile_map_pixel(LCDC_WINDOW_MAP, window_x, self.window_line);
+                }
+                self.window_line += 1;
+            }
+        }
+
+        for (x, &color) in bg_colors.iter().enumerate() {
+            self.framebuffer[row + x] = shade(self.bgp, color);
+        }
+
+        if self.lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.render_objects(&bg_colors);
+        }
+    }
+
+    fn render_objects(&mut self, bg_colors: &[u8; SCREEN_WIDTH]) {
+        let row = self.line as usize * SCREEN_WIDTH;
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+
+        // the object with the smaller X wins, then the one earlier in OAM
+        let mut objects = self.line_objects;
+        let objects = &mut objects[..self.line_object_count];
+        objects.sort_by_key(|&index| self.oam[index as usize * 4 + 1]);
+
+        // pixels already taken by a higher priority object, even when it's
+        // hidden behind the background
+        let mut taken = [false; SCREEN_WIDTH];
+        for &index in objects.iter() {
+            let entry = &self.oam[index as usize * 4..index as usize * 4 + 4];
+            let (y, x, mut tile, attributes) = (entry[0], entry[1], entry[2], entry[3]);
+
+            // LCDC and OAM can change after the scan, so the row can be
+            // outside the object by now
+            let mut tile_y = (self.line + 16).wrapping_sub(y) % height;
+            if attributes & OBJ_Y_FLIP != 0 {
+                tile_y = height - 1 - tile_y;
+            }
+            if height == 16 {
+                // 8x16 objects ignore bit 0, the bottom half is the next tile
+                tile &= 0xFE;
+            }
+            let palette = if attributes & OBJ_PALETTE != 0 {
+                self.obp1
+            } else {
+                self.obp0
+            };
+
+            for pixel in 0..8u8 {
+                // X is offset by 8 so objects can be partly left of the screen
+                let screen_x = x as isize - 8 + pixel as isize;
+                if !(0..SCREEN_WIDTH as isize).contains(&screen_x) || taken[screen_x as usize] {
+                    continue;
+                }
+                let screen_x = screen_x as usize;
+                let tile_x = if attributes & OBJ_X_FLIP != 0 {
+                    7 - pixel
+                } else {
+                    pixel
+                };
+                // tiles past the first continue into the next one
+                let color = self.tile_pixel(tile as usize * 16, tile_x, tile_y);
+                // color 0 is transparent
+                if color == 0 {
+                    continue;
+                }
+                taken[screen_x] = true;
+                if attributes & OBJ_BG_PRIORITY != 0 && bg_colors[screen_x] != 0 {
+                    continue;
+                }
+                self.framebuffer[row + screen_x] = shade(palette, color);
+            }
+        }
+    }
+
+    fn stat_sources(&self, enabled: u8) -> bool {
+        let mode = match self.mode {
+            Mode::HBlank => enabled & STAT_HBLANK != 0,
+            // on the first VBlank line the OAM source fires as well
+            Mode::VBlank => {
+                enabled & STAT_VBLANK != 0
+                    || (enabled & STAT_OAM != 0 && self.line == VISIBLE_LINES && self.dot == 0)
+            }
+            Mode::OamScan => enabled & STAT_OAM != 0,
+            Mode::Drawing => false,
+        };
+        let lyc = enabled & STAT_LYC != 0 && self.ly == self.lyc;
+        self.lcd_enabled() && (mode || lyc)
+    }
+
+    fn update_stat_line(&mut self) {
+        let line = self.stat_sources(self.stat);
+        if line && !self.stat_line {
+            self.interrupts |= 1 << interrupt::LCD_STAT;
+        }
+        self.stat_line = line;
+    }
+
+    // the CPU can't see VRAM while it's being drawn from
+    fn vram_blocked(&self) -> bool {
+        self.mode == Mode::Drawing
+    }
+
+    // or OAM while it's being scanned or drawn from
+    fn oam_blocked(&self) -> bool {
+        matches!(self.mode, Mode::OamScan | Mode::Drawing)
+    }
+
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        if self.vram_blocked() {
+            return 0xFF;
+        }
+        self.vram[(addr & 0x1FFF) as usize]
+    }
+
+    pub fn write_vram(&mut self, addr: u16, value: u8) {
+        if !self.vram_blocked() {
+            self.vram[(addr & 0x1FFF) as usize] = value;
+        }
+    }
+
+    pub fn read_oam(&self, addr: u16) -> u8 {
+        if self.oam_blocked() {
+            return 0xFF;
+        }
+        self.oam[(addr & 0xFF) as usize]
+    }
+
+    pub fn write_oam(&mut self, addr: u16, value: u8) {
+        if !self.oam_blocked() {
+            self.oam[(addr & 0xFF) as usize] = value;
+        }
+    }
+
+    // OAM DMA writes regardless of the mode
+    pub fn write_oam_dma(&mut self, index: usize, value: u8) {
+        self.oam[index] = value;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            LCDC_REGISTER => self.lcdc,
+            STAT_REGISTER => {
+                let coincidence = if self.ly == self.lyc { 0x04 } else { 0x00 };
+                // bit 7 is unused, the mode reads as 0 while the LCD is off
+                0x80 | self.stat | coincidence | self.mode as u8
+            }
+            SCY_REGISTER => self.scy,
+            SCX_REGISTER => self.scx,
+            LY_REGISTER => self.ly,
+            LYC_REGISTER => self.lyc,
+            BGP_REGISTER => self.bgp,
+            OBP0_REGISTER => self.obp0,
+            OBP1_REGISTER => self.obp1,
+            WY_REGISTER => self.wy,
+            WX_REGISTER => self.wx,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            LCDC_REGISTER => {
+                let was_enabled = self.lcd_enabled();
+                self.lcdc = value;
+                if was_enabled != self.lcd_enabled() {
+                    // LY and the mode stay at 0 while the LCD is off, and the
+                    // first line after turning it on skips the OAM scan
+                    self.line = 0;
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.mode = Mode::HBlank;
+                    self.window_triggered = false;
+                    self.window_line = 0;
+                }
+                if !self.lcd_enabled() {
+                    // a disabled LCD shows white
+                    self.framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+                }
+            }
+            STAT_REGISTER => {
+                // on the DMG, writing STAT briefly enables every source, so a
+                // write during HBlank, VBlank or an LY match raises an interrupt
+                if self.stat_sources(STAT_HBLANK | STAT_VBLANK | STAT_LYC) && !self.stat_line {
+                    self.interrupts |= 1 << interrupt::LCD_STAT;
+                }
+                self.stat = value & 0x78;
+            }
+            SCY_REGISTER => self.scy = value,
+            SCX_REGISTER => self.scx = value,
+            LY_REGISTER => {}
+            LYC_REGISTER => self.lyc = value,
+            BGP_REGISTER => self.bgp = value,
+            OBP0_REGISTER => self.obp0 = value,
+            OBP1_REGISTER => self.obp1 = value,
+            WY_REGISTER => self.wy = value,
+            WX_REGISTER => self.wx = value,
+            _ => return,
+        }
+        self.update_stat_line();
+    }
+}
+
+// maps a color number through a palette register
+fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.write(LCDC_REGISTER, LCDC_ENABLE);
+        ppu
+    }
+
+    // runs until the start of a line
+    fn run_to_line(ppu: &mut Ppu, line: u8) {
+        while !(ppu.line == line && ppu.dot == 0) {
+            ppu.tick(1);
+        }
+    }
+
+    // runs until the next VBlank, so the whole screen has been drawn
+    fn run_frame(ppu: &mut Ppu) {
+        let frame = ppu.frame();
+        while ppu.frame() == frame {
+            ppu.tick(1);
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u8 {
+        ppu.framebuffer()[y * SCREEN_WIDTH + x]
+    }
+
+    // a ppu with tile 1 solid color 3, tile 2 solid color 1 and the identity
+    // palette everywhere
+    fn with_tiles(lcdc: u8) -> Ppu {
+        let mut ppu = Ppu::default();
+        for row in 0..8 {
+            ppu.vram[16 + row * 2] = 0xFF;
+            ppu.vram[16 + row * 2 + 1] = 0xFF;
+            ppu.vram[32 + row * 2] = 0xFF;
+        }
+        ppu.write(BGP_REGISTER, 0xE4);
+        ppu.write(OBP0_REGISTER, 0xE4);
+        ppu.write(OBP1_REGISTER, 0x1B);
+        ppu.write(LCDC_REGISTER, LCDC_ENABLE | lcdc);
+        ppu
+    }
+
+    #[test]
+    fn test_background() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_BG_ENABLE);
+        ppu.vram[0x1800] = 1;
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 3);
+        assert_eq!(pixel(&ppu, 7, 7), 3);
+        assert_eq!(pixel(&ppu, 8, 0), 0);
+        assert_eq!(pixel(&ppu, 0, 8), 0);
+
+        // scrolling, the map wraps around at 256 pixels
+        ppu.write(SCX_REGISTER, 4);
+        ppu.write(SCY_REGISTER, 252);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+        assert_eq!(pixel(&ppu, 3, 4), 3);
+        assert_eq!(pixel(&ppu, 4, 4), 0);
+
+        // signed tile numbers from 0x9000, tile 1 is at 0x9010
+        ppu.write(LCDC_REGISTER, LCDC_ENABLE | LCDC_BG_ENABLE);
+        ppu.write(SCX_REGISTER, 0);
+        ppu.write(SCY_REGISTER, 0);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+        ppu.vram[0x1010] = 0xFF;
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 1);
+
+        // the BG enable bit blanks it
+        ppu.write(LCDC_REGISTER, LCDC_ENABLE | LCDC_TILE_DATA);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_window() {
+        let mut ppu =
+            with_tiles(LCDC_TILE_DATA | LCDC_BG_ENABLE | LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP);
+        ppu.vram[0x1C00] = 1;
+        ppu.write(WX_REGISTER, 80 + 7);
+        ppu.write(WY_REGISTER, 72);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 79, 72), 0);
+        assert_eq!(pixel(&ppu, 80, 72), 3);
+        assert_eq!(pixel(&ppu, 87, 79), 3);
+        assert_eq!(pixel(&ppu, 80, 80), 0);
+        assert_eq!(pixel(&ppu, 80, 71), 0);
+
+        // the window's line counter doesn't move while it's hidden, so
+        // the window carries on from where it stopped
+        let mut ppu =
+            with_tiles(LCDC_TILE_DATA | LCDC_BG_ENABLE | LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP);
+        ppu.vram[0x1C00] = 1;
+        ppu.write(WY_REGISTER, 0);
+        ppu.write(WX_REGISTER, 7);
+        run_to_line(&mut ppu, 4);
+        ppu.write(WX_REGISTER, 200);
+        run_to_line(&mut ppu, 10);
+        ppu.write(WX_REGISTER, 7);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 3), 3);
+        assert_eq!(pixel(&ppu, 0, 5), 0);
+        // window line 4 is drawn on LY 10
+        assert_eq!(pixel(&ppu, 0, 13), 3);
+        assert_eq!(pixel(&ppu, 0, 14), 0);
+    }
+
+    #[test]
+    fn test_objects() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_OBJ_ENABLE);
+        // tile 3 has only its leftmost column set, in color 3
+        for row in 0..8 {
+            ppu.vram[48 + row * 2] = 0x80;
+            ppu.vram[48 + row * 2 + 1] = 0x80;
+        }
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 3, 0]);
+        ppu.oam[4..8].copy_from_slice(&[16, 20, 3, OBJ_X_FLIP]);
+        ppu.oam[8..12].copy_from_slice(&[32, 8, 3, OBJ_PALETTE]);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 3);
+        assert_eq!(pixel(&ppu, 1, 0), 0);
+        assert_eq!(pixel(&ppu, 19, 0), 3);
+        assert_eq!(pixel(&ppu, 12, 0), 0);
+        // OBP1 maps color 3 to 0
+        assert_eq!(pixel(&ppu, 0, 16), 0);
+    }
+
+    #[test]
+    fn test_object_priority() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_OBJ_ENABLE | LCDC_BG_ENABLE);
+        // tile 1 (color 3) over tile 2 (color 1), the lower X wins
+        ppu.oam[0..4].copy_from_slice(&[16, 12, 2, 0]);
+        ppu.oam[4..8].copy_from_slice(&[16, 8, 1, 0]);
+        // same X, the earlier OAM entry wins
+        ppu.oam[8..12].copy_from_slice(&[32, 8, 2, 0]);
+        ppu.oam[12..16].copy_from_slice(&[32, 8, 1, 0]);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 4, 0), 3);
+        assert_eq!(pixel(&ppu, 8, 0), 1);
+        assert_eq!(pixel(&ppu, 0, 16), 1);
+
+        // behind a non-zero background, in front of color 0
+        ppu.oam[0..16].fill(0);
+        ppu.vram[0x1800] = 2;
+        ppu.oam[0..4].copy_from_slice(&[16, 12, 1, OBJ_BG_PRIORITY]);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 4, 0), 1);
+        assert_eq!(pixel(&ppu, 8, 0), 3);
+    }
+
+    #[test]
+    fn test_object_limit() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_OBJ_ENABLE);
+        for index in 0..11 {
+            ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[16, 8 + index as u8 * 8, 1, 0]);
+        }
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 72, 0), 3);
+        // the 11th object isn't drawn
+        assert_eq!(pixel(&ppu, 80, 0), 0);
+    }
+
+    #[test]
+    fn test_tall_objects() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_OBJ_ENABLE | LCDC_OBJ_SIZE);
+        // tile 3 selects tiles 2 (top) and 3 (bottom, empty)
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 3, 0]);
+        ppu.oam[4..8].copy_from_slice(&[16, 16, 3, OBJ_Y_FLIP]);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 1);
+        assert_eq!(pixel(&ppu, 0, 8), 0);
+        assert_eq!(pixel(&ppu, 8, 0), 0);
+        assert_eq!(pixel(&ppu, 8, 15), 1);
+    }
+
+    #[test]
+    fn test_objects_changed_in_mode_3() {
+        for renderer in [Renderer::Scanline, Renderer::PixelFifo] {
+            let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_OBJ_ENABLE | LCDC_OBJ_SIZE);
+            ppu.set_renderer(renderer);
+            ppu.oam[0..4].copy_from_slice(&[16, 8, 1, OBJ_Y_FLIP]);
+            ppu.oam[4..8].copy_from_slice(&[16, 16, 1, 0]);
+            run_to_line(&mut ppu, 12);
+            ppu.tick(OAM_SCAN_DOTS as i64 + 1);
+            assert_eq!(ppu.mode(), Mode::Drawing);
+            // the objects were picked as 8x16, and the second one moves
+            // below the line, as a game or an OAM DMA can do
+            ppu.write(
+                LCDC_REGISTER,
+                LCDC_ENABLE | LCDC_TILE_DATA | LCDC_OBJ_ENABLE,
+            );
+            ppu.write_oam_dma(4, 30);
+            run_to_line(&mut ppu, 13);
+            assert_eq!(pixel(&ppu, 0, 12), 3);
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut ppu = enabled();
+        run_to_line(&mut ppu, 1);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+        ppu.tick(79);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+        ppu.tick(1);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+        ppu.tick(171);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+        ppu.tick(1);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+        assert_eq!(ppu.read(STAT_REGISTER) & 0x03, 0);
+
+        run_to_line(&mut ppu, 144);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert_eq!(ppu.read(LY_REGISTER), 144);
+    }
+
+    #[test]
+    fn test_frame_timing() {
+        let mut ppu = enabled();
+        ppu.tick(DOTS_PER_LINE as i64 * 144);
+        assert_eq!(ppu.take_interrupts(), 1 << interrupt::VBLANK);
+        ppu.tick(DOTS_PER_LINE as i64 * 154);
+        // one VBlank per 70224 dots
+        assert_eq!(ppu.take_interrupts(), 1 << interrupt::VBLANK);
+        assert_eq!(ppu.ly(), 144);
+    }
+
+    #[test]
+    fn test_line_153() {
+        let mut ppu = enabled();
+        run_to_line(&mut ppu, 153);
+        assert_eq!(ppu.ly(), 153);
+        ppu.tick(4);
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        run_to_line(&mut ppu, 0);
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+    }
+
+    #[test]
+    fn test_drawing_length() {
+        let mut ppu = enabled();
+        ppu.write(SCX_REGISTER, 3);
+        // an object on every line at X 8
+        ppu.oam[0] = 16;
+        ppu.oam[1] = 8;
+        run_to_line(&mut ppu, 0);
+        ppu.tick(OAM_SCAN_DOTS as i64);
+        assert_eq!(ppu.line_object_count, 1);
+        // 3 for SCX, 6 + 2 for the object
+        assert_eq!(ppu.drawing_dots, 172 + 3 + 8);
+    }
+
+    // mode 3's length with the pixel FIFO, measured on line 1
+    fn fifo_drawing_dots(setup: impl Fn(&mut Ppu)) -> u16 {
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(Renderer::PixelFifo);
+        ppu.write(
+            LCDC_REGISTER,
+            LCDC_ENABLE | LCDC_OBJ_ENABLE | LCDC_BG_ENABLE,
+        );
+        setup(&mut ppu);
+        run_to_line(&mut ppu, 1);
+        run_to_line(&mut ppu, 2);
+        ppu.drawing_dots
+    }
+
+    #[test]
+    fn test_fifo_drawing_length() {
+        assert_eq!(fifo_drawing_dots(|_| {}), 172);
+        assert_eq!(fifo_drawing_dots(|ppu| ppu.write(SCX_REGISTER, 3)), 175);
+        // the object lines up with the fetcher, which has a whole tile to
+        // fetch before the object can be
+        assert_eq!(
+            fifo_drawing_dots(|ppu| ppu.oam[0..2].copy_from_slice(&[16, 8])),
+            172 + 11
+        );
+        // half way through a tile the background fetch is nearly done
+        assert_eq!(
+            fifo_drawing_dots(|ppu| ppu.oam[0..2].copy_from_slice(&[16, 12])),
+            172 + 7
+        );
+        // a second object at the same X only waits for its own fetch
+        assert_eq!(
+            fifo_drawing_dots(|ppu| {
+                ppu.oam[0..2].copy_from_slice(&[16, 12]);
+                ppu.oam[4..6].copy_from_slice(&[16, 12]);
+            }),
+            172 + 13
+        );
+        // no penalty with objects off
+        assert_eq!(
+            fifo_drawing_dots(|ppu| {
+                ppu.oam[0..2].copy_from_slice(&[16, 8]);
+                ppu.write(LCDC_REGISTER, LCDC_ENABLE | LCDC_BG_ENABLE);
+            }),
+            172
+        );
+        // the window restarts the fetcher
+        assert_eq!(
+            fifo_drawing_dots(|ppu| {
+                ppu.write(
+                    LCDC_REGISTER,
+                    LCDC_ENABLE | LCDC_WINDOW_ENABLE | LCDC_BG_ENABLE,
+                );
+                ppu.write(WX_REGISTER, 87);
+            }),
+            172 + 7
+        );
+    }
+
+    // a scene with every feature the scanline renderer draws, filled in
+    // from a simple pseudo random generator
+    fn random_scene(renderer: Renderer, seed: u32) -> Ppu {
+        let mut state = seed;
+        let mut random = move || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        };
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(renderer);
+        for byte in ppu.vram.iter_mut() {
+            *byte = random();
+        }
+        for (index, byte) in ppu.oam.iter_mut().enumerate() {
+            *byte = match index % 4 {
+                // keep the objects on screen more often than not
+                0 => random() % 170,
+                1 => random() % 176,
+                _ => random(),
+            };
+        }
+        ppu.write(SCX_REGISTER, random());
+        ppu.write(SCY_REGISTER, random());
+        ppu.write(WX_REGISTER, random() % 176);
+        ppu.write(WY_REGISTER, random() % 150);
+        ppu.write(BGP_REGISTER, random());
+        ppu.write(OBP0_REGISTER, random());
+        ppu.write(OBP1_REGISTER, random());
+        ppu.write(
+            LCDC_REGISTER,
+            LCDC_ENABLE | (random() & 0x7F) | LCDC_BG_ENABLE,
+        );
+        run_frame(&mut ppu);
+        ppu
+    }
+
+    #[test]
+    fn test_fifo_matches_scanline() {
+        // without mid-line changes both renderers draw the same picture
+        for seed in 0..20 {
+            let scanline = random_scene(Renderer::Scanline, seed);
+            let fifo = random_scene(Renderer::PixelFifo, seed);
+            for y in 0..SCREEN_HEIGHT {
+                for x in 0..SCREEN_WIDTH {
+                    assert_eq!(
+                        pixel(&fifo, x, y),
+                        pixel(&scanline, x, y),
+                        "seed {} at ({}, {}), LCDC {:#04X}, SCX {}, WX {}, WY {}",
+                        seed,
+                        x,
+                        y,
+                        fifo.lcdc,
+                        fifo.scx,
+                        fifo.wx,
+                        fifo.wy
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fifo_mid_line() {
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_BG_ENABLE);
+        ppu.set_renderer(Renderer::PixelFifo);
+        ppu.vram[0x1800..0x1820].fill(1);
+        run_to_line(&mut ppu, 1);
+        // 12 dots to the first pixel, then one pixel per dot
+        ppu.tick(OAM_SCAN_DOTS as i64 + 12 + 80);
+        ppu.write(BGP_REGISTER, 0x00);
+        run_to_line(&mut ppu, 2);
+        assert_eq!(pixel(&ppu, 79, 1), 3);
+        assert_eq!(pixel(&ppu, 80, 1), 0);
+        assert_eq!(pixel(&ppu, 80, 0), 3);
+
+        // the scanline renderer only sees the palette at the end of mode 3
+        let mut ppu = with_tiles(LCDC_TILE_DATA | LCDC_BG_ENABLE);
+        ppu.vram[0x1800..0x1820].fill(1);
+        run_to_line(&mut ppu, 1);
+        ppu.tick(OAM_SCAN_DOTS as i64 + 12 + 80);
+        ppu.write(BGP_REGISTER, 0x00);
+        run_to_line(&mut ppu, 2);
+        assert_eq!(pixel(&ppu, 0, 1), 0);
+    }
+
+    #[test]
+    fn test_oam_scan_limit() {
+        let mut ppu = enabled();
+        for index in 0..20 {
+            ppu.oam[index * 4] = 16;
+        }
+        ppu.write(LCDC_REGISTER, LCDC_ENABLE | LCDC_OBJ_SIZE);
+        run_to_line(&mut ppu, 1);
+        ppu.tick(OAM_SCAN_DOTS as i64);
+        assert_eq!(ppu.line_object_count, 10);
+        assert_eq!(ppu.line_objects[9], 9);
+    }
+
+    #[test]
+    fn test_lyc() {
+        let mut ppu = enabled();
+        ppu.write(LYC_REGISTER, 2);
+        ppu.write(STAT_REGISTER, STAT_LYC);
+        run_to_line(&mut ppu, 2);
+        assert_eq!(ppu.read(STAT_REGISTER) & 0x04, 0x04);
+        assert_eq!(ppu.take_interrupts(), 1 << interrupt::LCD_STAT);
+        run_to_line(&mut ppu, 3);
+        assert_eq!(ppu.read(STAT_REGISTER) & 0x04, 0x00);
+    }
+
+    #[test]
+    fn test_stat_blocking() {
+        let mut ppu = enabled();
+        ppu.write(STAT_REGISTER, STAT_HBLANK | STAT_OAM);
+        run_to_line(&mut ppu, 1);
+        ppu.take_interrupts();
+        // the line drops while drawing so HBlank raises it again, the OAM
+        // scan straight after it doesn't
+        run_to_line(&mut ppu, 2);
+        assert_eq!(ppu.take_interrupts(), 1 << interrupt::LCD_STAT);
+
+        // with LYC matching the whole line the line never drops, so only
+        // the first source fires
+        ppu.write(LYC_REGISTER, 3);
+        ppu.write(STAT_REGISTER, STAT_HBLANK | STAT_LYC);
+        run_to_line(&mut ppu, 3);
+        ppu.take_interrupts();
+        ppu.tick(DOTS_PER_LINE as i64 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_vram_blocking() {
+        let mut ppu = enabled();
+        run_to_line(&mut ppu, 1);
+        ppu.write_oam(0xFE00, 0x12);
+        ppu.write_vram(0x8000, 0x34);
+        assert_eq!(ppu.oam[0], 0x00);
+        assert_eq!(ppu.read_vram(0x8000), 0x34);
+        ppu.tick(OAM_SCAN_DOTS as i64);
+        ppu.write_vram(0x8000, 0x56);
+        assert_eq!(ppu.read_vram(0x8000), 0xFF);
+        assert_eq!(ppu.read_oam(0xFE00), 0xFF);
+
+        // the LCD being off frees both
+        ppu.write(LCDC_REGISTER, 0x00);
+        assert_eq!(ppu.read_vram(0x8000), 0x34);
+        ppu.write_oam(0xFE00, 0x12);
+        assert_eq!(ppu.read_oam(0xFE00), 0x12);
+        assert_eq!(ppu.read(LY_REGISTER), 0);
+    }
+
+    #[test]
+    fn test_stat_write_bug() {
+        let mut ppu = enabled();
+        run_to_line(&mut ppu, 144);
+        ppu.take_interrupts();
+        ppu.write(STAT_REGISTER, 0x00);
+        assert_eq!(ppu.take_interrupts(), 1 << interrupt::LCD_STAT);
+    }
+}
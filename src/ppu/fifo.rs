@@ -0,0 +1,296 @@
+// Reference: https://gbdev.io/pandocs/pixel_fifo.html
+// Reference: https://github.com/gbdev/pandocs/blob/master/src/Rendering.md
+
+use std::collections::VecDeque;
+
+use super::*;
+
+// an object fetch stalls the background fetcher for this long
+const OBJECT_FETCH_DOTS: u8 = 6;
+
+// each fetcher step takes 2 dots, pushing waits for the FIFO to empty
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum FetchStep {
+    #[default]
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ObjectPixel {
+    color: u8,
+    attributes: u8,
+}
+
+#[derive(Default)]
+pub(super) struct PixelFifo {
+    // background or window color numbers waiting to be shifted out
+    background: VecDeque<u8>,
+    // object pixels, lined up with the front of the background FIFO
+    objects: VecDeque<ObjectPixel>,
+    step: FetchStep,
+    // the first of the step's 2 dots has passed
+    half_step: bool,
+    // the fetcher's position in the tile map, in tiles
+    tile_x: u8,
+    tile: u8,
+    data_low: u8,
+    data_high: u8,
+    // the first fetch of every line is thrown away
+    first_fetch: bool,
+    // pixels shifted out so far, the next one goes to this column
+    x: u8,
+    // pixels still to be thrown away, for fine scrolling
+    discard: u8,
+    // the fetcher is reading the window's tile map
+    window: bool,
+    // the window was started on this line, so its line counter moves on
+    window_drawn: bool,
+    // line objects that have been fetched already
+    fetched: [bool; MAX_LINE_OBJECTS],
+    // the line object being fetched, and the dots spent on it
+    object_fetch: Option<(usize, u8)>,
+}
+
+impl Ppu {
+    // resets the fetcher at the start of mode 3
+    pub(super) fn start_fifo(&mut self) {
+        let fifo = &mut self.fifo;
+        fifo.background.clear();
+        fifo.objects.clear();
+        fifo.step = FetchStep::Tile;
+        fifo.half_step = false;
+        fifo.tile_x = 0;
+        fifo.first_fetch = true;
+        fifo.x = 0;
+        fifo.discard = self.scx % 8;
+        fifo.window = false;
+        fifo.window_drawn = false;
+        fifo.fetched = [false; MAX_LINE_OBJECTS];
+        fifo.object_fetch = None;
+    }
+
+    // runs mode 3 for one dot, returns true once the line is finished
+    pub(super) fn step_fifo(&mut self) -> bool {
+        if self.fifo.object_fetch.is_none() && self.lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.fifo.object_fetch = self.next_object().map(|slot| (slot, 0));
+        }
+
+        if let Some((slot, dots)) = self.fifo.object_fetch {
+            // the background fetcher gets to the end of its tile first, then
+            // stalls while the object is fetched, no pixels go out meanwhile
+            let ready = !self.fifo.background.is_empty()
+                && matches!(self.fifo.step, FetchStep::DataHigh | FetchStep::Push);
+            if !ready {
+                self.step_fetcher();
+            } else if dots + 1 == OBJECT_FETCH_DOTS {
+                self.fetch_object(slot);
+                self.fifo.fetched[slot] = true;
+                self.fifo.object_fetch = None;
+            } else {
+                self.fifo.object_fetch = Some((slot, dots + 1));
+            }
+            return false;
+        }
+
+        self.step_fetcher();
+        self.shift_pixel();
+        if self.fifo.x as usize == SCREEN_WIDTH {
+            if self.fifo.window_drawn {
+                self.window_line = self.window_line.wrapping_add(1);
+            }
+            return true;
+        }
+        false
+    }
+
+    // the line object the output has reached that hasn't been fetched, the
+    // ones partly off the left edge all match at once and go in X order
+    fn next_object(&self) -> Option<usize> {
+        if self.fifo.discard > 0 {
+            return None;
+        }
+        let object_x = |slot: &usize| self.oam[self.line_objects[*slot] as usize * 4 + 1];
+        (0..self.line_object_count)
+            .filter(|slot| {
+                !self.fifo.fetched[*slot] && object_x(slot) as u16 <= self.fifo.x as u16 + 8
+            })
+            .min_by_key(object_x)
+    }
+
+    fn step_fetcher(&mut self) {
+        // turning the window off part way through a line goes back to the
+        // background
+        if self.fifo.window && self.lcdc & LCDC_WINDOW_ENABLE == 0 {
+            self.fifo.window = false;
+        }
+
+        if self.fifo.step == FetchStep::Push {
+            // the DMG only pushes into an empty FIFO
+            if self.fifo.background.is_empty() {
+                let (low, high) = (self.fifo.data_low, self.fifo.data_high);
+                self.fifo.background.extend(
+                    (0..8)
+                        .rev()
+                        .map(|bit| (((high >> bit) & 1) << 1) | ((low >> bit) & 1)),
+                );
+                self.fifo.tile_x = self.fifo.tile_x.wrapping_add(1);
+                self.fifo.step = FetchStep::Tile;
+            }
+            return;
+        }
+
+        if !self.fifo.half_step {
+            self.fifo.half_step = true;
+            return;
+        }
+        self.fifo.half_step = false;
+
+        match self.fifo.step {
+            FetchStep::Tile => {
+                let (map_select, x, y) = self.fetch_position();
+                let map = if self.lcdc & map_select != 0 {
+                    0x1C00
+                } else {
+                    0x1800
+                };
+                self.fifo.tile = self.vram[map + (y as usize / 8) * 32 + x as usize];
+                self.fifo.step = FetchStep::DataLow;
+            }
+            FetchStep::DataLow => {
+                self.fifo.data_low = self.vram[self.fetch_address()];
+                self.fifo.step = FetchStep::DataHigh;
+            }
+            FetchStep::DataHigh => {
+                self.fifo.data_high = self.vram[self.fetch_address() + 1];
+                self.fifo.step = if self.fifo.first_fetch {
+                    self.fifo.first_fetch = false;
+                    FetchStep::Tile
+                } else {
+                    FetchStep::Push
+                };
+            }
+            FetchStep::Push => {}
+        }
+    }
+
+    // the tile map, the column in tiles and the line in pixels the fetcher
+    // reads from, the registers are read as the fetch happens
+    fn fetch_position(&self) -> (u8, u8, u8) {
+        if self.fifo.window {
+            (LCDC_WINDOW_MAP, self.fifo.tile_x & 0x1F, self.window_line)
+        } else {
+            let x = (self.scx / 8).wrapping_add(self.fifo.tile_x) & 0x1F;
+            (LCDC_BG_MAP, x, self.line.wrapping_add(self.scy))
+        }
+    }
+
+    // the address of the low byte of the current tile's row
+    fn fetch_address(&self) -> usize {
+        let (_, _, y) = self.fetch_position();
+        let tile = self.fifo.tile;
+        let tile_addr = if self.lcdc & LCDC_TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
+        tile_addr + (y as usize % 8) * 2
+    }
+
+    // merges an object's row into the object FIFO, pixels already there
+    // from an earlier object win unless they're transparent
+    fn fetch_object(&mut self, slot: usize) {
+        let index = self.line_objects[slot] as usize;
+        let entry = &self.oam[index * 4..index * 4 + 4];
+        let (y, x, mut tile, attributes) = (entry[0], entry[1], entry[2], entry[3]);
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+
+        let mut tile_y = (self.line + 16).wrapping_sub(y) % height;
+        if attributes & OBJ_Y_FLIP != 0 {
+            tile_y = height - 1 - tile_y;
+        }
+        if height == 16 {
+            tile &= 0xFE;
+        }
+
+        // objects partly off the left edge lose their first pixels
+        let skip = (self.fifo.x + 8).saturating_sub(x);
+        while self.fifo.objects.len() < 8 {
+            self.fifo.objects.push_back(ObjectPixel::default());
+        }
+        for pixel in skip..8 {
+            let tile_x = if attributes & OBJ_X_FLIP != 0 {
+                7 - pixel
+            } else {
+                pixel
+            };
+            let color = self.tile_pixel(tile as usize * 16, tile_x, tile_y);
+            let slot = &mut self.fifo.objects[(pixel - skip) as usize];
+            if slot.color == 0 {
+                *slot = ObjectPixel { color, attributes };
+            }
+        }
+    }
+
+    // shifts one pixel out to the LCD, mixing in the object FIFO
+    fn shift_pixel(&mut self) {
+        if self.fifo.background.is_empty() {
+            return;
+        }
+
+        // the window starts when the output reaches WX, the FIFO is
+        // cleared and the fetcher starts over on the window's tile map
+        if !self.fifo.window && self.window_visible() && self.window_starts() {
+            self.fifo.window = true;
+            self.fifo.window_drawn = true;
+            self.fifo.background.clear();
+            self.fifo.step = FetchStep::Tile;
+            self.fifo.half_step = false;
+            self.fifo.tile_x = 0;
+            // WX below 7 hides the start of the window
+            self.fifo.discard = 7u8.saturating_sub(self.wx);
+            return;
+        }
+
+        let Some(mut color) = self.fifo.background.pop_front() else {
+            return;
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+        let object = self.fifo.objects.pop_front().unwrap_or_default();
+
+        // the palettes and enable bits are read as each pixel goes out
+        if self.lcdc & LCDC_BG_ENABLE == 0 {
+            color = 0;
+        }
+        let mut pixel = shade(self.bgp, color);
+        let hidden = object.attributes & OBJ_BG_PRIORITY != 0 && color != 0;
+        if object.color != 0 && self.lcdc & LCDC_OBJ_ENABLE != 0 && !hidden {
+            let palette = if object.attributes & OBJ_PALETTE != 0 {
+                self.obp1
+            } else {
+                self.obp0
+            };
+            pixel = shade(palette, object.color);
+        }
+
+        self.framebuffer[self.line as usize * SCREEN_WIDTH + self.fifo.x as usize] = pixel;
+        self.fifo.x += 1;
+    }
+
+    fn window_starts(&self) -> bool {
+        if self.wx < 7 {
+            self.fifo.x == 0
+        } else {
+            self.fifo.x as u16 + 7 == self.wx as u16
+        }
+    }
+}
@@ -0,0 +1,535 @@
+// Reference: https://www.w3.org/TR/png/
+// Reference: https://netpbm.sourceforge.net/doc/ppm.html
+
+use std::{fs, io, path::Path};
+
+mod inflate;
+
+use crate::ppu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// the DMG's four shades as gray levels, lightest first
+const GRAY_LEVELS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+// 8 bit grayscale
+const PNG_BIT_DEPTH: u8 = 8;
+const PNG_GRAYSCALE: u8 = 0;
+const PNG_RGB: u8 = 2;
+const PNG_PALETTE: u8 = 3;
+const PNG_GRAYSCALE_ALPHA: u8 = 4;
+const PNG_RGBA: u8 = 6;
+// the largest deflate block that can be stored uncompressed
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Ppm,
+}
+
+impl Format {
+    // picks the format from a file extension, PNG unless it's .ppm
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("ppm") => Format::Ppm,
+            _ => Format::Png,
+        }
+    }
+}
+
+fn gray(shade: u8) -> u8 {
+    GRAY_LEVELS[(shade & 0x03) as usize]
+}
+
+// the shade closest to a gray level, so golden images from other tools
+// with slightly different grays still compare equal
+fn nearest_shade(level: u8) -> u8 {
+    (0..4)
+        .min_by_key(|&shade| (GRAY_LEVELS[shade as usize] as i16 - level as i16).abs())
+        .unwrap_or(0)
+}
+
+fn luminance(red: u8, green: u8, blue: u8) -> u8 {
+    ((red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000) as u8
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// an image read back in, as one shade (0-3) per pixel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub shades: Vec<u8>,
+}
+
+impl Image {
+    // whether the image is exactly the framebuffer
+    pub fn matches(&self, framebuffer: &Framebuffer) -> bool {
+        self.width == SCREEN_WIDTH && self.height == SCREEN_HEIGHT && self.shades == framebuffer
+    }
+}
+
+// a binary (P6) PPM, readable by most image tools without any decoder
+pub fn encode_ppm(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT).into_bytes();
+    for &shade in framebuffer.iter() {
+        data.extend_from_slice(&[gray(shade); 3]);
+    }
+    data
+}
+
+// a grayscale PNG, the image data is stored without compression so it
+// doesn't need a deflate implementation
+pub fn encode_png(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&(SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(SCREEN_HEIGHT as u32).to_be_bytes());
+    // compression, filter and interlace methods are all 0
+    header.extend_from_slice(&[PNG_BIT_DEPTH, PNG_GRAYSCALE, 0, 0, 0]);
+
+    // every row starts with its filter type, 0 is none
+    let mut rows = Vec::with_capacity(SCREEN_HEIGHT * (SCREEN_WIDTH + 1));
+    for row in framebuffer.chunks(SCREEN_WIDTH) {
+        rows.push(0);
+        rows.extend(row.iter().map(|&shade| gray(shade)));
+    }
+
+    let mut data = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut data, b"IHDR", &header);
+    write_chunk(&mut data, b"IDAT", &zlib_stored(&rows));
+    write_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+pub fn encode(framebuffer: &Framebuffer, format: Format) -> Vec<u8> {
+    match format {
+        Format::Png => encode_png(framebuffer),
+        Format::Ppm => encode_ppm(framebuffer),
+    }
+}
+
+// writes the framebuffer in the format the file extension asks for
+pub fn save(framebuffer: &Framebuffer, path: &Path) -> io::Result<()> {
+    fs::write(path, encode(framebuffer, Format::from_path(path)))
+}
+
+// reads a PNG or PPM, for comparing frames against golden images
+pub fn load(path: &Path) -> io::Result<Image> {
+    let data = fs::read(path)?;
+    match Format::from_path(path) {
+        Format::Png => decode_png(&data),
+        Format::Ppm => decode_ppm(&data),
+    }
+}
+
+// a binary PPM with 8 bit samples, as encode_ppm writes
+pub fn decode_ppm(data: &[u8]) -> io::Result<Image> {
+    // the header is 4 whitespace separated fields, comments run to the end
+    // of the line
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while position < data.len() && data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if data.get(position) == Some(&b'#') {
+            while position < data.len() && data[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("PPM header ends early"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+    }
+    // a single whitespace byte separates the header from the pixels
+    position += 1;
+
+    let number = |field: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| invalid("bad PPM header"))
+    };
+    if fields[0] != "P6" || number(&fields[3])? != 255 {
+        return Err(invalid("only 8 bit binary PPMs are supported"));
+    }
+    let (width, height) = (number(&fields[1])?, number(&fields[2])?);
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(|| invalid("PPM is too large"))?;
+    let pixels = data
+        .get(position..)
+        .and_then(|pixels| pixels.get(..size))
+        .ok_or_else(|| invalid("PPM pixels end early"))?;
+    let shades = pixels
+        .chunks(3)
+        .map(|rgb| nearest_shade(luminance(rgb[0], rgb[1], rgb[2])))
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        shades,
+    })
+}
+
+// any non-interlaced PNG
+pub fn decode_png(data: &[u8]) -> io::Result<Image> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err(invalid("not a PNG"));
+    }
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut position = PNG_SIGNATURE.len();
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+        let chunk = data
+            .get(position + 8..position + 8 + length)
+            .ok_or_else(|| invalid("PNG chunk ends early"))?;
+        match chunk_type {
+            b"IHDR" if length == 13 => header = Some(chunk),
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        // skip the CRC
+        position += 12 + length;
+    }
+
+    let header = header.ok_or_else(|| invalid("PNG has no header"))?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let (bit_depth, color_type) = (header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return Err(invalid("interlaced PNGs aren't supported"));
+    }
+    let channels = match color_type {
+        PNG_GRAYSCALE | PNG_PALETTE => 1,
+        PNG_GRAYSCALE_ALPHA => 2,
+        PNG_RGB => 3,
+        PNG_RGBA => 4,
+        _ => return Err(invalid("bad PNG color type")),
+    };
+    // the depths each color type allows
+    let depth_allowed = match color_type {
+        PNG_GRAYSCALE => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        PNG_PALETTE => matches!(bit_depth, 1 | 2 | 4 | 8),
+        _ => matches!(bit_depth, 8 | 16),
+    };
+    if !depth_allowed {
+        return Err(invalid("bad PNG bit depth"));
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid("PNG has no pixels"));
+    }
+    let bits_per_pixel = channels * bit_depth;
+    let too_large = || invalid("PNG is too large");
+    let stride = width
+        .checked_mul(bits_per_pixel)
+        .ok_or_else(too_large)?
+        .div_ceil(8);
+    // each row starts with its filter type
+    let raw_size = height.checked_mul(stride + 1).ok_or_else(too_large)?;
+    let pixels = width.checked_mul(height).ok_or_else(too_large)?;
+    // filters work on whole pixels, or bytes for packed pixels
+    let pixel_bytes = bits_per_pixel.div_ceil(8);
+
+    let raw = inflate::zlib_decompress(&compressed)?;
+    if raw.len() < raw_size {
+        return Err(invalid("PNG image data ends early"));
+    }
+    let mut rows = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for i in 0..stride {
+            let left = if i >= pixel_bytes {
+                rows[y * stride + i - pixel_bytes]
+            } else {
+                0
+            };
+            let up = if y > 0 { rows[(y - 1) * stride + i] } else { 0 };
+            let up_left = if y > 0 && i >= pixel_bytes {
+                rows[(y - 1) * stride + i - pixel_bytes]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid("bad PNG filter")),
+            };
+            rows[y * stride + i] = line[i].wrapping_add(predicted);
+        }
+    }
+
+    // a sample scaled to 8 bits, 16 bit samples keep their high byte
+    let sample = |row: &[u8], index: usize| -> u8 {
+        match bit_depth {
+            8 => row[index],
+            16 => row[index * 2],
+            _ => {
+                let bit = index * bit_depth;
+                let value = (row[bit / 8] >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1);
+                if color_type == PNG_PALETTE {
+                    value
+                } else {
+                    (value as usize * 255 / ((1 << bit_depth) - 1)) as u8
+                }
+            }
+        }
+    };
+    let mut shades = Vec::with_capacity(pixels);
+    for row in rows.chunks(stride) {
+        for x in 0..width {
+            let level = match color_type {
+                PNG_RGB | PNG_RGBA => luminance(
+                    sample(row, x * channels),
+                    sample(row, x * channels + 1),
+                    sample(row, x * channels + 2),
+                ),
+                PNG_PALETTE => {
+                    let entry = sample(row, x) as usize * 3;
+                    let rgb = palette
+                        .get(entry..entry + 3)
+                        .ok_or_else(|| invalid("PNG palette index out of range"))?;
+                    luminance(rgb[0], rgb[1], rgb[2])
+                }
+                _ => sample(row, x * channels),
+            };
+            shades.push(nearest_shade(level));
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        shades,
+    })
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (a, b, c) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if a <= b && a <= c {
+        left
+    } else if b <= c {
+        up
+    } else {
+        up_left
+    }
+}
+
+// length, type, data, then the CRC of the type and data
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary, the check bits make
+    // the header a multiple of 31
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        // an empty final block
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_framebuffer() -> Framebuffer {
+        let mut framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (i, shade) in framebuffer.iter_mut().enumerate() {
+            *shade = (i % 4) as u8;
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_ppm() {
+        let ppm = encode_ppm(&test_framebuffer());
+        let header = b"P6\n160 144\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(
+            &ppm[header.len()..header.len() + 12],
+            &[0xFF, 0xFF, 0xFF, 0xAA, 0xAA, 0xAA, 0x55, 0x55, 0x55, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_png() {
+        let png = encode_png(&test_framebuffer());
+        assert_eq!(&png[..8], &PNG_SIGNATURE);
+        // IHDR
+        assert_eq!(&png[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&png[16..20], &160u32.to_be_bytes());
+        assert_eq!(&png[20..24], &144u32.to_be_bytes());
+        assert_eq!(png[24], PNG_BIT_DEPTH);
+        assert_eq!(png[25], PNG_GRAYSCALE);
+        assert_eq!(
+            &png[png.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+
+        // IDAT holds a single stored block with the rows
+        let rows = SCREEN_HEIGHT * (SCREEN_WIDTH + 1);
+        let idat = &png[33..];
+        assert_eq!(&idat[..4], &((rows + 11) as u32).to_be_bytes());
+        assert_eq!(&idat[4..8], b"IDAT");
+        let stream = &idat[8..8 + rows + 11];
+        assert_eq!(stream[..2], [0x78, 0x01]);
+        assert_eq!(((stream[0] as u16) << 8 | stream[1] as u16) % 31, 0);
+        assert_eq!(stream[2], 0x01);
+        assert_eq!(&stream[3..5], &(rows as u16).to_le_bytes());
+        // the first row's filter byte and pixels
+        assert_eq!(&stream[7..12], &[0x00, 0xFF, 0xAA, 0x55, 0x00]);
+    }
+
+    #[test]
+    fn test_zlib_blocks() {
+        let data = vec![0x12; STORED_BLOCK_SIZE + 1];
+        let stream = zlib_stored(&data);
+        assert_eq!(stream.len(), 2 + 5 + STORED_BLOCK_SIZE + 5 + 1 + 4);
+        assert_eq!(stream[2], 0x00);
+        let second = 2 + 5 + STORED_BLOCK_SIZE;
+        assert_eq!(&stream[second..second + 5], &[0x01, 0x01, 0x00, 0xFE, 0xFF]);
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let framebuffer = test_framebuffer();
+        for format in [Format::Png, Format::Ppm] {
+            let image = match format {
+                Format::Png => decode_png(&encode_png(&framebuffer)),
+                Format::Ppm => decode_ppm(&encode_ppm(&framebuffer)),
+            }
+            .unwrap();
+            assert!(image.matches(&framebuffer));
+        }
+        assert!(decode_png(b"not a png").is_err());
+        assert!(decode_ppm(b"P3\n1 1\n255\n0 0 0").is_err());
+    }
+
+    #[test]
+    fn test_decode_png() {
+        // a 2x2 RGB image with compressed data (fixed huffman codes) and the
+        // sub and up filters, as written by zlib
+        let png = [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x02, 0x00, 0x00,
+            0x00, 0xFD, 0xD4, 0x9A, 0x73, 0x00, 0x00, 0x00, 0x16, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0xDA, 0x63, 0xFC, 0xFF, 0xFF, 0x3F, 0x23, 0x23, 0x23, 0xD3, 0xEA, 0xD5, 0xAB, 0x43,
+            0x43, 0x43, 0x01, 0x30, 0x24, 0x06, 0x04, 0xBF, 0x98, 0xC7, 0x13, 0x00, 0x00, 0x00,
+            0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+        ];
+        let image = decode_png(&png).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.shades, [0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_decode_png_header() {
+        let png = encode_png(&test_framebuffer());
+        // bit depth and color type, the CRC isn't checked
+        for (bit_depth, color_type) in [
+            (0, PNG_GRAYSCALE),
+            (3, PNG_GRAYSCALE),
+            (32, PNG_GRAYSCALE),
+            (16, PNG_PALETTE),
+            (4, PNG_RGB),
+            (1, PNG_RGBA),
+        ] {
+            let mut png = png.clone();
+            png[24] = bit_depth;
+            png[25] = color_type;
+            assert!(decode_png(&png).is_err(), "{} {}", bit_depth, color_type);
+        }
+        let mut png = png.clone();
+        png[16..20].fill(0);
+        assert!(decode_png(&png).is_err());
+        png[16..24].fill(0xFF);
+        png[24] = 16;
+        png[25] = PNG_RGBA;
+        let err = decode_png(&png).unwrap_err();
+        assert_eq!(err.to_string(), "PNG is too large");
+
+        let huge = format!("P6\n{} {}\n255\n", usize::MAX, 2);
+        assert!(decode_ppm(huge.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::from_path(Path::new("shot.PPM")), Format::Ppm);
+        assert_eq!(Format::from_path(Path::new("shot.png")), Format::Png);
+        assert_eq!(Format::from_path(Path::new("shot")), Format::Png);
+    }
+}
@@ -0,0 +1,265 @@
+// Reference: https://www.rfc-editor.org/rfc/rfc1950
+// Reference: https://www.rfc-editor.org/rfc/rfc1951
+
+use std::io;
+
+const MAX_BITS: usize = 15;
+
+// the order the code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+// base lengths and extra bits for length codes 257-285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// base distances and extra bits for distance codes 0-29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// deflate packs bits starting from the least significant
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> io::Result<u16> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| invalid("deflate stream ends early"))?;
+        let bit = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+        Ok(bit as u16)
+    }
+
+    fn bits(&mut self, count: u8) -> io::Result<u16> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// a canonical huffman code, as the number of codes of each length and the
+// symbols in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_BITS {
+            for (symbol, &symbol_length) in lengths.iter().enumerate() {
+                if symbol_length as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    // codes are stored most significant bit first, unlike everything else
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= reader.bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    // the literal and distance lengths are one run length encoded list
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() != literals + distances {
+        return Err(invalid("code lengths overrun"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literal_code: &Huffman,
+    distance_code: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literal_code.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                if code >= LENGTH_BASE.len() {
+                    return Err(invalid("bad length code"));
+                }
+                let length = LENGTH_BASE[code] + reader.bits(LENGTH_EXTRA[code])?;
+                let code = distance_code.decode(reader)? as usize;
+                if code >= DISTANCE_BASE.len() {
+                    return Err(invalid("bad distance code"));
+                }
+                let distance = (DISTANCE_BASE[code] + reader.bits(DISTANCE_EXTRA[code])?) as usize;
+                if distance > output.len() {
+                    return Err(invalid("distance before the start of the output"));
+                }
+                // the copy can overlap what it's writing
+                let start = output.len() - distance;
+                for i in 0..length as usize {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// decompresses a zlib stream, the checksum isn't verified
+pub fn zlib_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    // deflate, with the header check bits making it a multiple of 31
+    let valid = data.len() >= 2
+        && data[0] & 0x0F == 8
+        && ((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31);
+    if !valid {
+        return Err(invalid("not a zlib stream"));
+    }
+    let mut reader = BitReader {
+        data: &data[2..],
+        position: 0,
+        bit: 0,
+    };
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = reader
+                    .data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or_else(|| invalid("deflate stream ends early"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let start = reader.position + 4;
+                let block = reader
+                    .data
+                    .get(start..start + length)
+                    .ok_or_else(|| invalid("deflate stream ends early"))?;
+                output.extend_from_slice(block);
+                reader.position = start + length;
+            }
+            1 => {
+                let (literal_code, distance_code) = fixed_codes();
+                inflate_block(&mut reader, &mut output, &literal_code, &distance_code)?;
+            }
+            2 => {
+                let (literal_code, distance_code) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literal_code, &distance_code)?;
+            }
+            _ => return Err(invalid("bad deflate block type")),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_codes() {
+        // zlib level 9 picks dynamic huffman codes for this
+        let compressed = [
+            0x78, 0xDA, 0xE5, 0xCC, 0x01, 0x11, 0xC0, 0x20, 0x08, 0x00, 0xC0, 0x6C, 0x6C, 0x22,
+            0x70, 0x0A, 0xE2, 0xA1, 0xD0, 0xBF, 0xC9, 0x82, 0xEC, 0x03, 0x3C, 0x70, 0x8F, 0xF4,
+            0x07, 0x2C, 0xAC, 0x60, 0x9D, 0xDD, 0xF0, 0x36, 0xD0, 0xC5, 0x6E, 0x2F, 0x81, 0x48,
+            0xBE, 0xC9, 0x5C, 0x08, 0x53, 0x71, 0xCA, 0x29, 0xAF, 0x1C, 0x26, 0xDB, 0x69, 0x74,
+            0x77, 0x52, 0x89, 0xAB, 0xB1, 0x91, 0xE0, 0x1F, 0xC9, 0x07, 0x4A, 0xB8, 0x58, 0xFE,
+        ];
+        let expected: Vec<u8> = (0..300usize)
+            .map(|i| ((i * i * 7 + i / 3) % 23 + 65) as u8)
+            .collect();
+        assert_eq!(zlib_decompress(&compressed).unwrap(), expected);
+        assert!(zlib_decompress(&compressed[..20]).is_err());
+        assert!(zlib_decompress(&[0x12, 0x34]).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+// Reference: https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+
+pub mod link;
+
+use link::{Link, Message};
+
+pub const SB_REGISTER: u16 = 0xFF01; // serial data, shifted out MSB first
+pub const SC_REGISTER: u16 = 0xFF02; // serial control
+
+const SC_TRANSFER: u8 = 0x80;
+// set to drive the clock, clear to wait for the other Game Boy's
+const SC_INTERNAL_CLOCK: u8 = 0x01;
+// the unused bits read as 1
+const SC_UNUSED: u8 = 0x7E;
+
+// the internal clock runs at 8192 Hz
+const CYCLES_PER_BIT: i64 = 512;
+// how often the two ends of a link cable catch up with each other
+const SYNC_CYCLES: i64 = CYCLES_PER_BIT;
+
+#[derive(Default)]
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    // the bits left in the current transfer
+    bits: u8,
+    timer: i64,
+    // the byte shifted in from the other end, all 1s with nothing plugged in
+    incoming: u8,
+    // every byte sent while capturing, for test ROMs that print their
+    // results
+    output: Option<Vec<u8>>,
+    // a serial interrupt for the bus to raise
+    interrupt: bool,
+    // the other Game Boy, None when nothing is plugged in
+    link: Option<Box<dyn Link>>,
+    // cycles until the next exchange over the link
+    sync_timer: i64,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            incoming: 0xFF,
+            ..Serial::default()
+        }
+    }
+
+    pub fn transferring(&self) -> bool {
+        self.sc & SC_TRANSFER != 0
+    }
+
+    pub fn internal_clock(&self) -> bool {
+        self.sc & SC_INTERNAL_CLOCK != 0
+    }
+
+    // from now on bytes are swapped with the other end of the link
+    pub fn connect(&mut self, link: Box<dyn Link>) {
+        self.link = Some(link);
+        self.sync_timer = SYNC_CYCLES;
+    }
+
+    pub fn connected(&self) -> bool {
+        self.link.is_some()
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        if self.connected() {
+            self.tick_linked(cycles);
+            return;
+        }
+        // an external clock never ticks without another Game Boy
+        if !self.transferring() || !self.internal_clock() {
+            return;
+        }
+        self.timer -= cycles;
+        while self.timer <= 0 && self.transferring() {
+            self.timer += CYCLES_PER_BIT;
+            self.shift_bit();
+        }
+    }
+
+    // with a link the whole byte is swapped at the first exchange after
+    // the transfer has had time to finish
+    fn tick_linked(&mut self, cycles: i64) {
+        if self.transferring() && self.internal_clock() {
+            self.timer -= cycles;
+        }
+        self.sync_timer -= cycles;
+        while self.sync_timer <= 0 {
+            self.sync_timer += SYNC_CYCLES;
+            self.sync();
+        }
+    }
+
+    fn sync(&mut self) {
+        let master = self.transferring() && self.internal_clock() && self.timer <= 0;
+        let slave = self.transferring() && !self.internal_clock();
+        let message = Message {
+            master: master.then_some(self.sb),
+            slave: slave.then_some(self.sb),
+        };
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        let received = match link.exchange(message) {
+            Ok(received) => received,
+            // the other end went away, as if the cable was pulled out
+            Err(_) => {
+                self.link = None;
+                Message::default()
+            }
+        };
+        if master {
+            // a Game Boy that isn't waiting for a transfer sends all 1s
+            self.finish_transfer(received.slave.unwrap_or(0xFF));
+        } else if let (true, Some(byte)) = (slave, received.master) {
+            self.finish_transfer(byte);
+        }
+    }
+
+    fn finish_transfer(&mut self, received: u8) {
+        self.sb = received;
+        self.bits = 0;
+        self.sc &= !SC_TRANSFER;
+        self.interrupt = true;
+    }
+
+    fn shift_bit(&mut self) {
+        let bit = self.incoming >> 7;
+        self.incoming <<= 1;
+        self.sb = self.sb << 1 | bit;
+        self.bits -= 1;
+        if self.bits == 0 {
+            self.finish_transfer(self.sb);
+        }
+    }
+
+    // starts or stops keeping every byte sent
+    pub fn set_capture(&mut self, capture: bool) {
+        self.output = capture.then(Vec::new);
+    }
+
+    // the bytes sent since capturing started
+    pub fn output(&self) -> &[u8] {
+        self.output.as_deref().unwrap_or_default()
+    }
+
+    // the bytes sent since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // true once for every serial interrupt
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            SB_REGISTER => self.sb,
+            SC_REGISTER => self.sc | SC_UNUSED,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            SB_REGISTER => self.sb = value,
+            SC_REGISTER => {
+                self.sc = value & (SC_TRANSFER | SC_INTERNAL_CLOCK);
+                if self.transferring() {
+                    self.bits = 8;
+                    self.timer = if self.connected() {
+                        CYCLES_PER_BIT * 8
+                    } else {
+                        CYCLES_PER_BIT
+                    };
+                    self.incoming = 0xFF;
+                    if let Some(output) = &mut self.output {
+                        output.push(self.sb);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_clock() {
+        let mut serial = Serial::new();
+        serial.set_capture(true);
+        assert_eq!(serial.read(SC_REGISTER), 0x7E);
+        serial.write(SB_REGISTER, b'O');
+        serial.write(SC_REGISTER, 0x81);
+        assert_eq!(serial.read(SC_REGISTER), 0xFF);
+
+        serial.tick(CYCLES_PER_BIT * 4);
+        assert_eq!(serial.read(SB_REGISTER), b'O' << 4 | 0x0F);
+        assert!(serial.transferring());
+        serial.tick(CYCLES_PER_BIT * 4 - 4);
+        assert!(!serial.take_interrupt());
+        serial.tick(4);
+        // nothing on the other end reads as all 1s
+        assert_eq!(serial.read(SB_REGISTER), 0xFF);
+        assert_eq!(serial.read(SC_REGISTER), 0x7F);
+        assert!(serial.take_interrupt());
+        assert!(!serial.take_interrupt());
+        assert_eq!(serial.output(), b"O");
+
+        serial.write(SB_REGISTER, b'k');
+        serial.write(SC_REGISTER, 0x81);
+        assert_eq!(serial.take_output(), b"Ok");
+        assert!(serial.output().is_empty());
+    }
+
+    #[test]
+    fn test_external_clock() {
+        let mut serial = Serial::new();
+        serial.set_capture(true);
+        serial.write(SB_REGISTER, 0x42);
+        serial.write(SC_REGISTER, 0x80);
+        serial.tick(CYCLES_PER_BIT * 16);
+        // still waiting for a clock from the other end
+        assert!(serial.transferring());
+        assert!(!serial.take_interrupt());
+        assert_eq!(serial.read(SB_REGISTER), 0x42);
+        assert_eq!(serial.output(), &[0x42]);
+    }
+
+    #[test]
+    fn test_capture() {
+        // a game polling the link doesn't pile up bytes nobody reads
+        let mut serial = Serial::new();
+        for _ in 0..10 {
+            serial.write(SC_REGISTER, 0x80);
+        }
+        assert!(serial.output().is_empty());
+        serial.set_capture(true);
+        serial.write(SC_REGISTER, 0x80);
+        serial.set_capture(false);
+        assert!(serial.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_link() {
+        let (a, b) = link::MemoryLink::pair();
+        let mut master = Serial::new();
+        let mut slave = Serial::new();
+        master.connect(Box::new(a));
+        slave.connect(Box::new(b));
+        // the slave is ready first
+        slave.write(SB_REGISTER, 0x99);
+        slave.write(SC_REGISTER, 0x80);
+        master.write(SB_REGISTER, 0x42);
+        master.write(SC_REGISTER, 0x81);
+
+        let handle = std::thread::spawn(move || {
+            slave.tick(SYNC_CYCLES * 9);
+            slave
+        });
+        master.tick(SYNC_CYCLES * 8 - 4);
+        assert!(master.transferring());
+        master.tick(4);
+        master.tick(SYNC_CYCLES);
+        let mut slave = handle.join().unwrap();
+        assert_eq!(master.read(SB_REGISTER), 0x99);
+        assert_eq!(slave.read(SB_REGISTER), 0x42);
+        assert!(master.take_interrupt() && slave.take_interrupt());
+
+        // the other end going away unplugs the cable
+        drop(slave);
+        master.tick(SYNC_CYCLES);
+        assert!(!master.connected());
+    }
+}
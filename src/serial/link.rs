@@ -0,0 +1,230 @@
+// the cable between two Game Boys, over a socket or between two threads
+//
+// both ends stop every SYNC_CYCLES and swap a Message saying what their
+// serial port is doing, so a transfer finishes at the same point on both
+// sides however fast each one runs
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+#[cfg(unix)]
+use std::os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+};
+
+// the prefix for addresses that are Unix socket paths instead of host:port
+const UNIX_PREFIX: &str = "unix:";
+
+const MASTER_FLAG: u8 = 0x01;
+const SLAVE_FLAG: u8 = 0x02;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    // the byte sent by a transfer on the internal clock that has finished
+    pub master: Option<u8>,
+    // SB while waiting for the other end's clock
+    pub slave: Option<u8>,
+}
+
+impl Message {
+    fn encode(&self) -> [u8; 3] {
+        let mut flags = 0;
+        if self.master.is_some() {
+            flags |= MASTER_FLAG;
+        }
+        if self.slave.is_some() {
+            flags |= SLAVE_FLAG;
+        }
+        [
+            flags,
+            self.master.unwrap_or(0xFF),
+            self.slave.unwrap_or(0xFF),
+        ]
+    }
+
+    fn decode(bytes: [u8; 3]) -> Message {
+        Message {
+            master: (bytes[0] & MASTER_FLAG != 0).then_some(bytes[1]),
+            slave: (bytes[0] & SLAVE_FLAG != 0).then_some(bytes[2]),
+        }
+    }
+}
+
+pub trait Link: Send {
+    // sends this end's message and waits for the other end's
+    fn exchange(&mut self, message: Message) -> io::Result<Message>;
+}
+
+// a link over a TCP or Unix socket
+pub struct StreamLink<S: Read + Write> {
+    stream: S,
+}
+
+impl<S: Read + Write> StreamLink<S> {
+    pub fn new(stream: S) -> Self {
+        StreamLink { stream }
+    }
+}
+
+impl<S: Read + Write + Send> Link for StreamLink<S> {
+    fn exchange(&mut self, message: Message) -> io::Result<Message> {
+        self.stream.write_all(&message.encode())?;
+        self.stream.flush()?;
+        let mut bytes = [0; 3];
+        self.stream.read_exact(&mut bytes)?;
+        Ok(Message::decode(bytes))
+    }
+}
+
+// waits for the other emulator to connect, the address is host:port or
+// unix:<path>
+pub fn listen(address: &str) -> io::Result<Box<dyn Link>> {
+    if let Some(path) = address.strip_prefix(UNIX_PREFIX) {
+        return listen_unix(path);
+    }
+    let (stream, _) = TcpListener::bind(address)?.accept()?;
+    tcp_link(stream)
+}
+
+pub fn connect(address: &str) -> io::Result<Box<dyn Link>> {
+    if let Some(path) = address.strip_prefix(UNIX_PREFIX) {
+        return connect_unix(path);
+    }
+    tcp_link(TcpStream::connect(address)?)
+}
+
+fn tcp_link(stream: TcpStream) -> io::Result<Box<dyn Link>> {
+    // every exchange is a few bytes that the other end is waiting for
+    stream.set_nodelay(true)?;
+    Ok(Box::new(StreamLink::new(stream)))
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &str) -> io::Result<Box<dyn Link>> {
+    // a socket file left behind by an earlier run is in the way, anything
+    // else at the path is left alone
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "not a socket, refusing to replace it",
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let (stream, _) = UnixListener::bind(path)?.accept()?;
+    Ok(Box::new(StreamLink::new(stream)))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &str) -> io::Result<Box<dyn Link>> {
+    Ok(Box::new(StreamLink::new(UnixStream::connect(path)?)))
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_path: &str) -> io::Result<Box<dyn Link>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets aren't supported here",
+    ))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(path: &str) -> io::Result<Box<dyn Link>> {
+    listen_unix(path)
+}
+
+// one end of a link between two Game Boys in the same process, each has to
+// run on its own thread as an exchange waits for the other end
+pub struct MemoryLink {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+}
+
+impl MemoryLink {
+    pub fn pair() -> (MemoryLink, MemoryLink) {
+        let (sender_a, receiver_b) = mpsc::channel();
+        let (sender_b, receiver_a) = mpsc::channel();
+        (
+            MemoryLink {
+                sender: sender_a,
+                receiver: receiver_a,
+            },
+            MemoryLink {
+                sender: sender_b,
+                receiver: receiver_b,
+            },
+        )
+    }
+}
+
+impl Link for MemoryLink {
+    fn exchange(&mut self, message: Message) -> io::Result<Message> {
+        let disconnected = || io::Error::from(io::ErrorKind::BrokenPipe);
+        self.sender.send(message).map_err(|_| disconnected())?;
+        self.receiver.recv().map_err(|_| disconnected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        for message in [
+            Message::default(),
+            Message {
+                master: Some(0x12),
+                slave: None,
+            },
+            Message {
+                master: None,
+                slave: Some(0xFF),
+            },
+        ] {
+            assert_eq!(Message::decode(message.encode()), message);
+        }
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut link = connect(&address.to_string()).unwrap();
+            link.exchange(Message {
+                master: Some(0x42),
+                slave: None,
+            })
+            .unwrap()
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut link = tcp_link(stream).unwrap();
+        let received = link
+            .exchange(Message {
+                master: None,
+                slave: Some(0x99),
+            })
+            .unwrap();
+        assert_eq!(received.master, Some(0x42));
+        assert_eq!(client.join().unwrap().slave, Some(0x99));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listen_unix_keeps_other_files() {
+        let path = std::env::temp_dir().join(format!("link-{}.txt", std::process::id()));
+        std::fs::write(&path, b"save").unwrap();
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        assert!(listen(&address).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"save");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+// Reference: https://gbdev.io/pandocs/Timer_and_Divider_Registers.html
+// Reference: https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+
+// timer registers
+pub const DIV_REGISTER: u16 = 0xFF04; // upper 8 bits of the divider
+pub const TIMA_REGISTER: u16 = 0xFF05; // timer counter
+pub const TMA_REGISTER: u16 = 0xFF06; // timer modulo, reloaded on overflow
+pub const TAC_REGISTER: u16 = 0xFF07; // timer control
+
+const TAC_ENABLE: u8 = 0x04;
+
+// the timer works in m-cycles
+const MCYCLE: i64 = 4;
+
+#[derive(Default)]
+pub struct Timer {
+    // the 16 bit internal counter, DIV is its upper byte
+    divider: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // TIMA overflowed last m-cycle, it reads 0 until the reload next m-cycle
+    overflow: bool,
+    // this m-cycle reloaded TIMA from TMA, writes to TIMA are ignored
+    reloading: bool,
+    // a timer interrupt for the bus to raise
+    interrupt: bool,
+}
+
+impl Timer {
+    // the divider bit TIMA counts the falling edges of
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0x03 {
+            0 => 9, // 4096 Hz
+            1 => 3, // 262144 Hz
+            2 => 5, // 65536 Hz
+            _ => 7, // 16384 Hz
+        }
+    }
+
+    // the selected divider bit ANDed with the enable bit, TIMA increments
+    // whenever this goes from 1 to 0
+    fn signal(&self) -> bool {
+        self.tac & TAC_ENABLE != 0 && self.divider & (1 << self.selected_bit()) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.overflow = overflow;
+    }
+
+    pub fn tick(&mut self, cycles: i64) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            self.tick_mcycle();
+            remaining -= MCYCLE;
+        }
+    }
+
+    fn tick_mcycle(&mut self) {
+        self.reloading = false;
+        if self.overflow {
+            // the reload and interrupt happen one m-cycle after the overflow
+            self.overflow = false;
+            self.reloading = true;
+            self.tima = self.tma;
+            self.interrupt = true;
+        }
+
+        let before = self.signal();
+        self.divider = self.divider.wrapping_add(MCYCLE as u16);
+        if before && !self.signal() {
+            self.increment_tima();
+        }
+    }
+
+    // true once for every timer interrupt
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    pub fn divider(&self) -> u16 {
+        self.divider
+    }
+
+    // the boot ROM leaves the divider at a model specific value
+    pub fn set_divider(&mut self, value: u16) {
+        self.divider = value;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            DIV_REGISTER => (self.divider >> 8) as u8,
+            TIMA_REGISTER => self.tima,
+            TMA_REGISTER => self.tma,
+            // the upper 5 bits are unused
+            TAC_REGISTER => self.tac | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            DIV_REGISTER => {
+                // any write clears the whole divider, which is a falling
+                // edge if the selected bit was set
+                let before = self.signal();
+                self.divider = 0;
+                if before {
+                    self.increment_tima();
+                }
+            }
+            // a write in the m-cycle after an overflow cancels the reload,
+            // one during the reload is overwritten by TMA
+            TIMA_REGISTER if !self.reloading => {
+                self.tima = value;
+                self.overflow = false;
+            }
+            TMA_REGISTER => {
+                self.tma = value;
+                // TMA is still being copied while reloading
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            TAC_REGISTER => {
+                // disabling the timer or switching to a bit that is 0 can
+                // look like a falling edge (the DMG behaviour)
+                let before = self.signal();
+                self.tac = value & 0x07;
+                if before && !self.signal() {
+                    self.increment_tima();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(tac: u8) -> Timer {
+        let mut timer = Timer::default();
+        timer.write(TAC_REGISTER, TAC_ENABLE | tac);
+        timer
+    }
+
+    #[test]
+    fn test_div() {
+        let mut timer = Timer::default();
+        timer.tick(256);
+        assert_eq!(timer.read(DIV_REGISTER), 1);
+        timer.tick(255 * 256);
+        assert_eq!(timer.read(DIV_REGISTER), 0);
+
+        timer.tick(0x1234);
+        timer.write(DIV_REGISTER, 0x55);
+        assert_eq!(timer.divider(), 0);
+    }
+
+    #[test]
+    fn test_frequencies() {
+        for (tac, period) in [(0, 1024), (1, 16), (2, 64), (3, 256)] {
+            let mut timer = enabled(tac);
+            timer.tick(period - MCYCLE);
+            assert_eq!(timer.read(TIMA_REGISTER), 0);
+            timer.tick(MCYCLE);
+            assert_eq!(timer.read(TIMA_REGISTER), 1);
+        }
+
+        // disabled
+        let mut timer = Timer::default();
+        timer.tick(4096);
+        assert_eq!(timer.read(TIMA_REGISTER), 0);
+        assert_eq!(timer.read(TAC_REGISTER), 0xF8);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut timer = enabled(1);
+        timer.write(TMA_REGISTER, 0x80);
+        timer.write(TIMA_REGISTER, 0xFF);
+        timer.tick(16);
+        // TIMA is 0 for one m-cycle before the reload
+        assert_eq!(timer.read(TIMA_REGISTER), 0x00);
+        assert!(!timer.take_interrupt());
+        timer.tick(MCYCLE);
+        assert_eq!(timer.read(TIMA_REGISTER), 0x80);
+        assert!(timer.take_interrupt());
+        assert!(!timer.take_interrupt());
+    }
+
+    #[test]
+    fn test_overflow_writes() {
+        // writing TIMA right after the overflow cancels the reload
+        let mut timer = enabled(1);
+        timer.write(TMA_REGISTER, 0x80);
+        timer.write(TIMA_REGISTER, 0xFF);
+        timer.tick(16);
+        timer.write(TIMA_REGISTER, 0x12);
+        timer.tick(MCYCLE);
+        assert_eq!(timer.read(TIMA_REGISTER), 0x12);
+        assert!(!timer.take_interrupt());
+
+        // writing TIMA during the reload is ignored, writing TMA goes
+        // through to TIMA as well
+        let mut timer = enabled(1);
+        timer.write(TIMA_REGISTER, 0xFF);
+        timer.tick(16 + MCYCLE);
+        timer.write(TIMA_REGISTER, 0x12);
+        assert_eq!(timer.read(TIMA_REGISTER), 0x00);
+        timer.write(TMA_REGISTER, 0x34);
+        assert_eq!(timer.read(TIMA_REGISTER), 0x34);
+    }
+
+    #[test]
+    fn test_div_write_glitch() {
+        // bit 3 is set halfway through the period, resetting the divider
+        // is a falling edge
+        let mut timer = enabled(1);
+        timer.tick(8);
+        timer.write(DIV_REGISTER, 0);
+        assert_eq!(timer.read(TIMA_REGISTER), 1);
+
+        // no edge when the bit is clear
+        let mut timer = enabled(1);
+        timer.tick(4);
+        timer.write(DIV_REGISTER, 0);
+        assert_eq!(timer.read(TIMA_REGISTER), 0);
+    }
+
+    #[test]
+    fn test_tac_write_glitch() {
+        // disabling the timer while the selected bit is set
+        let mut timer = enabled(1);
+        timer.tick(8);
+        timer.write(TAC_REGISTER, 0x01);
+        assert_eq!(timer.read(TIMA_REGISTER), 1);
+
+        // switching from a set bit (3) to a clear one (9)
+        let mut timer = enabled(1);
+        timer.tick(8);
+        timer.write(TAC_REGISTER, TAC_ENABLE);
+        assert_eq!(timer.read(TIMA_REGISTER), 1);
+    }
+}
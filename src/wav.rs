@@ -0,0 +1,144 @@
+// Reference: http://soundfile.sapp.org/doc/WaveFormat/
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Cursor, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+// the RIFF header, the fmt chunk and the data chunk's header
+const HEADER_SIZE: u32 = 44;
+// where the sizes that aren't known until the end are stored
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+// the RIFF size counts the data and has to fit in 32 bits
+const MAX_DATA_SIZE: u32 = u32::MAX - (HEADER_SIZE - 8);
+
+// 16 bit stereo PCM, written as the samples come in, the sizes in the
+// header are filled in by finish
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let byte_rate = sample_rate * BLOCK_ALIGN as u32;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter {
+            writer,
+            data_size: 0,
+        })
+    }
+
+    // interleaved left and right samples, -1.0 to 1.0
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let data_size = u32::try_from(samples.len() * 2)
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|&size| size <= MAX_DATA_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::FileTooLarge, "WAV size limit reached"))?;
+        for &sample in samples {
+            self.writer.write_all(&to_pcm16(sample).to_le_bytes())?;
+        }
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    // fills in the sizes and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// a whole WAV file in memory, for hashing rendered audio
+pub fn encode(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let mut writer =
+        WavWriter::new(Cursor::new(Vec::new()), sample_rate).expect("writing to memory can't fail");
+    writer
+        .write_samples(samples)
+        .expect("writing to memory can't fail");
+    writer
+        .finish()
+        .expect("writing to memory can't fail")
+        .into_inner()
+}
+
+// clamps, so a loud mix clips instead of wrapping around
+pub fn to_pcm16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let wav = encode(&[0.0, 1.0, -1.0, 0.5], 48000);
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[22..24], &2u16.to_le_bytes());
+        assert_eq!(&wav[24..28], &48000u32.to_le_bytes());
+        assert_eq!(&wav[28..32], &(48000u32 * 4).to_le_bytes());
+        assert_eq!(&wav[32..36], &[4, 0, 16, 0]);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+        assert_eq!(
+            &wav[44..],
+            &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x3F]
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        writer.write_samples(&[0.25, -0.25]).unwrap();
+        writer.write_samples(&[0.5, -0.5]).unwrap();
+        let wav = writer.finish().unwrap().into_inner();
+        assert_eq!(wav, encode(&[0.25, -0.25, 0.5, -0.5], 44100));
+        assert_eq!(to_pcm16(2.0), i16::MAX);
+        assert_eq!(to_pcm16(-2.0), -i16::MAX);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        writer.data_size = MAX_DATA_SIZE - 4;
+        writer.write_samples(&[0.0, 0.0]).unwrap();
+        let err = writer.write_samples(&[0.0]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+        assert_eq!(writer.data_size, MAX_DATA_SIZE);
+    }
+}
@@ -0,0 +1,112 @@
+// Reference: https://github.com/retrio/gb-test-roms
+//
+// the ROMs keep the layout of the test ROM archive, see tests/common:
+//   tests/roms/blargg/cpu_instrs/individual/*.gb
+//   tests/roms/blargg/instr_timing/instr_timing.gb
+
+mod common;
+
+use std::path::Path;
+
+use emulator::{cartridge::Cartridge, gameboy};
+
+// the ROMs write their results here as well as to the serial port, behind a
+// signature so a blank cartridge RAM doesn't look like a result
+const STATUS_ADDR: u16 = 0xA000;
+const SIGNATURE_ADDR: u16 = 0xA001;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT_ADDR: u16 = 0xA004;
+// the status while the test is still running
+const RUNNING: u8 = 0x80;
+
+const CYCLES_PER_SECOND: i64 = 4194304;
+
+// the status and text the ROM has written to cartridge RAM, if it has
+// started writing any
+fn memory_result(gb: &gameboy::Gameboy) -> Option<(u8, String)> {
+    let signature = [0, 1, 2].map(|i| gb.bus.read8(SIGNATURE_ADDR + i));
+    if signature != SIGNATURE {
+        return None;
+    }
+    let text = (TEXT_ADDR..0xC000)
+        .map(|addr| gb.bus.read8(addr))
+        .take_while(|&byte| byte != 0)
+        .map(char::from)
+        .collect();
+    Some((gb.bus.read8(STATUS_ADDR), text))
+}
+
+// runs the ROM until it reports a result, or fails once the budget of
+// emulated seconds runs out
+fn run(rom: &Path, seconds: i64) -> Result<(), String> {
+    let cartridge = Cartridge::from_file(rom).map_err(|err| err.to_string())?;
+    let mut gb = gameboy::create_gameboy();
+    gb.bus.insert_cartridge(cartridge);
+    gameboy::skip_boot_rom(&mut gb, gameboy::Model::Dmg);
+    gameboy::set_serial_capture(&mut gb, true);
+
+    let mut serial = String::new();
+    let mut cycles = 0;
+    while cycles < seconds * CYCLES_PER_SECOND {
+        cycles += gameboy::run_frame(&mut gb);
+        serial.extend(
+            gameboy::take_serial_output(&mut gb)
+                .into_iter()
+                .map(char::from),
+        );
+        if serial.contains("Passed") {
+            return Ok(());
+        }
+        if serial.contains("Failed") {
+            return Err(serial);
+        }
+        match memory_result(&gb) {
+            Some((0, _)) => return Ok(()),
+            Some((RUNNING, _)) | None => {}
+            Some((status, text)) => return Err(format!("status {}: {}", status, text)),
+        }
+    }
+    Err(format!("no result after {} seconds: {}", seconds, serial))
+}
+
+// runs each ROM with its budget in emulated seconds
+fn run_suite(dir: &str, roms: &[(&str, i64)]) {
+    let dir = common::roms_dir("blargg").join(dir);
+    let mut failures = Vec::new();
+    for &(name, seconds) in roms {
+        let rom = dir.join(name);
+        common::require(&rom);
+        match run(&rom, seconds) {
+            Ok(()) => eprintln!("{}: ok", name),
+            Err(err) => failures.push(format!("{}: {}", name, err.trim())),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms/blargg"]
+fn cpu_instrs() {
+    run_suite(
+        "cpu_instrs/individual",
+        &[
+            ("01-special.gb", 10),
+            ("02-interrupts.gb", 10),
+            ("03-op sp,hl.gb", 10),
+            ("04-op r,imm.gb", 10),
+            ("05-op rp.gb", 10),
+            ("06-ld r,r.gb", 10),
+            ("07-jr,jp,call,ret,rst.gb", 10),
+            ("08-misc instrs.gb", 10),
+            ("09-op r,r.gb", 20),
+            ("10-bit ops.gb", 20),
+            ("11-op a,(hl).gb", 30),
+        ],
+    );
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms/blargg"]
+fn instr_timing() {
+    run_suite("instr_timing", &[("instr_timing.gb", 10)]);
+}
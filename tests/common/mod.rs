@@ -0,0 +1,18 @@
+// the test ROMs and vectors aren't part of the repository, so the tests
+// that need them are ignored, copy the files into tests/roms as the top of
+// each test file describes and run them with:
+//   cargo test --test <file> -- --ignored
+
+use std::path::{Path, PathBuf};
+
+pub fn roms_dir(suite: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(suite)
+}
+
+// a test that was asked to run fails without its files instead of passing
+// without running anything
+pub fn require(path: &Path) {
+    assert!(path.exists(), "{} is missing", path.display());
+}
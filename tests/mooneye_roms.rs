@@ -0,0 +1,177 @@
+// Reference: https://github.com/Gekkio/mooneye-test-suite
+//
+// the built acceptance tests, see tests/common:
+//   tests/roms/mooneye/acceptance/**/*.gb
+//
+// a failure lists every ROM's result, set MOONEYE_SUMMARY=1 to print the
+// same table without failing, for tracking accuracy over time:
+//   MOONEYE_SUMMARY=1 cargo test --test mooneye_roms -- --ignored --nocapture
+
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use emulator::{cartridge::Cartridge, cpu, gameboy};
+
+// B, C, D, E, H and L when a test passes, failures load 0x42 into all of them
+const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAIL_REGISTERS: [u8; 6] = [0x42; 6];
+// the slowest tests take a few seconds of emulated time
+const MAX_CYCLES: i64 = 20 * 4194304;
+
+#[derive(PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    // LD B,B with registers that mean neither
+    Unknown([u8; 6]),
+    Timeout,
+    Error(String),
+}
+
+impl Outcome {
+    fn describe(&self) -> String {
+        match self {
+            Outcome::Pass => "pass".to_string(),
+            Outcome::Fail => "FAIL".to_string(),
+            Outcome::Unknown(registers) => format!("FAIL, registers {:02X?}", registers),
+            Outcome::Timeout => "FAIL, never finished".to_string(),
+            Outcome::Error(err) => format!("FAIL, {}", err),
+        }
+    }
+}
+
+fn registers(cpu: &cpu::CPU) -> [u8; 6] {
+    [
+        cpu.get_b(),
+        cpu.get_c(),
+        cpu.get_d(),
+        cpu.get_e(),
+        cpu.get_h(),
+        cpu.get_l(),
+    ]
+}
+
+// runs the ROM until it executes LD B,B and reads the result from the
+// registers
+fn run(rom: &Path) -> Outcome {
+    let cartridge = match Cartridge::from_file(rom) {
+        Ok(cartridge) => cartridge,
+        Err(err) => return Outcome::Error(err.to_string()),
+    };
+    let mut gb = gameboy::create_gameboy();
+    gb.bus.insert_cartridge(cartridge);
+    gameboy::skip_boot_rom(&mut gb, gameboy::Model::Dmg);
+
+    let mut cycles = 0;
+    while !gb.cpu.get_breakpoint() {
+        cycles += gameboy::step_cpu(&mut gb);
+        if cycles > MAX_CYCLES {
+            return Outcome::Timeout;
+        }
+    }
+    match registers(&gb.cpu) {
+        PASS_REGISTERS => Outcome::Pass,
+        FAIL_REGISTERS => Outcome::Fail,
+        registers => Outcome::Unknown(registers),
+    }
+}
+
+// the suffix after the last hyphen says which models a test is for, G is
+// the DMG and MGB, S the SGBs, C the CGB and A the AGB
+fn runs_on_dmg(name: &str) -> bool {
+    let Some((_, models)) = name.rsplit_once('-') else {
+        return true;
+    };
+    if models.contains("dmgABC") || models.starts_with('G') {
+        return true;
+    }
+    let is_model_list = ["dmg", "mgb", "sgb", "cgb", "agb", "ags"]
+        .iter()
+        .any(|model| models.starts_with(model))
+        || models.chars().all(|c| "GSCA".contains(c));
+    !is_model_list
+}
+
+// every .gb file under dir
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+fn summary_table(results: &[(String, Outcome)]) -> String {
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut table = String::new();
+    for (name, outcome) in results {
+        table += &format!("{:<width$}  {}\n", name, outcome.describe());
+    }
+    let passed = results
+        .iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Pass)
+        .count();
+    table += &format!("{}/{} passed\n", passed, results.len());
+    table
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms/mooneye"]
+fn acceptance() {
+    let dir = common::roms_dir("mooneye").join("acceptance");
+    common::require(&dir);
+    let mut roms = Vec::new();
+    find_roms(&dir, &mut roms);
+    // sorted so the table always comes out the same
+    roms.sort();
+    assert!(!roms.is_empty(), "no ROMs in {}", dir.display());
+
+    let mut results = Vec::new();
+    for rom in roms {
+        let name = rom.strip_prefix(&dir).unwrap().with_extension("");
+        let name = name.to_string_lossy().into_owned();
+        if !runs_on_dmg(&name) {
+            continue;
+        }
+        let outcome = run(&rom);
+        results.push((name, outcome));
+    }
+
+    let table = summary_table(&results);
+    if env::var_os("MOONEYE_SUMMARY").is_some() {
+        println!("{}", table);
+        return;
+    }
+    let all_passed = results.iter().all(|(_, outcome)| *outcome == Outcome::Pass);
+    assert!(all_passed, "\n{}", table);
+}
+
+#[test]
+fn model_names() {
+    for (name, expected) in [
+        ("ei_sequence", true),
+        ("timer/tima_reload", true),
+        ("di_timing-GS", true),
+        ("boot_regs-dmgABC", true),
+        ("boot_div-dmgABCmgb", true),
+        ("boot_regs-dmg0", false),
+        ("boot_regs-mgb", false),
+        ("boot_regs-sgb2", false),
+        ("boot_div-S", false),
+    ] {
+        assert_eq!(runs_on_dmg(name), expected, "{}", name);
+    }
+}
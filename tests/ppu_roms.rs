@@ -0,0 +1,86 @@
+// Reference: https://github.com/mattcurrie/dmg-acid2
+// Reference: https://github.com/mattcurrie/mealybug-tearoom-tests
+//
+// the ROMs and reference images, see tests/common:
+//   tests/roms/dmg-acid2/dmg-acid2.gb
+//   tests/roms/dmg-acid2/reference-dmg.png
+//   tests/roms/mealybug/*.gb
+//   tests/roms/mealybug/expected/DMG-blob/*.png
+
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use emulator::{cartridge::Cartridge, gameboy, ppu::Renderer, screenshot};
+
+// both suites execute LD B,B once the picture is ready
+const LD_B_B: u8 = 0x40;
+// a few seconds of emulated time
+const MAX_CYCLES: i64 = 20 * 70224;
+
+// runs the ROM headless with the pixel FIFO until it executes LD B,B and
+// compares the screen with the reference image
+fn run(rom: &Path, reference: &Path) -> Result<(), String> {
+    let cartridge = Cartridge::from_file(rom).map_err(|err| err.to_string())?;
+    let reference = screenshot::load(reference).map_err(|err| err.to_string())?;
+
+    let mut gb = gameboy::create_gameboy();
+    gb.bus.insert_cartridge(cartridge);
+    gb.bus.ppu_mut().set_renderer(Renderer::PixelFifo);
+    gameboy::skip_boot_rom(&mut gb, gameboy::Model::Dmg);
+
+    let mut cycles = 0;
+    while gb.bus.read8(gb.cpu.get_pc()) != LD_B_B {
+        cycles += gameboy::step_cpu(&mut gb);
+        if cycles > MAX_CYCLES {
+            return Err("never finished".to_string());
+        }
+    }
+    if reference.matches(gameboy::framebuffer(&gb)) {
+        Ok(())
+    } else {
+        Err("the screen doesn't match the reference".to_string())
+    }
+}
+
+#[test]
+#[ignore = "needs the ROM in tests/roms/dmg-acid2"]
+fn dmg_acid2() {
+    let dir = common::roms_dir("dmg-acid2");
+    let rom = dir.join("dmg-acid2.gb");
+    common::require(&rom);
+    if let Err(err) = run(&rom, &dir.join("reference-dmg.png")) {
+        panic!("dmg-acid2: {}", err);
+    }
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms/mealybug"]
+fn mealybug_tearoom() {
+    let dir = common::roms_dir("mealybug");
+    common::require(&dir);
+    let mut roms: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gb"))
+        .collect();
+    roms.sort();
+
+    // only the tests with a DMG reference image, the rest are for the CGB
+    let mut failures = Vec::new();
+    for rom in roms {
+        let name = rom.file_stem().unwrap().to_string_lossy().into_owned();
+        let reference = dir.join("expected/DMG-blob").join(format!("{}.png", name));
+        if !reference.exists() {
+            continue;
+        }
+        match run(&rom, &reference) {
+            Ok(()) => eprintln!("{}: ok", name),
+            Err(err) => failures.push(format!("{}: {}", name, err)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,418 @@
+// Reference: https://github.com/SingleStepTests/sm83
+//
+// the JSON test vectors, see tests/common:
+//   tests/roms/sm83/v1/*.json
+//
+// each test gives the registers and memory before and after one
+// instruction, which runs on a flat 64 KiB of RAM with nothing else behind it
+//
+// only the number of bus cycles is checked, the address and value of each
+// one isn't compared yet, as the CPU doesn't access memory at the cycle it
+// would and has no record of its accesses to compare against
+
+mod common;
+
+use std::{
+    fs, panic,
+    path::{Path, PathBuf},
+};
+
+use emulator::{gameboy, instruction};
+use json::Json;
+
+// STOP depends on hardware the vectors don't model
+const SKIPPED_FILES: [&str; 1] = ["10.json"];
+// failures listed per file, the rest are only counted
+const MAX_REPORTED: usize = 5;
+
+// just enough JSON for the test files
+mod json {
+    pub enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    impl Json {
+        pub fn get(&self, key: &str) -> Option<&Json> {
+            match self {
+                Json::Object(members) => members
+                    .iter()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value),
+                _ => None,
+            }
+        }
+
+        pub fn as_array(&self) -> Option<&[Json]> {
+            match self {
+                Json::Array(items) => Some(items),
+                _ => None,
+            }
+        }
+
+        pub fn as_u64(&self) -> Option<u64> {
+            match self {
+                Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+                _ => None,
+            }
+        }
+
+        pub fn as_str(&self) -> Option<&str> {
+            match self {
+                Json::String(s) => Some(s),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    struct Parser<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl Parser<'_> {
+        fn error(&self, message: &str) -> String {
+            format!("{} at byte {}", message, self.pos)
+        }
+
+        fn skip_whitespace(&mut self) {
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| b.is_ascii_whitespace())
+            {
+                self.pos += 1;
+            }
+        }
+
+        fn expect(&mut self, byte: u8) -> Result<(), String> {
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) != Some(&byte) {
+                return Err(self.error(&format!("expected '{}'", byte as char)));
+            }
+            self.pos += 1;
+            Ok(())
+        }
+
+        fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+            if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+                return Err(self.error("unknown literal"));
+            }
+            self.pos += word.len();
+            Ok(value)
+        }
+
+        fn value(&mut self) -> Result<Json, String> {
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b'{') => self.object(),
+                Some(b'[') => self.array(),
+                Some(b'"') => self.string().map(Json::String),
+                Some(b't') => self.literal("true", Json::Bool(true)),
+                Some(b'f') => self.literal("false", Json::Bool(false)),
+                Some(b'n') => self.literal("null", Json::Null),
+                Some(_) => self.number(),
+                None => Err(self.error("unexpected end")),
+            }
+        }
+
+        // the items between the brackets, separated by commas
+        fn list<T>(
+            &mut self,
+            close: u8,
+            mut item: impl FnMut(&mut Self) -> Result<T, String>,
+        ) -> Result<Vec<T>, String> {
+            self.pos += 1;
+            let mut items = Vec::new();
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) == Some(&close) {
+                self.pos += 1;
+                return Ok(items);
+            }
+            loop {
+                items.push(item(self)?);
+                self.skip_whitespace();
+                match self.bytes.get(self.pos) {
+                    Some(b',') => self.pos += 1,
+                    Some(&b) if b == close => {
+                        self.pos += 1;
+                        return Ok(items);
+                    }
+                    _ => return Err(self.error("expected ',' or the end of the list")),
+                }
+            }
+        }
+
+        fn object(&mut self) -> Result<Json, String> {
+            self.list(b'}', |parser| {
+                parser.skip_whitespace();
+                let key = parser.string()?;
+                parser.expect(b':')?;
+                Ok((key, parser.value()?))
+            })
+            .map(Json::Object)
+        }
+
+        fn array(&mut self) -> Result<Json, String> {
+            self.list(b']', Self::value).map(Json::Array)
+        }
+
+        fn string(&mut self) -> Result<String, String> {
+            if self.bytes.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected a string"));
+            }
+            self.pos += 1;
+            let mut string = Vec::new();
+            loop {
+                match self.bytes.get(self.pos) {
+                    Some(b'"') => break,
+                    Some(b'\\') => {
+                        self.pos += 1;
+                        let escaped = match self.bytes.get(self.pos) {
+                            Some(b'n') => b'\n',
+                            Some(b't') => b'\t',
+                            Some(b'r') => b'\r',
+                            Some(&b @ (b'"' | b'\\' | b'/')) => b,
+                            _ => return Err(self.error("unsupported escape")),
+                        };
+                        string.push(escaped);
+                    }
+                    Some(&b) => string.push(b),
+                    None => return Err(self.error("unterminated string")),
+                }
+                self.pos += 1;
+            }
+            self.pos += 1;
+            String::from_utf8(string).map_err(|_| self.error("invalid UTF-8"))
+        }
+
+        fn number(&mut self) -> Result<Json, String> {
+            let start = self.pos;
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| b"+-.eE".contains(b) || b.is_ascii_digit())
+            {
+                self.pos += 1;
+            }
+            std::str::from_utf8(&self.bytes[start..self.pos])
+                .ok()
+                .and_then(|number| number.parse().ok())
+                .map(Json::Number)
+                .ok_or_else(|| self.error("invalid number"))
+        }
+    }
+}
+
+fn field(state: &Json, key: &str) -> Result<u64, String> {
+    state
+        .get(key)
+        .and_then(Json::as_u64)
+        .ok_or_else(|| format!("missing {}", key))
+}
+
+// the [address, value] pairs of a state's memory
+fn ram(state: &Json) -> Result<Vec<(u16, u8)>, String> {
+    let entries = state.get("ram").and_then(Json::as_array).unwrap_or(&[]);
+    entries
+        .iter()
+        .map(|entry| match entry.as_array() {
+            Some([addr, value]) => match (addr.as_u64(), value.as_u64()) {
+                (Some(addr), Some(value)) => Ok((addr as u16, value as u8)),
+                _ => Err("bad ram entry".to_string()),
+            },
+            _ => Err("bad ram entry".to_string()),
+        })
+        .collect()
+}
+
+const REGISTERS: [&str; 8] = ["a", "b", "c", "d", "e", "f", "h", "l"];
+
+fn set_state(gb: &mut gameboy::Gameboy, state: &Json) -> Result<(), String> {
+    let cpu = &mut gb.cpu;
+    cpu.set_pc(field(state, "pc")? as u16);
+    cpu.set_sp(field(state, "sp")? as u16);
+    cpu.set_a(field(state, "a")? as u8);
+    cpu.set_b(field(state, "b")? as u8);
+    cpu.set_c(field(state, "c")? as u8);
+    cpu.set_d(field(state, "d")? as u8);
+    cpu.set_e(field(state, "e")? as u8);
+    cpu.set_f(field(state, "f")? as u8);
+    cpu.set_h(field(state, "h")? as u8);
+    cpu.set_l(field(state, "l")? as u8);
+    cpu.set_ime(field(state, "ime")? != 0);
+    if let Ok(ie) = field(state, "ie") {
+        gb.bus.write8(0xFFFF, ie as u8);
+    }
+    for (addr, value) in ram(state)? {
+        gb.bus.write8(addr, value);
+    }
+    Ok(())
+}
+
+fn check(differences: &mut Vec<String>, name: &str, actual: u64, expected: u64) {
+    if actual != expected {
+        differences.push(format!("{} is {:X}, expected {:X}", name, actual, expected));
+    }
+}
+
+// what differs from the expected final state, empty when it all matches
+fn compare(gb: &gameboy::Gameboy, expected: &Json, cycles: i64, bus_cycles: usize) -> Vec<String> {
+    let mut differences = Vec::new();
+    let cpu = &gb.cpu;
+    let actual = [
+        cpu.get_a(),
+        cpu.get_b(),
+        cpu.get_c(),
+        cpu.get_d(),
+        cpu.get_e(),
+        cpu.get_f(),
+        cpu.get_h(),
+        cpu.get_l(),
+    ];
+    for (name, value) in REGISTERS.iter().zip(actual) {
+        if let Ok(expected) = field(expected, name) {
+            check(&mut differences, name, value as u64, expected);
+        }
+    }
+    if let Ok(pc) = field(expected, "pc") {
+        check(&mut differences, "pc", cpu.get_pc() as u64, pc);
+    }
+    if let Ok(sp) = field(expected, "sp") {
+        check(&mut differences, "sp", cpu.get_sp() as u64, sp);
+    }
+    if let Ok(ime) = field(expected, "ime") {
+        check(&mut differences, "ime", cpu.get_ime() as u64, ime);
+    }
+    if let Ok(ie) = field(expected, "ie") {
+        check(&mut differences, "ie", gb.bus.read8(0xFFFF) as u64, ie);
+    }
+    match ram(expected) {
+        Ok(entries) => {
+            for (addr, value) in entries {
+                check(
+                    &mut differences,
+                    &format!("({:04X})", addr),
+                    gb.bus.read8(addr) as u64,
+                    value as u64,
+                );
+            }
+        }
+        Err(err) => differences.push(err),
+    }
+    // one entry per m-cycle
+    check(
+        &mut differences,
+        "cycles",
+        cycles as u64,
+        bus_cycles as u64 * 4,
+    );
+    differences
+}
+
+// runs every test in the file, returns the failures
+fn run_file(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let tests = json::parse(&text)?;
+    let tests = tests.as_array().ok_or("expected a list of tests")?;
+    let mut failures = Vec::new();
+    for test in tests {
+        let name = test.get("name").and_then(Json::as_str).unwrap_or("?");
+        let (Some(initial), Some(expected)) = (test.get("initial"), test.get("final")) else {
+            return Err(format!("{}: missing the initial or final state", name));
+        };
+        let bus_cycles = test
+            .get("cycles")
+            .and_then(Json::as_array)
+            .map_or(0, |c| c.len());
+
+        // a panic fails this test rather than the rest of the file
+        let result = panic::catch_unwind(|| -> Result<_, String> {
+            let mut gb = gameboy::create_gameboy();
+            gb.bus.use_flat_memory();
+            set_state(&mut gb, initial)?;
+            let opcode = gameboy::read_byte(&mut gb);
+            let cycles = instruction::execute_instruction(&mut gb, opcode);
+            Ok(compare(&gb, expected, cycles, bus_cycles))
+        });
+        let differences = match result {
+            Ok(Ok(differences)) => differences,
+            Ok(Err(err)) => return Err(format!("{}: {}", name, err)),
+            Err(_) => vec!["panicked".to_string()],
+        };
+        if !differences.is_empty() {
+            failures.push(format!("{}: {}", name, differences.join(", ")));
+        }
+    }
+    Ok(failures)
+}
+
+#[test]
+#[ignore = "needs the test vectors in tests/roms/sm83"]
+fn sm83() {
+    let dir = common::roms_dir("sm83/v1");
+    common::require(&dir);
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+
+    let mut report = Vec::new();
+    for file in files {
+        let name = file.file_name().unwrap().to_string_lossy().into_owned();
+        if SKIPPED_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        match run_file(&file) {
+            Ok(failures) if failures.is_empty() => {}
+            Ok(failures) => {
+                report.push(format!("{}: {} failed", name, failures.len()));
+                report.extend(
+                    failures
+                        .into_iter()
+                        .take(MAX_REPORTED)
+                        .map(|f| format!("  {}", f)),
+                );
+            }
+            Err(err) => report.push(format!("{}: {}", name, err)),
+        }
+    }
+    assert!(report.is_empty(), "\n{}", report.join("\n"));
+}
+
+#[test]
+fn parse_json() {
+    let value =
+        json::parse(r#" {"name": "a\"b", "list": [1, 2.5, true, null], "empty": {}} "#).unwrap();
+    assert_eq!(value.get("name").and_then(Json::as_str), Some("a\"b"));
+    let list = value.get("list").and_then(Json::as_array).unwrap();
+    assert_eq!(list[0].as_u64(), Some(1));
+    assert_eq!(list[1].as_u64(), None);
+    assert!(matches!(list[2], Json::Bool(true)));
+    assert!(matches!(list[3], Json::Null));
+    assert!(value.get("empty").is_some());
+    assert!(json::parse("[1, 2").is_err());
+    assert!(json::parse("[1] x").is_err());
+}